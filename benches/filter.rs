@@ -15,6 +15,7 @@ fn bench_filter_covered(b: &mut Bencher) {
         Function {
             start: 1,
             executed: true,
+            end: None,
         },
     );
     functions.insert(
@@ -22,12 +23,13 @@ fn bench_filter_covered(b: &mut Bencher) {
         Function {
             start: 2,
             executed: false,
+            end: None,
         },
     );
     let result = CovResult {
         lines: [(1, 21), (2, 7), (7, 0)].iter().cloned().collect(),
-        branches: [].iter().cloned().collect(),
         functions,
+        ..Default::default()
     };
     b.iter(|| black_box(grcov::is_covered(&result)));
 }
@@ -36,8 +38,7 @@ fn bench_filter_covered(b: &mut Bencher) {
 fn bench_filter_covered_no_functions(b: &mut Bencher) {
     let result = CovResult {
         lines: [(1, 21), (2, 7), (7, 0)].iter().cloned().collect(),
-        branches: [].iter().cloned().collect(),
-        functions: FxHashMap::default(),
+        ..Default::default()
     };
     b.iter(|| black_box(grcov::is_covered(&result)));
 }
@@ -50,6 +51,7 @@ fn bench_filter_uncovered_no_lines_executed(b: &mut Bencher) {
         Function {
             start: 1,
             executed: true,
+            end: None,
         },
     );
     functions.insert(
@@ -57,12 +59,12 @@ fn bench_filter_uncovered_no_lines_executed(b: &mut Bencher) {
         Function {
             start: 2,
             executed: false,
+            end: None,
         },
     );
     let result = CovResult {
         lines: [(1, 0), (2, 0), (7, 0)].iter().cloned().collect(),
-        branches: [].iter().cloned().collect(),
-        functions: FxHashMap::default(),
+        ..Default::default()
     };
     b.iter(|| black_box(grcov::is_covered(&result)));
 }
@@ -75,6 +77,7 @@ fn bench_filter_covered_functions_executed(b: &mut Bencher) {
         Function {
             start: 1,
             executed: true,
+            end: None,
         },
     );
     functions.insert(
@@ -82,12 +85,13 @@ fn bench_filter_covered_functions_executed(b: &mut Bencher) {
         Function {
             start: 2,
             executed: true,
+            end: None,
         },
     );
     let result = CovResult {
         lines: [(1, 21), (2, 7), (7, 0)].iter().cloned().collect(),
-        branches: [].iter().cloned().collect(),
         functions,
+        ..Default::default()
     };
     b.iter(|| black_box(grcov::is_covered(&result)));
 }
@@ -100,12 +104,13 @@ fn bench_filter_covered_toplevel_executed(b: &mut Bencher) {
         Function {
             start: 1,
             executed: true,
+            end: None,
         },
     );
     let result = CovResult {
         lines: [(1, 21), (2, 7), (7, 0)].iter().cloned().collect(),
-        branches: [].iter().cloned().collect(),
         functions,
+        ..Default::default()
     };
     b.iter(|| black_box(grcov::is_covered(&result)));
 }
@@ -118,6 +123,7 @@ fn bench_filter_uncovered_functions_not_executed(b: &mut Bencher) {
         Function {
             start: 1,
             executed: true,
+            end: None,
         },
     );
     functions.insert(
@@ -125,12 +131,13 @@ fn bench_filter_uncovered_functions_not_executed(b: &mut Bencher) {
         Function {
             start: 7,
             executed: false,
+            end: None,
         },
     );
     let result = CovResult {
         lines: [(1, 21), (2, 7), (7, 0)].iter().cloned().collect(),
-        branches: [].iter().cloned().collect(),
         functions,
+        ..Default::default()
     };
     b.iter(|| black_box(grcov::is_covered(&result)));
 }
@@ -22,6 +22,7 @@ fn bench_lib_merge_results(b: &mut Bencher) {
         Function {
             start: 1,
             executed: false,
+            end: None,
         },
     );
     functions1.insert(
@@ -29,6 +30,7 @@ fn bench_lib_merge_results(b: &mut Bencher) {
         Function {
             start: 2,
             executed: false,
+            end: None,
         },
     );
     let mut result = CovResult {
@@ -42,6 +44,7 @@ fn bench_lib_merge_results(b: &mut Bencher) {
         .cloned()
         .collect(),
         functions: functions1,
+        ..Default::default()
     };
 
     let mut functions2: FunctionMap = FxHashMap::default();
@@ -50,6 +53,7 @@ fn bench_lib_merge_results(b: &mut Bencher) {
         Function {
             start: 1,
             executed: false,
+            end: None,
         },
     );
     functions2.insert(
@@ -57,6 +61,7 @@ fn bench_lib_merge_results(b: &mut Bencher) {
         Function {
             start: 2,
             executed: true,
+            end: None,
         },
     );
     let result2 = CovResult {
@@ -73,6 +78,7 @@ fn bench_lib_merge_results(b: &mut Bencher) {
         .cloned()
         .collect(),
         functions: functions2,
+        ..Default::default()
     };
 
     b.iter(|| black_box(grcov::merge_results(&mut result, result2.clone())));
@@ -85,7 +91,7 @@ fn bench_lib_consumer(b: &mut Bencher) {
         FxHashMap::with_capacity_and_hasher(20_000, Default::default()),
     ));
     let (sender, receiver) = unbounded();
-    let source_root = None;
+    let source_root: Option<PathBuf> = None;
     let working_dir = PathBuf::from("");
     let gcno_buf: Vec<u8> = vec![
         111, 110, 99, 103, 42, 50, 48, 52, 74, 200, 254, 66, 0, 0, 0, 1, 9, 0, 0, 0, 0, 0, 0, 0,
@@ -111,13 +117,17 @@ fn bench_lib_consumer(b: &mut Bencher) {
                 .spawn(move || {
                     consumer(
                         &working_dir,
-                        &source_root,
+                        source_root.as_deref(),
                         &result_map,
                         receiver,
                         false,
                         false,
-                        &None,
-                    );
+                        None,
+                        false,
+                        false,
+                        None,
+                    )
+                    .unwrap();
                 })
                 .unwrap();
 
@@ -127,7 +137,7 @@ fn bench_lib_consumer(b: &mut Bencher) {
         for _ in 0..10_000 {
             sender
                 .send(Some(WorkItem {
-                    format: ItemFormat::GCNO,
+                    format: ItemFormat::Gcno,
                     item: ItemType::Buffers(GcnoBuffers {
                         stem: "".to_string(),
                         gcno_buf: gcno_buf.clone(),
@@ -5,7 +5,7 @@ extern crate test;
 
 use grcov::{
     output_activedata_etl, output_covdir, output_lcov, CovResult, CovResultIter, Function,
-    FunctionMap,
+    FunctionMap, LineEnding,
 };
 use rustc_hash::FxHashMap;
 use std::path::PathBuf;
@@ -21,7 +21,6 @@ fn generate_cov_result_iter() -> CovResultIter {
                     PathBuf::from(""),
                     PathBuf::from(""),
                     CovResult {
-                        branches: [].iter().cloned().collect(),
                         functions: {
                             let mut functions: FunctionMap = FxHashMap::default();
                             functions.insert(
@@ -29,6 +28,7 @@ fn generate_cov_result_iter() -> CovResultIter {
                                 Function {
                                     start: 1,
                                     executed: true,
+                                    end: None,
                                 },
                             );
                             functions.insert(
@@ -36,11 +36,13 @@ fn generate_cov_result_iter() -> CovResultIter {
                                 Function {
                                     start: 2,
                                     executed: false,
+                                    end: None,
                                 },
                             );
                             functions
                         },
                         lines: [(1, 21), (2, 7), (7, 0)].iter().cloned().collect(),
+                        ..Default::default()
                     },
                 )
             }),
@@ -53,6 +55,7 @@ fn bench_output_activedata_etl(b: &mut Bencher) {
         black_box(output_activedata_etl(
             generate_cov_result_iter(),
             Some(dir.path().join("temp").to_str().unwrap()),
+            LineEnding::Lf,
             false,
         ))
     });
@@ -62,10 +65,12 @@ fn bench_output_activedata_etl(b: &mut Bencher) {
 fn bench_output_covdir(b: &mut Bencher) {
     let dir = tempdir().unwrap();
     b.iter(|| {
-        black_box(output_covdir(
+        output_covdir(
             generate_cov_result_iter(),
             Some(dir.path().join("temp").to_str().unwrap()),
-        ));
+            LineEnding::Lf,
+        )
+        .unwrap();
     });
 }
 
@@ -73,10 +78,12 @@ fn bench_output_covdir(b: &mut Bencher) {
 fn bench_output_lcov(b: &mut Bencher) {
     let dir = tempdir().unwrap();
     b.iter(|| {
-        black_box(output_lcov(
+        output_lcov(
             generate_cov_result_iter(),
             Some(dir.path().join("temp").to_str().unwrap()),
+            LineEnding::Lf,
             false,
-        ));
+        )
+        .unwrap();
     });
 }
@@ -0,0 +1,196 @@
+use chrono::DateTime;
+use log::warn;
+use rustc_hash::FxHashMap;
+use std::path::Path;
+use std::process::Command;
+
+use crate::defs::*;
+
+/// Parses the value of `--blame-since`: a Unix timestamp, a date (`2021-03-01`) or a
+/// RFC 3339 date and time (`2021-03-01T12:00:00+01:00`).
+pub fn parse_blame_since(since: &str) -> Option<i64> {
+    if let Ok(timestamp) = since.parse::<i64>() {
+        return Some(timestamp);
+    }
+    DateTime::parse_from_rfc3339(since)
+        .or_else(|_| DateTime::parse_from_rfc3339(&format!("{}T00:00:00+00:00", since)))
+        .ok()
+        .map(|date| date.timestamp())
+}
+
+/// Extracts the committer time of each line from the output of `git blame --line-porcelain`.
+pub fn parse_blame_times(porcelain: &str) -> FxHashMap<u32, i64> {
+    let mut times = FxHashMap::default();
+    let mut line_number = None;
+
+    for line in porcelain.lines() {
+        if line.starts_with('\t') {
+            // The content of the line ends the entry.
+            line_number = None;
+        } else if line_number.is_none() {
+            // Header: <sha> <line in the original file> <line in the final file> [<group size>]
+            line_number = line
+                .split(' ')
+                .nth(2)
+                .and_then(|number| number.parse::<u32>().ok());
+        } else if let Some(time) = line.strip_prefix("committer-time ") {
+            if let Ok(time) = time.parse() {
+                times.insert(line_number.unwrap(), time);
+            }
+        }
+    }
+
+    times
+}
+
+fn run_git_blame(path: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .arg("blame")
+        .arg("--line-porcelain")
+        .arg("--")
+        .arg(path.file_name()?)
+        .current_dir(path.parent()?)
+        .output()
+        .ok()?;
+    if output.status.success() {
+        String::from_utf8(output.stdout).ok()
+    } else {
+        None
+    }
+}
+
+/// Only keeps the lines (and the branches and functions starting on them) which were
+/// committed at or after `since`. Returns false if no line is left.
+pub fn filter_recent_lines(
+    result: &mut CovResult,
+    times: &FxHashMap<u32, i64>,
+    since: i64,
+) -> bool {
+    let is_recent = |line: &u32| matches!(times.get(line), Some(&time) if time >= since);
+
+    result.lines.retain(|line, _| is_recent(line));
+    result.branches.retain(|line, _| is_recent(line));
+    result.branch_hits.retain(|line, _| is_recent(line));
+    result
+        .functions
+        .retain(|_, function| is_recent(&function.start));
+
+    !result.lines.is_empty()
+}
+
+/// Restricts the results to the lines committed at or after `since`, according to `git blame`.
+///
+/// Files for which `git blame` fails (e.g. outside of a repository) are kept as they are.
+pub fn filter_blame_since(results: CovResultIter, since: i64) -> CovResultIter {
+    Box::new(results.filter_map(move |(abs_path, rel_path, mut result)| {
+        match run_git_blame(&abs_path) {
+            Some(porcelain) => {
+                let times = parse_blame_times(&porcelain);
+                if filter_recent_lines(&mut result, &times, since) {
+                    Some((abs_path, rel_path, result))
+                } else {
+                    None
+                }
+            }
+            None => {
+                warn!(
+                    "Cannot run git blame on {}, all its lines are kept.",
+                    abs_path.display()
+                );
+                Some((abs_path, rel_path, result))
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    const PORCELAIN: &str = "\
+1111111111111111111111111111111111111111 1 1 2
+author Old
+committer-time 1600000000
+filename src/lib.rs
+\tfn foo() {
+1111111111111111111111111111111111111111 2 2
+author Old
+committer-time 1600000000
+filename src/lib.rs
+\t    bar();
+2222222222222222222222222222222222222222 3 3 1
+author New
+committer-time 1620000000
+filename src/lib.rs
+\t    baz();
+1111111111111111111111111111111111111111 3 4 1
+author Old
+committer-time 1600000000
+filename src/lib.rs
+\t}
+";
+
+    #[test]
+    fn test_parse_blame_since() {
+        assert_eq!(parse_blame_since("1610000000"), Some(1_610_000_000));
+        assert_eq!(parse_blame_since("2021-01-07"), Some(1_609_977_600));
+        assert_eq!(
+            parse_blame_since("2021-01-07T01:00:00+01:00"),
+            Some(1_609_977_600)
+        );
+        assert_eq!(parse_blame_since("last week"), None);
+    }
+
+    #[test]
+    fn test_filter_recent_lines() {
+        let times = parse_blame_times(PORCELAIN);
+        assert_eq!(times.len(), 4);
+        assert_eq!(times[&3], 1_620_000_000);
+
+        let mut functions = FxHashMap::default();
+        functions.insert(
+            "foo".to_string(),
+            Function {
+                start: 1,
+                executed: true,
+                end: None,
+            },
+        );
+        let mut branches = BTreeMap::new();
+        branches.insert(3, vec![true, false]);
+        let mut result = CovResult {
+            lines: [(1, 1), (2, 1), (3, 0), (4, 1)].iter().cloned().collect(),
+            branches,
+            functions,
+            ..Default::default()
+        };
+
+        assert!(filter_recent_lines(&mut result, &times, 1_610_000_000));
+        assert_eq!(result.lines, [(3, 0)].iter().cloned().collect());
+        assert_eq!(result.branches.len(), 1);
+        assert!(result.functions.is_empty());
+
+        assert!(!filter_recent_lines(&mut result, &times, 1_630_000_000));
+    }
+
+    #[test]
+    fn test_filter_blame_since_outside_repository() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let path = tmp_dir.path().join("lib.rs");
+        std::fs::write(&path, "fn foo() {}\n").unwrap();
+
+        let results = vec![(
+            path,
+            PathBuf::from("lib.rs"),
+            CovResult {
+                lines: [(1, 1)].iter().cloned().collect(),
+                ..Default::default()
+            },
+        )];
+        let results: Vec<_> = filter_blame_since(Box::new(results.into_iter()), 0).collect();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].2.lines.len(), 1);
+    }
+}
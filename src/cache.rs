@@ -0,0 +1,174 @@
+use log::warn;
+use md5::{Digest, Md5};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::defs::*;
+use crate::output::write_json;
+use crate::parser::parse_grcov_json;
+
+/// Computes the key of the results of a gcno file and its gcda files in the cache.
+///
+/// The inputs are copied to a temporary directory before being parsed, so the key is derived
+/// from their contents rather than from their paths and modification times. Only the gcno
+/// items are cached, the other formats are cheaper to parse than to look up.
+pub fn cache_key(item: &ItemType, branch_enabled: bool, guess_directory: bool) -> Option<String> {
+    let mut hasher = Md5::new();
+    // Prefix each part with its length, for the concatenation to be unambiguous.
+    let mut update = |bytes: &[u8]| {
+        hasher.update((bytes.len() as u64).to_le_bytes());
+        hasher.update(bytes);
+    };
+    update(env!("CARGO_PKG_VERSION").as_bytes());
+    update(&[branch_enabled as u8, guess_directory as u8]);
+
+    match item {
+        ItemType::Path((stem, gcno_path)) => {
+            update(stem.as_bytes());
+            update(&fs::read(gcno_path).ok()?);
+            // There is no gcda file when the code was never executed.
+            if let Ok(gcda) = fs::read(gcno_path.with_extension("gcda")) {
+                update(&gcda);
+            }
+        }
+        ItemType::Buffers(buffers) => {
+            update(buffers.stem.as_bytes());
+            update(&buffers.gcno_buf);
+            for gcda in &buffers.gcda_buf {
+                update(gcda);
+            }
+        }
+        ItemType::Paths(_) | ItemType::Content(_) => return None,
+    }
+
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+fn cache_path(cache_dir: &Path, key: &str) -> PathBuf {
+    cache_dir.join(format!("{}.json", key))
+}
+
+/// Loads the results stored in the cache under the key, if any.
+pub fn load_cached_results(cache_dir: &Path, key: &str) -> Option<Vec<(String, CovResult)>> {
+    let path = cache_path(cache_dir, key);
+    let file = File::open(&path).ok()?;
+    match parse_grcov_json(BufReader::new(file)) {
+        Ok(results) => Some(results),
+        Err(e) => {
+            warn!("Ignoring the invalid cache entry {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Stores the results in the cache under the key, in the grcov JSON format.
+///
+/// The entry is written to a temporary file first, for the other threads never to read a
+/// partial entry. Failing to write it only costs parsing the inputs again on the next run.
+pub fn store_cached_results(cache_dir: &Path, key: &str, results: &[(String, CovResult)]) {
+    let store = || -> io::Result<()> {
+        let file = tempfile::NamedTempFile::new_in(cache_dir)?;
+        let results: Vec<_> = results
+            .iter()
+            .map(|(name, result)| (PathBuf::from(name), PathBuf::from(name), result.clone()))
+            .collect();
+        let mut writer = BufWriter::new(file.as_file());
+        write_json(Box::new(results.into_iter()), &mut writer)?;
+        writer.flush()?;
+        drop(writer);
+        file.persist(cache_path(cache_dir, key))?;
+        Ok(())
+    };
+    if let Err(e) = store() {
+        warn!("Cannot write the cache entry {}: {}", key, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_hash::FxHashMap;
+
+    #[test]
+    fn test_cache_key() {
+        let buffers = |gcda: &[u8]| {
+            ItemType::Buffers(GcnoBuffers {
+                stem: "foo".to_string(),
+                gcno_buf: b"gcno".to_vec(),
+                gcda_buf: vec![gcda.to_vec()],
+            })
+        };
+
+        let key = cache_key(&buffers(b"gcda"), false, false).unwrap();
+        assert_eq!(
+            cache_key(&buffers(b"gcda"), false, false),
+            Some(key.clone())
+        );
+        assert_ne!(
+            cache_key(&buffers(b"gcda2"), false, false),
+            Some(key.clone())
+        );
+        assert_ne!(cache_key(&buffers(b"gcda"), true, false), Some(key));
+        assert_eq!(
+            cache_key(&ItemType::Content(Vec::new()), false, false),
+            None
+        );
+    }
+
+    #[test]
+    fn test_cache_path_item() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let gcno_path = tmp_dir.path().join("foo_1.gcno");
+        fs::write(&gcno_path, b"gcno").unwrap();
+        let item = ItemType::Path(("foo".to_string(), gcno_path.clone()));
+
+        let orphan_key = cache_key(&item, false, false).unwrap();
+        fs::write(gcno_path.with_extension("gcda"), b"gcda").unwrap();
+        let key = cache_key(&item, false, false).unwrap();
+        assert_ne!(key, orphan_key);
+
+        // The key doesn't depend on the temporary directory the inputs are copied to.
+        let tmp_dir2 = tempfile::tempdir().expect("Failed to create temporary directory");
+        let gcno_path2 = tmp_dir2.path().join("foo_1.gcno");
+        fs::write(&gcno_path2, b"gcno").unwrap();
+        fs::write(gcno_path2.with_extension("gcda"), b"gcda").unwrap();
+        let item2 = ItemType::Path(("foo".to_string(), gcno_path2));
+        assert_eq!(cache_key(&item2, false, false), Some(key));
+    }
+
+    #[test]
+    fn test_cached_results() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let mut functions: FunctionMap = FxHashMap::default();
+        functions.insert(
+            "main".to_string(),
+            Function {
+                start: 1,
+                executed: true,
+                end: Some(3),
+            },
+        );
+        let results = vec![(
+            "src/main.cpp".to_string(),
+            CovResult {
+                lines: [(1, 1), (2, 0)].iter().cloned().collect(),
+                branches: [(2, vec![true, false])].iter().cloned().collect(),
+                functions,
+                ..Default::default()
+            },
+        )];
+
+        assert!(load_cached_results(tmp_dir.path(), "key").is_none());
+        store_cached_results(tmp_dir.path(), "key", &results);
+        assert_eq!(load_cached_results(tmp_dir.path(), "key"), Some(results));
+
+        // An invalid entry is a cache miss.
+        fs::write(tmp_dir.path().join("invalid.json"), b"{").unwrap();
+        assert!(load_cached_results(tmp_dir.path(), "invalid").is_none());
+
+        let empty: Vec<(String, CovResult)> = Vec::new();
+        store_cached_results(tmp_dir.path(), "empty", &empty);
+        assert_eq!(load_cached_results(tmp_dir.path(), "empty"), Some(empty));
+    }
+}
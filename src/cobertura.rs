@@ -2,43 +2,233 @@ use quick_xml::{
     events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event},
     Writer,
 };
+use regex::Regex;
 use rustc_hash::FxHashMap;
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::{
-    collections::BTreeSet,
-    io::{BufWriter, Cursor, Write},
+    cell::Cell,
+    collections::{btree_map, BTreeMap, BTreeSet},
+    env,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
 };
-use symbolic_common::Name;
+use symbolic_common::{Language, Name, NameMangling};
 use symbolic_demangle::{Demangle, DemangleOptions};
 
-use crate::defs::CovResultIter;
-use crate::output::get_target_output_writable;
+use crate::defs::{is_branch, CovResult, CovResultIter, Function};
+use crate::merge_results;
+use crate::output::{
+    finish_output, format_rounded, get_target_output_writable, LineEnding, Rounding,
+};
+use log::warn;
+
+// http://cobertura.sourceforge.net/xml/coverage-04.dtd
+
+/// How the conditions of a line are merged into a single one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BranchCollapse {
+    /// The condition is covered if any of the branches was taken.
+    Any,
+    /// The condition is covered if all the branches were taken.
+    All,
+}
+
+/// Whether and how the method names are demangled.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DemangleConfig {
+    /// Demangles the method names, they're written as they are in the symbols otherwise.
+    pub enabled: bool,
+    /// Demangles the method names completely, e.g. with the template arguments and the
+    /// parameter types, rather than just the names.
+    pub full: bool,
+}
+
+impl DemangleConfig {
+    /// Demangles just the names of the methods.
+    pub fn name_only() -> Self {
+        DemangleConfig {
+            enabled: true,
+            full: false,
+        }
+    }
+
+    /// Demangles the methods completely.
+    pub fn complete() -> Self {
+        DemangleConfig {
+            enabled: true,
+            full: true,
+        }
+    }
 
-macro_rules! demangle {
-    ($name: expr, $demangle: expr, $options: expr) => {{
-        if $demangle {
-            Name::from($name)
-                .demangle($options)
-                .unwrap_or_else(|| $name.clone())
+    fn options(&self) -> DemangleOptions {
+        if self.full {
+            DemangleOptions::complete()
         } else {
-            $name.clone()
+            DemangleOptions::name_only()
         }
-    }};
+    }
 }
 
-// http://cobertura.sourceforge.net/xml/coverage-04.dtd
+/// How the class names are derived from the file paths, rather than from the file stems
+/// which collide for e.g. `foo/mod.rs` and `bar/mod.rs`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClassNames {
+    /// The relative path of the file, e.g. `foo/mod.rs`.
+    Path,
+    /// The relative path without extension, with dots as separators, e.g. `foo.mod`.
+    Dotted,
+}
+
+/// How the packages are named, from the relative path of their file, or of their directory
+/// when the classes are grouped per directory.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PackageNames {
+    /// The relative path, e.g. `src/foo/bar.rs`, or `src/foo` per directory.
+    FilePath,
+    /// The relative path of the directory, with dots as separators, e.g. `src.foo`.
+    DottedDir,
+    /// A template where `{path}` is replaced by the relative path, `{dir}` by the one of the
+    /// directory, `{dotted_dir}` by the latter with dots as separators, and `{0}`, `{1}`, ...
+    /// by the components of the directory, e.g. `com.example.{1}` for Jacoco-style names.
+    CustomTemplate(String),
+}
+
+/// The DOCTYPE declaration of the report, instead of the one referencing the DTD on the
+/// Cobertura website, which XML parsers without network access can't fetch.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Doctype {
+    /// No DOCTYPE declaration is written.
+    Omit,
+    /// The DOCTYPE declaration references this DTD, e.g. a local path.
+    System(String),
+}
+
+impl Doctype {
+    /// The DTD is written between single quotes, which it can't contain, nor a `>` which
+    /// some parsers take as the end of the declaration.
+    fn check(dtd: &str) -> Result<(), String> {
+        if dtd.contains(&['\'', '>'][..]) {
+            Err(format!(
+                "The DTD {} can't contain a single quote or a '>'",
+                dtd
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl FromStr for Doctype {
+    type Err = String;
+
+    /// Parses the DTD of the DOCTYPE declaration, `none` to omit it.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "none" {
+            return Ok(Doctype::Omit);
+        }
+        Doctype::check(s)?;
+        Ok(Doctype::System(s.to_string()))
+    }
+}
+
+/// The indentation of the report, 4 spaces by default.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Indent {
+    /// The elements are written without whitespace between them, for smaller reports.
+    Minified,
+    /// Each level is indented with this number of spaces.
+    Spaces(usize),
+    /// Each level is indented with a tab.
+    Tab,
+}
 
-struct Coverage {
+/// Options controlling how the Cobertura report is built.
+#[derive(Clone, Debug, Default)]
+pub struct CoberturaConfig {
+    /// Methods with fewer attributed lines than this are not emitted, their lines are
+    /// reported at the class level instead.
+    pub min_method_lines: usize,
+    /// Files under these prefixes are generated (e.g. by build scripts), they are grouped
+    /// together in a package named "generated".
+    pub generated_prefixes: Vec<String>,
+    /// Adds the number of covered and valid functions, and the function rate, to the root
+    /// element.
+    pub function_stats: bool,
+    /// When set, warns about the classes whose file name can't be found under any of the
+    /// sources, resolved relative to this directory.
+    pub verify_root: Option<PathBuf>,
+    /// Merges the conditions of each line into one, to reduce the noise of e.g. `match`
+    /// statements with many arms.
+    pub collapse_branches: Option<BranchCollapse>,
+    /// Computes the branch rates over decisions rather than conditions: each line with
+    /// conditions counts as one branch, covered if all its conditions are.
+    pub decision_branch_rate: bool,
+    /// Derives the class names from the file paths, the file stems are used otherwise.
+    pub class_names: Option<ClassNames>,
+    /// Groups the classes of the files of each directory into one package, named after the
+    /// directory with dots as separators, instead of writing one package per file.
+    pub package_per_directory: bool,
+    /// Overrides the names of the packages, which are the file paths by default, or the
+    /// directories with dots as separators when grouped per directory.
+    pub package_names: Option<PackageNames>,
+    /// The directories the file names are relative to, written as `<source>` elements.
+    /// Defaults to the current directory.
+    pub sources: Vec<String>,
+    /// Overrides the DOCTYPE declaration, which references the DTD on the Cobertura website
+    /// by default.
+    pub doctype: Option<Doctype>,
+    /// Rounds the line and branch rates to this number of decimals, trailing zeros are
+    /// left out. The rates are written in full otherwise.
+    pub rate_precision: Option<usize>,
+    /// How the rates are rounded to their precision.
+    pub rounding: Rounding,
+    /// The timestamp of the report, in seconds since the Unix epoch. Defaults to the value of
+    /// the `SOURCE_DATE_EPOCH` environment variable if set, to the current time otherwise.
+    pub timestamp: Option<u64>,
+    /// Caps the hit counts of the lines, for consumers which read them into e.g. 32-bit
+    /// integers. The covered lines keep at least one hit.
+    pub max_hits: Option<u64>,
+    /// Reports all the lines at the class level, without methods, for smaller reports.
+    pub omit_methods: bool,
+    /// The version of the report, `1.9` by default.
+    pub version: Option<String>,
+    /// Leaves the complexity attributes out, for the consumers which don't support them.
+    pub omit_complexity: bool,
+    /// The language of the method names to demangle. By default (`Language::Unknown`), it's
+    /// guessed from the mangling scheme of each name.
+    pub demangle_language: Language,
+    /// Writes the file names as absolute paths, joined onto the first of the sources, for the
+    /// consumers which don't resolve them. Without sources, the file names are left relative.
+    pub absolute_filenames: bool,
+    /// Reports the start line of the functions which were never executed and have no lines
+    /// as uncovered, for them to show up in the report rather than as empty methods.
+    pub dead_function_lines: bool,
+    /// Reports the start line of all the functions which have no lines, as covered if they
+    /// were executed, for each function to show up as a method with a line.
+    pub empty_function_lines: bool,
+    /// Overrides the indentation of the XML, e.g. to minify it for automated consumers.
+    pub indent: Option<Indent>,
+    /// The methods whose names match are left out, e.g. the glue generated by the compiler
+    /// like `drop_in_place`, and their lines are reported at the class level instead.
+    pub excluded_methods: Option<Regex>,
+}
+
+pub(crate) struct Coverage {
     sources: Vec<String>,
-    packages: Vec<Package>,
+    pub(crate) packages: Vec<Package>,
+    /// The number of method names which look mangled but couldn't be demangled.
+    pub(crate) demangling_failures: usize,
 }
 
 #[derive(Default)]
-struct CoverageStats {
-    lines_covered: f64,
-    lines_valid: f64,
-    branches_covered: f64,
-    branches_valid: f64,
+pub(crate) struct CoverageStats {
+    pub(crate) lines_covered: f64,
+    pub(crate) lines_valid: f64,
+    pub(crate) branches_covered: f64,
+    pub(crate) branches_valid: f64,
+    pub(crate) functions_covered: f64,
+    pub(crate) functions_valid: f64,
     complexity: f64,
 }
 
@@ -51,6 +241,8 @@ impl std::ops::Add for CoverageStats {
             lines_valid: self.lines_valid + rhs.lines_valid,
             branches_covered: self.branches_covered + rhs.branches_covered,
             branches_valid: self.branches_valid + rhs.branches_valid,
+            functions_covered: self.functions_covered + rhs.functions_covered,
+            functions_valid: self.functions_valid + rhs.functions_valid,
             complexity: self.complexity + rhs.complexity,
         }
     }
@@ -63,21 +255,30 @@ impl CoverageStats {
             .fold(0.0, |c, (_, l)| if l.covered() { c + 1.0 } else { c });
         let lines_valid = lines.len() as f64;
 
-        let branches: Vec<Vec<Condition>> = lines
+        let branches: Vec<(Vec<Condition>, bool)> = lines
             .into_iter()
             .filter_map(|(_, l)| match l {
-                Line::Branch { conditions, .. } => Some(conditions),
+                Line::Branch {
+                    conditions,
+                    decision,
+                    ..
+                } => Some((conditions, decision)),
                 Line::Plain { .. } => None,
             })
             .collect();
         let (branches_covered, branches_valid) =
             branches
                 .iter()
-                .fold((0.0, 0.0), |(covered, valid), conditions| {
-                    (
-                        covered + conditions.iter().fold(0.0, |hits, c| c.coverage + hits),
-                        valid + conditions.len() as f64,
-                    )
+                .fold((0.0, 0.0), |(covered, valid), (conditions, decision)| {
+                    if *decision {
+                        let taken = conditions.iter().all(|c| c.coverage > 0.0);
+                        (covered + if taken { 1.0 } else { 0.0 }, valid + 1.0)
+                    } else {
+                        (
+                            covered + conditions.iter().filter(|c| c.coverage > 0.0).count() as f64,
+                            valid + conditions.len() as f64,
+                        )
+                    }
                 });
 
         Self {
@@ -85,28 +286,37 @@ impl CoverageStats {
             lines_covered,
             branches_valid,
             branches_covered,
-            // for now always 0
-            complexity: 0.0,
+            functions_covered: 0.0,
+            functions_valid: 0.0,
+            // A proxy of the complexity, refined for the methods.
+            complexity: branches_valid,
         }
     }
 
-    fn line_rate(&self) -> f64 {
+    pub(crate) fn line_rate(&self) -> f64 {
         if self.lines_valid > 0.0 {
             self.lines_covered / self.lines_valid
         } else {
             0.0
         }
     }
-    fn branch_rate(&self) -> f64 {
+    pub(crate) fn branch_rate(&self) -> f64 {
         if self.branches_valid > 0.0 {
             self.branches_covered / self.branches_valid
         } else {
             0.0
         }
     }
+    pub(crate) fn function_rate(&self) -> f64 {
+        if self.functions_valid > 0.0 {
+            self.functions_covered / self.functions_valid
+        } else {
+            0.0
+        }
+    }
 }
 
-trait Stats {
+pub(crate) trait Stats {
     fn get_lines(&self) -> FxHashMap<u32, Line>;
 
     fn get_stats(&self) -> CoverageStats {
@@ -127,22 +337,32 @@ impl Stats for Coverage {
     }
 }
 
-struct Package {
+pub(crate) struct Package {
     name: String,
-    classes: Vec<Class>,
+    pub(crate) classes: Vec<Class>,
 }
 
 impl Stats for Package {
     fn get_lines(&self) -> FxHashMap<u32, Line> {
         self.classes.get_lines()
     }
+
+    fn get_stats(&self) -> CoverageStats {
+        // Classes come from different files, so their lines can't be merged by number.
+        self.classes
+            .iter()
+            .map(|c| c.get_stats())
+            .fold(CoverageStats::default(), |acc, stats| acc + stats)
+    }
 }
 
-struct Class {
+pub(crate) struct Class {
     name: String,
-    file_name: String,
+    pub(crate) file_name: String,
     lines: Vec<Line>,
     methods: Vec<Method>,
+    functions_covered: usize,
+    functions_valid: usize,
 }
 
 impl Stats for Class {
@@ -151,6 +371,18 @@ impl Stats for Class {
         lines.extend(self.methods.get_lines());
         lines
     }
+
+    fn get_stats(&self) -> CoverageStats {
+        let mut stats = CoverageStats {
+            functions_covered: self.functions_covered as f64,
+            functions_valid: self.functions_valid as f64,
+            ..CoverageStats::from_lines(self.get_lines())
+        };
+        if !self.methods.is_empty() {
+            stats.complexity = self.methods.iter().map(|m| m.get_stats().complexity).sum();
+        }
+        stats
+    }
 }
 
 struct Method {
@@ -163,6 +395,15 @@ impl Stats for Method {
     fn get_lines(&self) -> FxHashMap<u32, Line> {
         self.lines.get_lines()
     }
+
+    fn get_stats(&self) -> CoverageStats {
+        let stats = CoverageStats::from_lines(self.get_lines());
+        // A cheap approximation of the cyclomatic complexity: one plus the number of branches.
+        CoverageStats {
+            complexity: 1.0 + stats.branches_valid,
+            ..stats
+        }
+    }
 }
 
 impl<T: Stats> Stats for Vec<T> {
@@ -176,7 +417,7 @@ impl<T: Stats> Stats for Vec<T> {
 }
 
 #[derive(Debug, Clone)]
-enum Line {
+pub(crate) enum Line {
     Plain {
         number: u32,
         hits: u64,
@@ -186,6 +427,8 @@ enum Line {
         number: u32,
         hits: u64,
         conditions: Vec<Condition>,
+        /// The line counts as a single branch in the branch rates.
+        decision: bool,
     },
 }
 
@@ -210,34 +453,66 @@ impl Stats for Line {
 }
 
 #[derive(Debug, Clone)]
-struct Condition {
+pub(crate) struct Condition {
     number: usize,
     cond_type: ConditionType,
     coverage: f64,
+    /// The numbers of taken and of all branches, for a condition merging several of them.
+    branches: Option<(usize, usize)>,
 }
 
 // Condition types
 #[derive(Debug, Clone)]
 enum ConditionType {
     Jump,
+    Switch,
 }
 
 impl ToString for ConditionType {
     fn to_string(&self) -> String {
         match *self {
             Self::Jump => String::from("jump"),
+            Self::Switch => String::from("switch"),
         }
     }
 }
 
-fn get_coverage(
+pub(crate) fn get_coverage(
     results: CovResultIter,
-    demangle: bool,
-    demangle_options: DemangleOptions,
+    demangle: DemangleConfig,
+    config: &CoberturaConfig,
 ) -> Coverage {
-    let sources = vec![".".to_owned()];
-    let packages: Vec<Package> = results
-        .map(|(_, rel_path, result)| {
+    let sources = if config.sources.is_empty() {
+        vec![".".to_owned()]
+    } else {
+        config.sources.clone()
+    };
+    // Several results can have the same relative path, e.g. when the prefix is removed from
+    // the paths of different machines: merge them, for each file to be reported once.
+    let mut results_by_path: BTreeMap<PathBuf, CovResult> = BTreeMap::new();
+    for (_, rel_path, result) in results {
+        if rel_path.to_str().is_none() {
+            warn!(
+                "The path {} isn't valid UTF-8, it's written with replacement characters in the Cobertura report.",
+                rel_path.display()
+            );
+        }
+        match results_by_path.entry(rel_path) {
+            btree_map::Entry::Occupied(mut entry) => {
+                if merge_results(entry.get_mut(), result) {
+                    warn!("Execution count overflow detected.");
+                }
+            }
+            btree_map::Entry::Vacant(entry) => {
+                entry.insert(result);
+            }
+        }
+    }
+
+    let demangling_failures = Cell::new(0);
+    let packages: Vec<Package> = results_by_path
+        .into_iter()
+        .map(|(rel_path, result)| {
             let all_lines: Vec<u32> = result.lines.iter().map(|(k, _)| k).cloned().collect();
 
             let mut orphan_lines: BTreeSet<u32> = all_lines.iter().cloned().collect();
@@ -249,174 +524,656 @@ fn get_coverage(
                 start_indexes.push(function.start);
             }
             start_indexes.sort_unstable();
+            start_indexes.dedup();
+
+            // A function ends at its end line when the format gives it, where the next one
+            // starts otherwise. The functions sharing their start line, e.g. a function and
+            // its closures, all get the same lines.
+            let func_end = |function: &Function| match function.end {
+                Some(last) => last + 1,
+                None => start_indexes
+                    .iter()
+                    .find(|&&start| start > function.start)
+                    .cloned()
+                    .unwrap_or(end),
+            };
+            // The ranges of the functions, to give the lines of nested functions, e.g.
+            // closures, to the innermost one.
+            let ranges: Vec<(u32, u32)> = result
+                .functions
+                .values()
+                .map(|function| (function.start, func_end(function)))
+                .collect();
 
             let functions = result.functions;
             let result_lines = result.lines;
             let result_branches = result.branches;
+            let result_branch_hits = result.branch_hits;
 
             let line_from_number = |number| {
                 let hits = result_lines.get(&number).cloned().unwrap_or_default();
-                if let Some(branches) = result_branches.get(&number) {
-                    let conditions = branches
-                        .iter()
-                        .enumerate()
-                        .map(|(i, b)| Condition {
-                            cond_type: ConditionType::Jump,
-                            coverage: if *b { 1.0 } else { 0.0 },
-                            number: i,
-                        })
-                        .collect::<Vec<_>>();
+                if let Some(branches) = result_branches
+                    .get(&number)
+                    .filter(|branches| is_branch(branches))
+                {
+                    let conditions = match config.collapse_branches {
+                        Some(collapse) => {
+                            let covered = match collapse {
+                                BranchCollapse::Any => branches.iter().any(|b| *b),
+                                BranchCollapse::All => branches.iter().all(|b| *b),
+                            };
+                            vec![Condition {
+                                cond_type: ConditionType::Jump,
+                                coverage: if covered { 1.0 } else { 0.0 },
+                                number: 0,
+                                branches: Some((
+                                    branches.iter().filter(|b| **b).count(),
+                                    branches.len(),
+                                )),
+                            }]
+                        }
+                        None => branches
+                            .iter()
+                            .enumerate()
+                            .map(|(i, b)| {
+                                // With the hit counts, the coverage of a branch is the share of
+                                // the executions of its line which took it.
+                                let branch_hits = result_branch_hits
+                                    .get(&number)
+                                    .and_then(|branch_hits| branch_hits.get(i))
+                                    .cloned()
+                                    .unwrap_or_default();
+                                let coverage = if *b && hits > 0 && branch_hits > 0 {
+                                    (branch_hits as f64 / hits as f64).min(1.0)
+                                } else if *b {
+                                    1.0
+                                } else {
+                                    0.0
+                                };
+                                Condition {
+                                    // More than two ways out of a line come from a switch or a match.
+                                    cond_type: if branches.len() > 2 {
+                                        ConditionType::Switch
+                                    } else {
+                                        ConditionType::Jump
+                                    },
+                                    coverage,
+                                    number: i,
+                                    branches: None,
+                                }
+                            })
+                            .collect::<Vec<_>>(),
+                    };
                     Line::Branch {
                         number,
                         hits,
                         conditions,
+                        decision: config.decision_branch_rate,
                     }
                 } else {
                     Line::Plain { number, hits }
                 }
             };
 
-            let methods: Vec<Method> = functions
+            let methods: Vec<(u32, Method, bool)> = functions
                 .iter()
                 .map(|(name, function)| {
-                    let mut func_end = end;
-
-                    for start in &start_indexes {
-                        if *start > function.start {
-                            func_end = *start;
-                            break;
-                        }
-                    }
+                    let func_end = func_end(function);
+                    let nested: Vec<&(u32, u32)> = ranges
+                        .iter()
+                        .filter(|(start, _)| *start > function.start && *start < func_end)
+                        .collect();
 
                     let mut lines_in_function: Vec<u32> = Vec::new();
-                    for line in all_lines
-                        .iter()
-                        .filter(|&&x| x >= function.start && x < func_end)
-                    {
+                    for line in all_lines.iter().filter(|&&x| {
+                        x >= function.start
+                            && x < func_end
+                            && !nested.iter().any(|(start, end)| x >= *start && x < *end)
+                    }) {
                         lines_in_function.push(*line);
                         orphan_lines.remove(line);
                     }
 
-                    let lines: Vec<Line> = lines_in_function
+                    let mut lines: Vec<Line> = lines_in_function
                         .into_iter()
                         .map(line_from_number)
                         .collect();
+                    if lines.is_empty()
+                        && (config.empty_function_lines
+                            || (!function.executed && config.dead_function_lines))
+                    {
+                        lines.push(Line::Plain {
+                            number: function.start,
+                            hits: if function.executed { 1 } else { 0 },
+                        });
+                    }
 
-                    Method {
-                        name: demangle!(name, demangle, demangle_options),
-                        signature: String::new(),
+                    let method = Method {
+                        name: xml_text(if demangle.enabled {
+                            demangle_name(
+                                name,
+                                demangle.options(),
+                                config.demangle_language,
+                                &demangling_failures,
+                            )
+                        } else {
+                            name.clone()
+                        }),
+                        signature: if demangle.enabled {
+                            xml_text(method_signature(name, config.demangle_language))
+                        } else {
+                            String::new()
+                        },
                         lines,
-                    }
+                    };
+                    (function.start, method, function.executed)
                 })
                 .collect();
+            // The instances of a function, e.g. with different hashes in several codegen
+            // units, have the same demangled name and start line: they're one function,
+            // executed if any of them was, with the lines of an executed instance.
+            let mut instances: BTreeMap<(u32, String), (Method, bool)> = BTreeMap::new();
+            for (start, method, executed) in methods {
+                match instances.entry((start, method.name.clone())) {
+                    btree_map::Entry::Occupied(mut entry) => {
+                        let (merged, merged_executed) = entry.get_mut();
+                        if executed && !*merged_executed {
+                            *merged = method;
+                            *merged_executed = true;
+                        }
+                    }
+                    btree_map::Entry::Vacant(entry) => {
+                        entry.insert((method, executed));
+                    }
+                }
+            }
+            let methods = instances
+                .into_iter()
+                .map(|((start, _), (method, executed))| (start, method, executed));
+            // The functions matching the excluded names, e.g. the glue generated by the
+            // compiler, aren't methods: their lines are reported at the class level.
+            let (mut methods, excluded_methods): (Vec<_>, Vec<_>) =
+                methods.partition(|(_, method, _)| {
+                    !config
+                        .excluded_methods
+                        .as_ref()
+                        .is_some_and(|excluded| excluded.is_match(&method.name))
+                });
+            // The functions are in a hash map, sort them for the report to be reproducible.
+            // The functions sharing their start line, e.g. a function and its closures or the
+            // instances of a generic function, are reported as one method, named after the
+            // shortest name, i.e. the enclosing function, with the lines of all of them and
+            // executed if any of them was.
+            methods.sort_by(|(a_start, a, _), (b_start, b, _)| {
+                (a_start, a.name.len(), &a.name).cmp(&(b_start, b.name.len(), &b.name))
+            });
+            let mut merged: Vec<(u32, Method, bool)> = Vec::with_capacity(methods.len());
+            for (start, method, executed) in methods {
+                match merged.last_mut() {
+                    Some((last_start, last, last_executed)) if *last_start == start => {
+                        *last_executed |= executed;
+                        for line in method.lines {
+                            match last.lines.iter_mut().find(|l| l.number() == line.number()) {
+                                Some(l) if !l.covered() && line.covered() => *l = line,
+                                Some(_) => {}
+                                None => last.lines.push(line),
+                            }
+                        }
+                        last.lines.sort_by_key(Line::number);
+                    }
+                    _ => merged.push((start, method, executed)),
+                }
+            }
+            let functions_covered = merged.iter().filter(|(_, _, executed)| *executed).count();
+            let functions_valid = merged.len();
+            let methods: Vec<Method> = merged.into_iter().map(|(_, method, _)| method).collect();
+
+            // Fold methods which are too short into the class.
+            let (methods, short_methods): (Vec<Method>, Vec<Method>) = methods
+                .into_iter()
+                .partition(|m| !config.omit_methods && m.lines.len() >= config.min_method_lines);
+            let method_lines: BTreeSet<u32> = methods
+                .iter()
+                .flat_map(|m| m.lines.iter().map(Line::number))
+                .collect();
+            let excluded_methods = excluded_methods.into_iter().map(|(_, method, _)| method);
+            for method in short_methods.into_iter().chain(excluded_methods) {
+                orphan_lines.extend(
+                    method
+                        .lines
+                        .iter()
+                        .map(Line::number)
+                        .filter(|n| !method_lines.contains(n)),
+                );
+            }
 
             let lines: Vec<Line> = orphan_lines.into_iter().map(line_from_number).collect();
             let class = Class {
-                name: rel_path
-                    .file_stem()
-                    .map(|x| x.to_str().unwrap())
-                    .unwrap_or_default()
-                    .to_string(),
-                file_name: rel_path.to_str().unwrap_or_default().to_string(),
+                name: xml_text(class_name(&rel_path, config.class_names)),
+                file_name: rel_path.to_string_lossy().into_owned(),
                 lines,
                 methods,
+                functions_covered,
+                functions_valid,
             };
 
             Package {
-                name: rel_path.to_str().unwrap_or_default().to_string(),
+                name: rel_path.to_string_lossy().into_owned(),
                 classes: vec![class],
             }
         })
         .collect();
 
-    Coverage { sources, packages }
+    // The paths are ordered by components, rather than by names.
+    let mut packages = packages;
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let (generated, mut packages): (Vec<Package>, Vec<Package>) =
+        packages.into_iter().partition(|package| {
+            config
+                .generated_prefixes
+                .iter()
+                .any(|prefix| Path::new(&package.name).starts_with(prefix))
+        });
+    if config.package_per_directory {
+        let package_names = config
+            .package_names
+            .as_ref()
+            .unwrap_or(&PackageNames::DottedDir);
+        let mut directories: BTreeMap<PathBuf, Vec<Class>> = BTreeMap::new();
+        for class in packages.into_iter().flat_map(|p| p.classes) {
+            let directory = Path::new(&class.file_name)
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+            directories.entry(directory).or_default().push(class);
+        }
+        packages = directories
+            .into_iter()
+            .map(|(directory, classes)| Package {
+                name: package_name(&directory, &directory, package_names),
+                classes,
+            })
+            .collect();
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+    } else if let Some(package_names) = &config.package_names {
+        for package in &mut packages {
+            let path = PathBuf::from(&package.name);
+            let directory = path.parent().unwrap_or_else(|| Path::new(""));
+            package.name = package_name(&path, directory, package_names);
+        }
+    }
+    if !generated.is_empty() {
+        packages.push(Package {
+            name: "generated".to_string(),
+            classes: generated.into_iter().flat_map(|p| p.classes).collect(),
+        });
+    }
+
+    Coverage {
+        sources,
+        packages,
+        demangling_failures: demangling_failures.get(),
+    }
 }
 
-pub fn output_cobertura(results: CovResultIter, output_file: Option<&str>, demangle: bool) {
-    let demangle_options = DemangleOptions::name_only();
+/// Demangles a method name, the names which look mangled but can't be demangled, e.g. with
+/// an unsupported mangling scheme, are counted and left as they are.
+fn demangle_name(
+    name: &str,
+    options: DemangleOptions,
+    language: Language,
+    failures: &Cell<usize>,
+) -> String {
+    let name = Name::new(name, NameMangling::Unknown, language);
+    match name.demangle(options) {
+        Some(demangled) => demangled,
+        None => {
+            // The names of e.g. C functions aren't mangled.
+            if name.detect_language() != Language::Unknown {
+                failures.set(failures.get() + 1);
+            }
+            name.as_str().to_string()
+        }
+    }
+}
 
-    let coverage = get_coverage(results, demangle, demangle_options);
+/// Returns the parameter list of a method, e.g. `(int, char const*) const`, from its
+/// complete demangled name. It's empty for Rust methods, whose symbols don't carry it.
+fn method_signature(name: &str, language: Language) -> String {
+    let name = Name::new(name, NameMangling::Unknown, language);
+    let complete = match name.demangle(DemangleOptions::complete()) {
+        Some(complete) => complete,
+        None => return String::new(),
+    };
+    // Find the parenthesis opening the last parameter list, the name itself can contain
+    // some, e.g. in `(anonymous namespace)::foo(int)`.
+    let close = match complete.rfind(')') {
+        Some(close) => close,
+        None => return String::new(),
+    };
+    let mut depth = 0;
+    for (i, c) in complete[..=close].char_indices().rev() {
+        match c {
+            ')' => depth += 1,
+            '(' => {
+                depth -= 1;
+                if depth == 0 {
+                    return complete[i..].to_string();
+                }
+            }
+            _ => {}
+        }
+    }
+    String::new()
+}
 
-    let mut writer = Writer::new_with_indent(Cursor::new(vec![]), b' ', 4);
-    writer
-        .write_event(Event::Decl(BytesDecl::new(b"1.0", None, None)))
-        .unwrap();
-    writer
-        .write_event(Event::DocType(BytesText::from_escaped_str(
-            " coverage SYSTEM 'http://cobertura.sourceforge.net/xml/coverage-04.dtd'",
-        )))
-        .unwrap();
+/// Replaces the characters which can't appear in an XML document, even escaped, e.g. the
+/// control characters of some names.
+fn xml_text(text: String) -> String {
+    let is_valid = |c: char| {
+        !matches!(
+            c,
+            '\u{0}'..='\u{8}' | '\u{b}' | '\u{c}' | '\u{e}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}'
+        )
+    };
+    if text.chars().all(is_valid) {
+        text
+    } else {
+        text.chars()
+            .map(|c| if is_valid(c) { c } else { '\u{fffd}' })
+            .collect()
+    }
+}
 
-    let cov_tag = b"coverage";
-    let mut cov = BytesStart::borrowed(cov_tag, cov_tag.len());
-    let stats = coverage.get_stats();
-    cov.push_attribute(("lines-covered", stats.lines_covered.to_string().as_ref()));
-    cov.push_attribute(("lines-valid", stats.lines_valid.to_string().as_ref()));
-    cov.push_attribute(("line-rate", stats.line_rate().to_string().as_ref()));
-    cov.push_attribute((
+fn class_name(rel_path: &Path, class_names: Option<ClassNames>) -> String {
+    match class_names {
+        None => rel_path
+            .file_stem()
+            .map(|x| x.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        Some(ClassNames::Path) => rel_path.to_string_lossy().into_owned(),
+        Some(ClassNames::Dotted) => dotted(&rel_path.with_extension("")),
+    }
+}
+
+/// Names the package of a file, or of a directory, from their relative paths.
+fn package_name(path: &Path, directory: &Path, package_names: &PackageNames) -> String {
+    match package_names {
+        PackageNames::FilePath => path.to_string_lossy().into_owned(),
+        PackageNames::DottedDir => dotted(directory),
+        PackageNames::CustomTemplate(template) => {
+            let mut name = template
+                .replace("{path}", &path.to_string_lossy())
+                .replace("{dir}", &directory.to_string_lossy())
+                .replace("{dotted_dir}", &dotted(directory));
+            for (i, component) in directory.iter().enumerate() {
+                name = name.replace(&format!("{{{}}}", i), &component.to_string_lossy());
+            }
+            name
+        }
+    }
+}
+
+/// Joins the components of a path with dots, e.g. `src.foo.bar` for `src/foo/bar`.
+fn dotted(path: &Path) -> String {
+    path.iter()
+        .map(|component| component.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Returns the class names shared by several files, e.g. `mod` for `foo/mod.rs` and
+/// `bar/mod.rs`, which some consumers merge into one class.
+fn find_duplicate_class_names(coverage: &Coverage) -> Vec<String> {
+    let mut names = BTreeSet::new();
+    let mut duplicates = BTreeSet::new();
+    for class in coverage
+        .packages
+        .iter()
+        .flat_map(|package| &package.classes)
+    {
+        if !names.insert(&class.name) {
+            duplicates.insert(class.name.clone());
+        }
+    }
+    duplicates.into_iter().collect()
+}
+
+/// Returns the file names of the classes which don't exist under any of the sources.
+fn find_dangling_filenames(coverage: &Coverage, root: &Path) -> Vec<String> {
+    coverage
+        .packages
+        .iter()
+        .flat_map(|package| package.classes.iter())
+        .filter(|class| {
+            !coverage
+                .sources
+                .iter()
+                .any(|source| root.join(source).join(&class.file_name).is_file())
+        })
+        .map(|class| class.file_name.clone())
+        .collect()
+}
+
+/// Joins the file name onto the root, with forward slashes whatever the platform. The file
+/// names which are already absolute are kept.
+fn absolute_file_name(root: &str, file_name: &str) -> String {
+    let file_name = file_name.replace('\\', "/");
+    if file_name.starts_with('/') || Path::new(&file_name).is_absolute() {
+        return file_name;
+    }
+    let root = root.replace('\\', "/");
+    format!("{}/{}", root.trim_end_matches('/'), file_name)
+}
+
+fn format_rate(rate: f64, precision: Option<usize>, rounding: Rounding) -> String {
+    match precision {
+        Some(precision) => {
+            let rate = format_rounded(rate, precision, rounding);
+            if rate.contains('.') {
+                rate.trim_end_matches('0').trim_end_matches('.').to_string()
+            } else {
+                rate
+            }
+        }
+        None => rate.to_string(),
+    }
+}
+
+/// Adds the line and branch counts and rates to the element, for the consumers which
+/// aggregate the counts rather than the rates.
+fn push_stats_attributes(
+    element: &mut BytesStart,
+    stats: &CoverageStats,
+    config: &CoberturaConfig,
+) {
+    element.push_attribute(("lines-covered", stats.lines_covered.to_string().as_ref()));
+    element.push_attribute(("lines-valid", stats.lines_valid.to_string().as_ref()));
+    element.push_attribute((
+        "line-rate",
+        format_rate(stats.line_rate(), config.rate_precision, config.rounding).as_ref(),
+    ));
+    element.push_attribute((
         "branches-covered",
         stats.branches_covered.to_string().as_ref(),
     ));
-    cov.push_attribute(("branches-valid", stats.branches_valid.to_string().as_ref()));
-    cov.push_attribute(("branch-rate", stats.branch_rate().to_string().as_ref()));
-    cov.push_attribute(("complexity", "0"));
-    cov.push_attribute(("version", "1.9"));
-
-    let secs = match SystemTime::now().duration_since(UNIX_EPOCH) {
-        Ok(s) => s.as_secs().to_string(),
-        Err(_) => String::from("0"),
+    element.push_attribute(("branches-valid", stats.branches_valid.to_string().as_ref()));
+    element.push_attribute((
+        "branch-rate",
+        format_rate(stats.branch_rate(), config.rate_precision, config.rounding).as_ref(),
+    ));
+}
+
+pub fn output_cobertura(
+    results: CovResultIter,
+    output_file: Option<&str>,
+    line_ending: LineEnding,
+    demangle: DemangleConfig,
+    config: &CoberturaConfig,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(get_target_output_writable(output_file, line_ending)?);
+    write_cobertura(results, &mut writer, demangle, config)?;
+    finish_output(writer)
+}
+
+/// Returns the Cobertura report in memory, e.g. to send it somewhere without a temporary file.
+pub fn cobertura_to_bytes(
+    results: CovResultIter,
+    demangle: DemangleConfig,
+    config: &CoberturaConfig,
+) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    write_cobertura(results, &mut bytes, demangle, config).expect("Writing to memory can't fail");
+    bytes
+}
+
+fn write_cobertura<W: Write>(
+    results: CovResultIter,
+    output: W,
+    demangle: DemangleConfig,
+    config: &CoberturaConfig,
+) -> io::Result<()> {
+    let coverage = get_coverage(results, demangle, config);
+
+    if coverage.demangling_failures > 0 {
+        warn!(
+            "{} method names couldn't be demangled, they're written mangled in the Cobertura report",
+            coverage.demangling_failures
+        );
+    }
+
+    if let Some(root) = &config.verify_root {
+        for file_name in find_dangling_filenames(&coverage, root) {
+            warn!(
+                "{} can't be found under the sources of the Cobertura report",
+                file_name
+            );
+        }
+    }
+
+    for name in find_duplicate_class_names(&coverage) {
+        warn!(
+            "Several files have the class name {} in the Cobertura report, derive the class names from the paths to tell them apart",
+            name
+        );
+    }
+
+    let absolute_root = if config.absolute_filenames {
+        config.sources.first().map(String::as_str)
+    } else {
+        None
+    };
+
+    // The events are written to the output as they come, the report of a large project
+    // doesn't fit in memory.
+    let mut writer = match config.indent {
+        Some(Indent::Minified) => Writer::new(output),
+        Some(Indent::Spaces(width)) => Writer::new_with_indent(output, b' ', width),
+        Some(Indent::Tab) => Writer::new_with_indent(output, b'\t', 1),
+        None => Writer::new_with_indent(output, b' ', 4),
+    };
+    write_event(&mut writer, Event::Decl(BytesDecl::new(b"1.0", None, None)))?;
+    let dtd = match &config.doctype {
+        Some(Doctype::Omit) => None,
+        Some(Doctype::System(dtd)) => {
+            Doctype::check(dtd).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            Some(dtd.as_str())
+        }
+        None => Some("http://cobertura.sourceforge.net/xml/coverage-04.dtd"),
     };
+    if let Some(dtd) = dtd {
+        write_event(
+            &mut writer,
+            Event::DocType(BytesText::from_escaped_str(format!(
+                " coverage SYSTEM '{}'",
+                dtd
+            ))),
+        )?;
+    }
+
+    let cov_tag = b"coverage";
+    let mut cov = BytesStart::borrowed(cov_tag, cov_tag.len());
+    let stats = coverage.get_stats();
+    push_stats_attributes(&mut cov, &stats, config);
+    if config.function_stats {
+        cov.push_attribute((
+            "functions-covered",
+            stats.functions_covered.to_string().as_ref(),
+        ));
+        cov.push_attribute((
+            "functions-valid",
+            stats.functions_valid.to_string().as_ref(),
+        ));
+        cov.push_attribute((
+            "function-rate",
+            format_rate(
+                stats.function_rate(),
+                config.rate_precision,
+                config.rounding,
+            )
+            .as_ref(),
+        ));
+    }
+    if !config.omit_complexity {
+        cov.push_attribute(("complexity", stats.complexity.to_string().as_ref()));
+    }
+    cov.push_attribute(("version", config.version.as_deref().unwrap_or("1.9")));
+
+    // Honor SOURCE_DATE_EPOCH for the report to be reproducible,
+    // see https://reproducible-builds.org/specs/source-date-epoch/.
+    let secs = config
+        .timestamp
+        .or_else(|| {
+            env::var("SOURCE_DATE_EPOCH")
+                .ok()
+                .and_then(|epoch| epoch.parse().ok())
+        })
+        .unwrap_or_else(|| match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(s) => s.as_secs(),
+            Err(_) => 0,
+        })
+        .to_string();
     cov.push_attribute(("timestamp", secs.as_ref()));
 
-    writer.write_event(Event::Start(cov)).unwrap();
+    write_event(&mut writer, Event::Start(cov))?;
 
     // export header
     let sources_tag = b"sources";
     let source_tag = b"source";
-    writer
-        .write_event(Event::Start(BytesStart::borrowed(
-            sources_tag,
-            sources_tag.len(),
-        )))
-        .unwrap();
+    write_event(
+        &mut writer,
+        Event::Start(BytesStart::borrowed(sources_tag, sources_tag.len())),
+    )?;
     for path in &coverage.sources {
-        writer
-            .write_event(Event::Start(BytesStart::borrowed(
-                source_tag,
-                source_tag.len(),
-            )))
-            .unwrap();
-        writer
-            .write_event(Event::Text(BytesText::from_plain_str(path)))
-            .unwrap();
-        writer
-            .write_event(Event::End(BytesEnd::borrowed(source_tag)))
-            .unwrap();
+        write_event(
+            &mut writer,
+            Event::Start(BytesStart::borrowed(source_tag, source_tag.len())),
+        )?;
+        write_event(&mut writer, Event::Text(BytesText::from_plain_str(path)))?;
+        write_event(&mut writer, Event::End(BytesEnd::borrowed(source_tag)))?;
     }
-    writer
-        .write_event(Event::End(BytesEnd::borrowed(sources_tag)))
-        .unwrap();
+    write_event(&mut writer, Event::End(BytesEnd::borrowed(sources_tag)))?;
 
     // export packages
     let packages_tag = b"packages";
     let pack_tag = b"package";
 
-    writer
-        .write_event(Event::Start(BytesStart::borrowed(
-            packages_tag,
-            packages_tag.len(),
-        )))
-        .unwrap();
+    write_event(
+        &mut writer,
+        Event::Start(BytesStart::borrowed(packages_tag, packages_tag.len())),
+    )?;
     // Export the package
     for package in &coverage.packages {
         let mut pack = BytesStart::borrowed(pack_tag, pack_tag.len());
         pack.push_attribute(("name", package.name.as_ref()));
         let stats = package.get_stats();
-        pack.push_attribute(("line-rate", stats.line_rate().to_string().as_ref()));
-        pack.push_attribute(("branch-rate", stats.branch_rate().to_string().as_ref()));
-        pack.push_attribute(("complexity", stats.complexity.to_string().as_ref()));
+        push_stats_attributes(&mut pack, &stats, config);
+        if !config.omit_complexity {
+            pack.push_attribute(("complexity", stats.complexity.to_string().as_ref()));
+        }
 
-        writer.write_event(Event::Start(pack)).unwrap();
+        write_event(&mut writer, Event::Start(pack))?;
 
         // export_classes
         let classes_tag = b"classes";
@@ -424,84 +1181,87 @@ pub fn output_cobertura(results: CovResultIter, output_file: Option<&str>, deman
         let methods_tag = b"methods";
         let method_tag = b"method";
 
-        writer
-            .write_event(Event::Start(BytesStart::borrowed(
-                classes_tag,
-                classes_tag.len(),
-            )))
-            .unwrap();
+        write_event(
+            &mut writer,
+            Event::Start(BytesStart::borrowed(classes_tag, classes_tag.len())),
+        )?;
 
         for class in &package.classes {
             let mut c = BytesStart::borrowed(class_tag, class_tag.len());
             c.push_attribute(("name", class.name.as_ref()));
-            c.push_attribute(("filename", class.file_name.as_ref()));
+            match absolute_root {
+                Some(root) => c.push_attribute((
+                    "filename",
+                    absolute_file_name(root, &class.file_name).as_ref(),
+                )),
+                None => c.push_attribute(("filename", class.file_name.as_ref())),
+            }
             let stats = class.get_stats();
-            c.push_attribute(("line-rate", stats.line_rate().to_string().as_ref()));
-            c.push_attribute(("branch-rate", stats.branch_rate().to_string().as_ref()));
-            c.push_attribute(("complexity", stats.complexity.to_string().as_ref()));
-
-            writer.write_event(Event::Start(c)).unwrap();
-            writer
-                .write_event(Event::Start(BytesStart::borrowed(
-                    methods_tag,
-                    methods_tag.len(),
-                )))
-                .unwrap();
+            push_stats_attributes(&mut c, &stats, config);
+            if !config.omit_complexity {
+                c.push_attribute(("complexity", stats.complexity.to_string().as_ref()));
+            }
+
+            write_event(&mut writer, Event::Start(c))?;
+            write_event(
+                &mut writer,
+                Event::Start(BytesStart::borrowed(methods_tag, methods_tag.len())),
+            )?;
 
             for method in &class.methods {
                 let mut m = BytesStart::borrowed(method_tag, method_tag.len());
                 m.push_attribute(("name", method.name.as_ref()));
                 m.push_attribute(("signature", method.signature.as_ref()));
                 let stats = method.get_stats();
-                m.push_attribute(("line-rate", stats.line_rate().to_string().as_ref()));
-                m.push_attribute(("branch-rate", stats.branch_rate().to_string().as_ref()));
-                m.push_attribute(("complexity", stats.complexity.to_string().as_ref()));
-                writer.write_event(Event::Start(m)).unwrap();
-
-                write_lines(&mut writer, &method.lines);
-                writer
-                    .write_event(Event::End(BytesEnd::borrowed(method_tag)))
-                    .unwrap();
+                m.push_attribute((
+                    "line-rate",
+                    format_rate(stats.line_rate(), config.rate_precision, config.rounding).as_ref(),
+                ));
+                m.push_attribute((
+                    "branch-rate",
+                    format_rate(stats.branch_rate(), config.rate_precision, config.rounding)
+                        .as_ref(),
+                ));
+                if !config.omit_complexity {
+                    m.push_attribute(("complexity", stats.complexity.to_string().as_ref()));
+                }
+                write_event(&mut writer, Event::Start(m))?;
+
+                write_lines(&mut writer, &method.lines, config.max_hits)?;
+                write_event(&mut writer, Event::End(BytesEnd::borrowed(method_tag)))?;
             }
-            writer
-                .write_event(Event::End(BytesEnd::borrowed(methods_tag)))
-                .unwrap();
-            write_lines(&mut writer, &class.lines);
+            write_event(&mut writer, Event::End(BytesEnd::borrowed(methods_tag)))?;
+            write_lines(&mut writer, &class.lines, config.max_hits)?;
+            write_event(&mut writer, Event::End(BytesEnd::borrowed(class_tag)))?;
         }
-        writer
-            .write_event(Event::End(BytesEnd::borrowed(class_tag)))
-            .unwrap();
-        writer
-            .write_event(Event::End(BytesEnd::borrowed(classes_tag)))
-            .unwrap();
-        writer
-            .write_event(Event::End(BytesEnd::borrowed(pack_tag)))
-            .unwrap();
+        write_event(&mut writer, Event::End(BytesEnd::borrowed(classes_tag)))?;
+        write_event(&mut writer, Event::End(BytesEnd::borrowed(pack_tag)))?;
     }
 
-    writer
-        .write_event(Event::End(BytesEnd::borrowed(packages_tag)))
-        .unwrap();
+    write_event(&mut writer, Event::End(BytesEnd::borrowed(packages_tag)))?;
 
-    writer
-        .write_event(Event::End(BytesEnd::borrowed(cov_tag)))
-        .unwrap();
+    write_event(&mut writer, Event::End(BytesEnd::borrowed(cov_tag)))?;
 
-    let result = writer.into_inner().into_inner();
-    let mut file = BufWriter::new(get_target_output_writable(output_file));
-    file.write_all(&result).unwrap();
+    writer.into_inner().flush()
 }
 
-fn write_lines(writer: &mut Writer<Cursor<Vec<u8>>>, lines: &[Line]) {
+fn write_lines<W: Write>(
+    writer: &mut Writer<W>,
+    lines: &[Line],
+    max_hits: Option<u64>,
+) -> io::Result<()> {
+    let capped = |hits: u64| match max_hits {
+        Some(max_hits) if hits > max_hits => max_hits.max(1),
+        _ => hits,
+    };
+
     let lines_tag = b"lines";
     let line_tag = b"line";
 
-    writer
-        .write_event(Event::Start(BytesStart::borrowed(
-            lines_tag,
-            lines_tag.len(),
-        )))
-        .unwrap();
+    write_event(
+        writer,
+        Event::Start(BytesStart::borrowed(lines_tag, lines_tag.len())),
+    )?;
     for line in lines {
         let mut l = BytesStart::borrowed(line_tag, line_tag.len());
         match line {
@@ -510,47 +1270,66 @@ fn write_lines(writer: &mut Writer<Cursor<Vec<u8>>>, lines: &[Line]) {
                 ref hits,
             } => {
                 l.push_attribute(("number", number.to_string().as_ref()));
-                l.push_attribute(("hits", hits.to_string().as_ref()));
-                writer.write_event(Event::Start(l)).unwrap();
+                l.push_attribute(("hits", capped(*hits).to_string().as_ref()));
+                write_event(writer, Event::Start(l))?;
             }
             Line::Branch {
                 ref number,
                 ref hits,
                 conditions,
+                ..
             } => {
                 l.push_attribute(("number", number.to_string().as_ref()));
-                l.push_attribute(("hits", hits.to_string().as_ref()));
+                l.push_attribute(("hits", capped(*hits).to_string().as_ref()));
                 l.push_attribute(("branch", "true"));
-                writer.write_event(Event::Start(l)).unwrap();
+                // The share of the conditions which were taken, as written by Cobertura.
+                let taken = conditions.iter().filter(|c| c.coverage > 0.0).count();
+                let condition_coverage = format!(
+                    "{}% ({}/{})",
+                    (taken * 100).checked_div(conditions.len()).unwrap_or(0),
+                    taken,
+                    conditions.len()
+                );
+                l.push_attribute(("condition-coverage", condition_coverage.as_ref()));
+                write_event(writer, Event::Start(l))?;
 
                 let conditions_tag = b"conditions";
                 let condition_tag = b"condition";
 
-                writer
-                    .write_event(Event::Start(BytesStart::borrowed(
-                        conditions_tag,
-                        conditions_tag.len(),
-                    )))
-                    .unwrap();
+                write_event(
+                    writer,
+                    Event::Start(BytesStart::borrowed(conditions_tag, conditions_tag.len())),
+                )?;
                 for condition in conditions {
                     let mut c = BytesStart::borrowed(condition_tag, condition_tag.len());
                     c.push_attribute(("number", condition.number.to_string().as_ref()));
                     c.push_attribute(("type", condition.cond_type.to_string().as_ref()));
-                    c.push_attribute(("coverage", condition.coverage.to_string().as_ref()));
-                    writer.write_event(Event::Empty(c)).unwrap();
+                    let coverage = match condition.branches {
+                        Some((taken, total)) => format!(
+                            "{}% ({}/{})",
+                            (taken * 100).checked_div(total).unwrap_or(0),
+                            taken,
+                            total
+                        ),
+                        None => format!("{}%", (condition.coverage * 100.0).round()),
+                    };
+                    c.push_attribute(("coverage", coverage.as_ref()));
+                    write_event(writer, Event::Empty(c))?;
                 }
-                writer
-                    .write_event(Event::End(BytesEnd::borrowed(conditions_tag)))
-                    .unwrap();
+                write_event(writer, Event::End(BytesEnd::borrowed(conditions_tag)))?;
             }
         }
-        writer
-            .write_event(Event::End(BytesEnd::borrowed(line_tag)))
-            .unwrap();
+        write_event(writer, Event::End(BytesEnd::borrowed(line_tag)))?;
     }
-    writer
-        .write_event(Event::End(BytesEnd::borrowed(lines_tag)))
-        .unwrap();
+    write_event(writer, Event::End(BytesEnd::borrowed(lines_tag)))
+}
+
+/// Writes an XML event, the errors of the writer are the ones of the output.
+fn write_event<W: Write>(writer: &mut Writer<W>, event: Event) -> io::Result<()> {
+    writer.write_event(event).map_err(|e| match e {
+        quick_xml::Error::Io(e) => e,
+        e => io::Error::other(e.to_string()),
+    })
 }
 
 #[cfg(test)]
@@ -607,10 +1386,12 @@ mod tests {
                         Function {
                             start: 1,
                             executed: true,
+                            end: None,
                         },
                     );
                     map
                 },
+                ..Default::default()
             },
             Result::Test => CovResult {
                 /* main.rs
@@ -651,6 +1432,7 @@ mod tests {
                         Function {
                             start: 6,
                             executed: true,
+                            end: None,
                         },
                     );
 
@@ -659,6 +1441,7 @@ mod tests {
                         Function {
                             start: 1,
                             executed: false,
+                            end: None,
                         },
                     );
 
@@ -667,6 +1450,7 @@ mod tests {
                         Function {
                             start: 1,
                             executed: true,
+                            end: None,
                         },
                     );
 
@@ -676,6 +1460,7 @@ mod tests {
                         Function {
                             start: 6,
                             executed: true,
+                            end: None,
                         },
                     );
 
@@ -684,10 +1469,12 @@ mod tests {
                         Function {
                             start: 1,
                             executed: false,
+                            end: None,
                         },
                     );
                     map
                 },
+                ..Default::default()
             },
         }
     }
@@ -700,6 +1487,41 @@ mod tests {
         s
     }
 
+    /// Reads back the written XML, which must be well formed, as the file names of the
+    /// classes of each package.
+    fn read_packages(xml: &[u8]) -> Vec<(String, Vec<String>)> {
+        let mut reader = quick_xml::Reader::from_reader(xml);
+        let mut buf = Vec::new();
+        let mut packages: Vec<(String, Vec<String>)> = Vec::new();
+        let mut in_class = false;
+        loop {
+            match reader.read_event(&mut buf).expect("Malformed XML") {
+                Event::Start(e) if e.name() == b"package" || e.name() == b"class" => {
+                    let is_package = e.name() == b"package";
+                    let key: &[u8] = if is_package { b"name" } else { b"filename" };
+                    let value = e
+                        .attributes()
+                        .map(|attribute| attribute.unwrap())
+                        .find(|attribute| attribute.key == key)
+                        .map(|attribute| attribute.unescape_and_decode_value(&reader).unwrap())
+                        .unwrap();
+                    if is_package {
+                        packages.push((value, Vec::new()));
+                    } else {
+                        assert!(!in_class, "Nested class {}", value);
+                        in_class = true;
+                        packages.last_mut().unwrap().1.push(value);
+                    }
+                }
+                Event::End(e) if e.name() == b"class" => in_class = false,
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+        packages
+    }
+
     #[test]
     fn test_cobertura() {
         let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
@@ -713,7 +1535,14 @@ mod tests {
         )];
 
         let results = Box::new(results.into_iter());
-        output_cobertura(results, Some(file_path.to_str().unwrap()), true);
+        output_cobertura(
+            results,
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            DemangleConfig::name_only(),
+            &CoberturaConfig::default(),
+        )
+        .unwrap();
 
         let results = read_file(&file_path);
 
@@ -724,7 +1553,7 @@ mod tests {
         assert!(results.contains(r#"method name="cov_test::main""#));
         assert!(results.contains(r#"line number="1" hits="1">"#));
         assert!(results.contains(r#"line number="3" hits="2" branch="true""#));
-        assert!(results.contains(r#"<condition number="0" type="jump" coverage="1"/>"#));
+        assert!(results.contains(r#"<condition number="0" type="jump" coverage="100%"/>"#));
 
         assert!(results.contains(r#"lines-covered="6""#));
         assert!(results.contains(r#"lines-valid="8""#));
@@ -748,7 +1577,14 @@ mod tests {
         )];
 
         let results = Box::new(results.into_iter());
-        output_cobertura(results, Some(file_path.to_str().unwrap()), true);
+        output_cobertura(
+            results,
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            DemangleConfig::name_only(),
+            &CoberturaConfig::default(),
+        )
+        .unwrap();
 
         let results = read_file(&file_path);
 
@@ -756,8 +1592,18 @@ mod tests {
 
         assert!(results.contains(r#"package name="src/main.rs""#));
         assert!(results.contains(r#"class name="main" filename="src/main.rs""#));
-        assert!(results.contains(r#"method name="cov_test::main""#));
-        assert!(results.contains(r#"method name="cov_test::test_fn""#));
+        // The instances of main and the closure of test_fn are merged into their functions.
+        assert_eq!(
+            results.matches(r#"method name="cov_test::main""#).count(),
+            1
+        );
+        assert_eq!(
+            results
+                .matches(r#"method name="cov_test::test_fn""#)
+                .count(),
+            1
+        );
+        assert!(!results.contains("closure"));
 
         assert!(results.contains(r#"lines-covered="7""#));
         assert!(results.contains(r#"lines-valid="8""#));
@@ -768,6 +1614,67 @@ mod tests {
         assert!(results.contains(r#"branch-rate="0.5""#));
     }
 
+    #[test]
+    fn test_cobertura_merged_instances() {
+        let mut result = coverage_result(Result::Test);
+        // Only the instances of main, one of them executed, covering line 1.
+        result.functions.retain(|name, _| name.contains("4main"));
+        for function in result.functions.values_mut() {
+            function.end = Some(1);
+        }
+        let results = |result: &CovResult| {
+            Box::new(
+                vec![(
+                    PathBuf::from("src/main.rs"),
+                    PathBuf::from("src/main.rs"),
+                    result.clone(),
+                )]
+                .into_iter(),
+            )
+        };
+        let config = CoberturaConfig {
+            empty_function_lines: true,
+            ..Default::default()
+        };
+
+        let method_lines = |class: &Class| {
+            class.methods[0]
+                .lines
+                .iter()
+                .map(|line| match line {
+                    Line::Plain { number, hits } | Line::Branch { number, hits, .. } => {
+                        (*number, *hits)
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let coverage = get_coverage(results(&result), DemangleConfig::name_only(), &config);
+        let class = &coverage.packages[0].classes[0];
+        assert_eq!(class.methods.len(), 1);
+        assert_eq!(class.methods[0].name, "cov_test::main");
+        // The merged method has the hits of its lines.
+        assert_eq!(method_lines(class), vec![(1, 2)]);
+        assert_eq!((class.functions_covered, class.functions_valid), (1, 1));
+
+        // Without lines, the start line is only reported as covered if an instance was
+        // executed.
+        result.lines.remove(&1);
+        let coverage = get_coverage(results(&result), DemangleConfig::name_only(), &config);
+        let class = &coverage.packages[0].classes[0];
+        assert_eq!(class.methods.len(), 1);
+        assert_eq!(method_lines(class), vec![(1, 1)]);
+        assert_eq!((class.functions_covered, class.functions_valid), (1, 1));
+
+        for function in result.functions.values_mut() {
+            function.executed = false;
+        }
+        let coverage = get_coverage(results(&result), DemangleConfig::name_only(), &config);
+        let class = &coverage.packages[0].classes[0];
+        assert_eq!(method_lines(class), vec![(1, 0)]);
+        assert_eq!((class.functions_covered, class.functions_valid), (0, 1));
+    }
+
     #[test]
     fn test_cobertura_multiple_files() {
         let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
@@ -788,7 +1695,14 @@ mod tests {
         ];
 
         let results = Box::new(results.into_iter());
-        output_cobertura(results, Some(file_path.to_str().unwrap()), true);
+        output_cobertura(
+            results,
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            DemangleConfig::name_only(),
+            &CoberturaConfig::default(),
+        )
+        .unwrap();
 
         let results = read_file(&file_path);
 
@@ -807,4 +1721,1806 @@ mod tests {
         assert!(results.contains(r#"branches-valid="6""#));
         assert!(results.contains(r#"branch-rate="0.3333333333333333""#));
     }
+
+    #[test]
+    fn test_cobertura_min_method_lines() {
+        let mut result = coverage_result(Result::Main);
+        result.functions.insert(
+            "_ZN8cov_test6getter17h0123456789abcdefE".to_string(),
+            Function {
+                start: 9,
+                executed: true,
+                end: None,
+            },
+        );
+
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            result,
+        )];
+
+        let results = Box::new(results.into_iter());
+        let config = CoberturaConfig {
+            min_method_lines: 2,
+            ..Default::default()
+        };
+        let coverage = get_coverage(results, DemangleConfig::name_only(), &config);
+
+        let class = &coverage.packages[0].classes[0];
+        assert_eq!(class.methods.len(), 1);
+        assert_eq!(class.methods[0].name, "cov_test::main");
+        // The single line of the getter is now reported at the class level.
+        let class_lines: Vec<u32> = class.lines.iter().map(Line::number).collect();
+        assert_eq!(class_lines, vec![9]);
+
+        let stats = coverage.get_stats();
+        assert_eq!(stats.lines_covered, 6.0);
+        assert_eq!(stats.lines_valid, 8.0);
+    }
+
+    #[test]
+    fn test_cobertura_generated_files() {
+        let results = vec![
+            (
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/main.rs"),
+                coverage_result(Result::Main),
+            ),
+            (
+                PathBuf::from("target/debug/build/foo/out/bindings.rs"),
+                PathBuf::from("target/debug/build/foo/out/bindings.rs"),
+                coverage_result(Result::Test),
+            ),
+            (
+                PathBuf::from("target/debug/build/bar/out/consts.rs"),
+                PathBuf::from("target/debug/build/bar/out/consts.rs"),
+                coverage_result(Result::Main),
+            ),
+        ];
+
+        let config = CoberturaConfig {
+            generated_prefixes: vec!["target".to_string()],
+            ..Default::default()
+        };
+        let coverage = get_coverage(
+            Box::new(results.clone().into_iter()),
+            DemangleConfig::name_only(),
+            &config,
+        );
+
+        assert_eq!(coverage.packages.len(), 2);
+        assert_eq!(coverage.packages[0].name, "src/main.rs");
+        let generated = &coverage.packages[1];
+        assert_eq!(generated.name, "generated");
+        let file_names: Vec<&str> = generated
+            .classes
+            .iter()
+            .map(|c| c.file_name.as_str())
+            .collect();
+        assert_eq!(
+            file_names,
+            vec![
+                "target/debug/build/bar/out/consts.rs",
+                "target/debug/build/foo/out/bindings.rs"
+            ]
+        );
+
+        // The classes of the package are written one after the other.
+        let mut bytes = Vec::new();
+        write_cobertura(
+            Box::new(results.into_iter()),
+            &mut bytes,
+            DemangleConfig::name_only(),
+            &config,
+        )
+        .unwrap();
+        assert_eq!(
+            read_packages(&bytes),
+            vec![
+                ("src/main.rs".to_string(), vec!["src/main.rs".to_string()]),
+                (
+                    "generated".to_string(),
+                    vec![
+                        "target/debug/build/bar/out/consts.rs".to_string(),
+                        "target/debug/build/foo/out/bindings.rs".to_string()
+                    ]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cobertura_class_counts() {
+        let results = vec![
+            (
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/main.rs"),
+                coverage_result(Result::Main),
+            ),
+            (
+                PathBuf::from("src/test.rs"),
+                PathBuf::from("src/test.rs"),
+                coverage_result(Result::Test),
+            ),
+        ];
+        let bytes = cobertura_to_bytes(
+            Box::new(results.into_iter()),
+            DemangleConfig::name_only(),
+            &CoberturaConfig {
+                package_per_directory: true,
+                ..Default::default()
+            },
+        );
+        let results = String::from_utf8(bytes).unwrap();
+
+        let tag = |prefix: &str| {
+            results
+                .lines()
+                .map(str::trim_start)
+                .find(|line| line.starts_with(prefix))
+                .unwrap()
+                .to_string()
+        };
+        let class = tag(r#"<class name="main""#);
+        assert!(
+            class.contains(
+                r#"lines-covered="6" lines-valid="8" line-rate="0.75" branches-covered="1" branches-valid="4" branch-rate="0.25""#
+            ),
+            "{}",
+            class
+        );
+        // The package counts are the sums of the counts of its classes.
+        let package = tag(r#"<package name="src""#);
+        let coverage = tag("<coverage ");
+        for count in &[
+            "lines-covered",
+            "lines-valid",
+            "branches-covered",
+            "branches-valid",
+        ] {
+            let value = |tag: &str| {
+                let start = tag.find(&format!(" {}=", count)).unwrap();
+                tag[start..].split('"').nth(1).unwrap().to_string()
+            };
+            assert_eq!(value(&package), value(&coverage), "{}", count);
+        }
+    }
+
+    #[test]
+    fn test_cobertura_function_stats() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_cobertura_function_stats.xml");
+
+        let results = vec![
+            (
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/main.rs"),
+                coverage_result(Result::Main),
+            ),
+            (
+                PathBuf::from("src/test.rs"),
+                PathBuf::from("src/test.rs"),
+                coverage_result(Result::Test),
+            ),
+        ];
+
+        output_cobertura(
+            Box::new(results.clone().into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            DemangleConfig::name_only(),
+            &CoberturaConfig::default(),
+        )
+        .unwrap();
+        let output = read_file(&file_path);
+        assert!(!output.contains("functions-covered"));
+        assert!(!output.contains("functions-valid"));
+        assert!(!output.contains("function-rate"));
+
+        let config = CoberturaConfig {
+            function_stats: true,
+            ..Default::default()
+        };
+        output_cobertura(
+            Box::new(results.into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            DemangleConfig::name_only(),
+            &config,
+        )
+        .unwrap();
+        let output = read_file(&file_path);
+        // The instances of main are one function, executed by one of them, and the closure
+        // of test_fn is merged into it.
+        assert!(output.contains(r#"functions-covered="3""#));
+        assert!(output.contains(r#"functions-valid="3""#));
+        assert!(output.contains(r#"function-rate="1""#));
+    }
+
+    #[test]
+    fn test_cobertura_dangling_filenames() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        std::fs::create_dir(tmp_dir.path().join("src")).unwrap();
+        File::create(tmp_dir.path().join("src").join("main.rs")).unwrap();
+
+        let results = vec![
+            (
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/main.rs"),
+                coverage_result(Result::Main),
+            ),
+            (
+                PathBuf::from("/build/src/main.rs"),
+                PathBuf::from("build/src/main.rs"),
+                coverage_result(Result::Main),
+            ),
+        ];
+
+        let results = Box::new(results.into_iter());
+        let coverage = get_coverage(
+            results,
+            DemangleConfig::name_only(),
+            &CoberturaConfig::default(),
+        );
+
+        assert_eq!(
+            find_dangling_filenames(&coverage, tmp_dir.path()),
+            vec!["build/src/main.rs"]
+        );
+    }
+
+    #[test]
+    fn test_cobertura_collapse_branches() {
+        let mut result = coverage_result(Result::Main);
+        result.branches.insert(6, vec![true, false, false, true]);
+
+        let conditions = |collapse| {
+            let results = vec![(
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/main.rs"),
+                result.clone(),
+            )];
+            let config = CoberturaConfig {
+                collapse_branches: collapse,
+                ..Default::default()
+            };
+            let coverage = get_coverage(
+                Box::new(results.into_iter()),
+                DemangleConfig::name_only(),
+                &config,
+            );
+            let lines = coverage.packages[0].classes[0].get_lines();
+            match &lines[&6] {
+                Line::Branch { conditions, .. } => {
+                    conditions.iter().map(|c| c.coverage).collect::<Vec<f64>>()
+                }
+                Line::Plain { .. } => panic!("Line 6 should have branches"),
+            }
+        };
+
+        assert_eq!(conditions(None), vec![1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(conditions(Some(BranchCollapse::Any)), vec![1.0]);
+        assert_eq!(conditions(Some(BranchCollapse::All)), vec![0.0]);
+
+        // The merged conditions tell how many of their branches were taken.
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            result,
+        )];
+        let config = CoberturaConfig {
+            collapse_branches: Some(BranchCollapse::Any),
+            ..Default::default()
+        };
+        let bytes = cobertura_to_bytes(
+            Box::new(results.into_iter()),
+            DemangleConfig::name_only(),
+            &config,
+        );
+        let results = String::from_utf8(bytes).unwrap();
+        assert!(results.contains(r#"<condition number="0" type="jump" coverage="50% (2/4)"/>"#));
+        assert!(results.contains(r#"<condition number="0" type="jump" coverage="0% (0/2)"/>"#));
+    }
+
+    #[test]
+    fn test_cobertura_single_condition() {
+        let mut result = coverage_result(Result::Main);
+        result.branches.insert(8, vec![true]);
+
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            result,
+        )];
+        let bytes = cobertura_to_bytes(
+            Box::new(results.into_iter()),
+            DemangleConfig::name_only(),
+            &CoberturaConfig::default(),
+        );
+        let results = String::from_utf8(bytes).unwrap();
+
+        // Line 8 has a single condition, it isn't a branch.
+        assert!(results.contains(r#"<line number="8" hits="1">"#));
+        assert!(results.contains(r#"branches-covered="1""#));
+        assert!(results.contains(r#"branches-valid="4""#));
+    }
+
+    #[test]
+    fn test_cobertura_method_complexity() {
+        let mut result = coverage_result(Result::Main);
+        result.branches.remove(&5);
+
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            result,
+        )];
+        let coverage = get_coverage(
+            Box::new(results.into_iter()),
+            DemangleConfig::name_only(),
+            &CoberturaConfig::default(),
+        );
+
+        let class = &coverage.packages[0].classes[0];
+        // One branch with two arms.
+        assert_eq!(class.methods[0].get_stats().complexity, 3.0);
+        assert_eq!(class.get_stats().complexity, 3.0);
+        assert_eq!(coverage.get_stats().complexity, 3.0);
+    }
+
+    #[test]
+    fn test_cobertura_complexity_without_methods() {
+        let mut result = coverage_result(Result::Main);
+        result.functions.clear();
+
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            result,
+        )];
+        let bytes = cobertura_to_bytes(
+            Box::new(results.into_iter()),
+            DemangleConfig::name_only(),
+            &CoberturaConfig::default(),
+        );
+        let results = String::from_utf8(bytes).unwrap();
+
+        // Without methods, the complexity is the number of branches.
+        let coverage_tag = results
+            .lines()
+            .find(|line| line.starts_with("<coverage "))
+            .unwrap();
+        assert!(
+            coverage_tag.contains(r#"branches-valid="4""#),
+            "{}",
+            coverage_tag
+        );
+        assert!(
+            coverage_tag.contains(r#"complexity="4""#),
+            "{}",
+            coverage_tag
+        );
+    }
+
+    #[test]
+    fn test_cobertura_from_grcov_json() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let json_path = tmp_dir.path().join("coverage.json");
+
+        let results = vec![
+            (
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/main.rs"),
+                coverage_result(Result::Main),
+            ),
+            (
+                PathBuf::from("src/test.rs"),
+                PathBuf::from("src/test.rs"),
+                coverage_result(Result::Test),
+            ),
+        ];
+        crate::output_json(
+            Box::new(results.clone().into_iter()),
+            Some(json_path.to_str().unwrap()),
+            LineEnding::Lf,
+        )
+        .unwrap();
+
+        let parsed = crate::parse_grcov_json(File::open(&json_path).unwrap()).unwrap();
+        let parsed: Vec<_> = parsed
+            .into_iter()
+            .map(|(name, result)| (PathBuf::from(&name), PathBuf::from(&name), result))
+            .collect();
+        assert_eq!(parsed, results);
+
+        // The reports only differ by their timestamp.
+        let get_report = |results: Vec<(PathBuf, PathBuf, CovResult)>| {
+            let file_path = tmp_dir.path().join("cobertura.xml");
+            output_cobertura(
+                Box::new(results.into_iter()),
+                Some(file_path.to_str().unwrap()),
+                LineEnding::Lf,
+                DemangleConfig::name_only(),
+                &CoberturaConfig::default(),
+            )
+            .unwrap();
+            let report = read_file(&file_path);
+            let start = report.find("timestamp=").unwrap();
+            let end = start + report[start..].find(' ').unwrap();
+            format!("{}{}", &report[..start], &report[end..])
+        };
+        assert_eq!(get_report(parsed), get_report(results));
+    }
+
+    #[test]
+    fn test_cobertura_branch_rate_decision() {
+        let mut result = coverage_result(Result::Main);
+        // Line 3 is half taken, line 5 is fully taken.
+        result.branches.insert(3, vec![true, false]);
+        result.branches.insert(5, vec![true, true]);
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            result,
+        )];
+
+        let branch_rate = |decision_branch_rate| {
+            let config = CoberturaConfig {
+                decision_branch_rate,
+                ..Default::default()
+            };
+            let coverage = get_coverage(
+                Box::new(results.clone().into_iter()),
+                DemangleConfig::name_only(),
+                &config,
+            );
+            coverage.get_stats().branch_rate()
+        };
+
+        assert_eq!(branch_rate(false), 0.75);
+        assert_eq!(branch_rate(true), 0.5);
+    }
+
+    #[test]
+    fn test_cobertura_class_names() {
+        let results = vec![
+            (
+                PathBuf::from("src/foo/mod.rs"),
+                PathBuf::from("src/foo/mod.rs"),
+                coverage_result(Result::Main),
+            ),
+            (
+                PathBuf::from("src/bar/mod.rs"),
+                PathBuf::from("src/bar/mod.rs"),
+                coverage_result(Result::Test),
+            ),
+        ];
+
+        let class_names = |class_names| {
+            let config = CoberturaConfig {
+                class_names,
+                ..Default::default()
+            };
+            let coverage = get_coverage(
+                Box::new(results.clone().into_iter()),
+                DemangleConfig::name_only(),
+                &config,
+            );
+            coverage
+                .packages
+                .iter()
+                .flat_map(|p| p.classes.iter().map(|c| c.name.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(class_names(None), vec!["mod", "mod"]);
+        let coverage = get_coverage(
+            Box::new(results.clone().into_iter()),
+            DemangleConfig::name_only(),
+            &CoberturaConfig::default(),
+        );
+        assert_eq!(find_duplicate_class_names(&coverage), vec!["mod"]);
+        assert_eq!(
+            class_names(Some(ClassNames::Path)),
+            vec!["src/bar/mod.rs", "src/foo/mod.rs"]
+        );
+        assert_eq!(
+            class_names(Some(ClassNames::Dotted)),
+            vec!["src.bar.mod", "src.foo.mod"]
+        );
+        let config = CoberturaConfig {
+            class_names: Some(ClassNames::Dotted),
+            ..Default::default()
+        };
+        let coverage = get_coverage(
+            Box::new(results.into_iter()),
+            DemangleConfig::name_only(),
+            &config,
+        );
+        assert!(find_duplicate_class_names(&coverage).is_empty());
+    }
+
+    #[test]
+    fn test_cobertura_switch_conditions() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_cobertura_switch_conditions.xml");
+
+        let mut result = coverage_result(Result::Main);
+        result.branches.insert(3, vec![true, false, true]);
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            result,
+        )];
+
+        output_cobertura(
+            Box::new(results.into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            DemangleConfig::name_only(),
+            &CoberturaConfig::default(),
+        )
+        .unwrap();
+
+        let results = read_file(&file_path);
+        assert!(results.contains(r#"<condition number="2" type="switch" coverage="100%"/>"#));
+        assert!(results.contains(r#"<condition number="1" type="jump" coverage="0%"/>"#));
+        assert!(!results.contains(r#"<condition number="2" type="jump""#));
+        assert!(results.contains(r#"condition-coverage="66% (2/3)""#));
+        assert!(results.contains(r#"condition-coverage="0% (0/2)""#));
+    }
+
+    #[test]
+    fn test_cobertura_branch_hits() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_cobertura_branch_hits.xml");
+
+        let mut result = coverage_result(Result::Main);
+        result.lines.insert(3, 4);
+        result.branches.insert(3, vec![true, true]);
+        result.branch_hits.insert(3, vec![2, 2]);
+        // Only the branches of line 3.
+        result.branches.retain(|line, _| *line == 3);
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            result,
+        )];
+
+        let coverage = get_coverage(
+            Box::new(results.clone().into_iter()),
+            DemangleConfig::name_only(),
+            &CoberturaConfig::default(),
+        );
+        let class = &coverage.packages[0].classes[0];
+        match &class.get_lines()[&3] {
+            Line::Branch { conditions, .. } => assert_eq!(
+                conditions.iter().map(|c| c.coverage).collect::<Vec<f64>>(),
+                vec![0.5, 0.5]
+            ),
+            Line::Plain { .. } => panic!("Line 3 should have branches"),
+        }
+        // The branch rate still counts the branches which were taken.
+        let stats = class.get_stats();
+        assert_eq!(stats.branches_covered, stats.branches_valid);
+
+        output_cobertura(
+            Box::new(results.into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            DemangleConfig::name_only(),
+            &CoberturaConfig::default(),
+        )
+        .unwrap();
+        let results = read_file(&file_path);
+        assert!(results.contains(r#"<condition number="0" type="jump" coverage="50%"/>"#));
+        assert!(results.contains(r#"condition-coverage="100% (2/2)""#));
+    }
+
+    #[test]
+    fn test_cobertura_package_per_directory() {
+        let results = vec![
+            (
+                PathBuf::from("src/foo/a.rs"),
+                PathBuf::from("src/foo/a.rs"),
+                coverage_result(Result::Main),
+            ),
+            (
+                PathBuf::from("src/bar.rs"),
+                PathBuf::from("src/bar.rs"),
+                coverage_result(Result::Test),
+            ),
+            (
+                PathBuf::from("src/foo/b.rs"),
+                PathBuf::from("src/foo/b.rs"),
+                coverage_result(Result::Test),
+            ),
+        ];
+
+        let config = CoberturaConfig {
+            package_per_directory: true,
+            ..Default::default()
+        };
+        let coverage = get_coverage(
+            Box::new(results.clone().into_iter()),
+            DemangleConfig::name_only(),
+            &config,
+        );
+
+        let packages: Vec<(&str, Vec<&str>)> = coverage
+            .packages
+            .iter()
+            .map(|p| {
+                (
+                    p.name.as_str(),
+                    p.classes.iter().map(|c| c.file_name.as_str()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            packages,
+            vec![
+                ("src", vec!["src/bar.rs"]),
+                ("src.foo", vec!["src/foo/a.rs", "src/foo/b.rs"]),
+            ]
+        );
+
+        // The stats of a package add up the ones of its classes.
+        let foo = &coverage.packages[1];
+        let stats = foo.get_stats();
+        let class_stats: Vec<CoverageStats> = foo.classes.iter().map(|c| c.get_stats()).collect();
+        assert_eq!(
+            stats.lines_valid,
+            class_stats[0].lines_valid + class_stats[1].lines_valid
+        );
+        assert_eq!(
+            stats.lines_covered,
+            class_stats[0].lines_covered + class_stats[1].lines_covered
+        );
+
+        let mut bytes = Vec::new();
+        write_cobertura(
+            Box::new(results.into_iter()),
+            &mut bytes,
+            DemangleConfig::name_only(),
+            &config,
+        )
+        .unwrap();
+        assert_eq!(
+            read_packages(&bytes),
+            vec![
+                ("src".to_string(), vec!["src/bar.rs".to_string()]),
+                (
+                    "src.foo".to_string(),
+                    vec!["src/foo/a.rs".to_string(), "src/foo/b.rs".to_string()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cobertura_sources() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_cobertura_sources.xml");
+
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            coverage_result(Result::Main),
+        )];
+        let config = CoberturaConfig {
+            sources: vec!["/build/src".to_string(), "/build/gen".to_string()],
+            ..Default::default()
+        };
+        output_cobertura(
+            Box::new(results.into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            DemangleConfig::name_only(),
+            &config,
+        )
+        .unwrap();
+
+        let results = read_file(&file_path);
+        assert!(results.contains("<source>/build/src</source>"));
+        assert!(results.contains("<source>/build/gen</source>"));
+        assert!(!results.contains("<source>.</source>"));
+    }
+
+    #[test]
+    fn test_cobertura_doctype() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_cobertura_doctype.xml");
+
+        let output = |doctype: Option<Doctype>| {
+            let results = vec![(
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/main.rs"),
+                coverage_result(Result::Main),
+            )];
+            let config = CoberturaConfig {
+                doctype,
+                ..Default::default()
+            };
+            output_cobertura(
+                Box::new(results.into_iter()),
+                Some(file_path.to_str().unwrap()),
+                LineEnding::Lf,
+                DemangleConfig::name_only(),
+                &config,
+            )
+            .unwrap();
+            read_file(&file_path)
+        };
+
+        assert!(output(None).contains(
+            "<!DOCTYPE coverage SYSTEM 'http://cobertura.sourceforge.net/xml/coverage-04.dtd'>"
+        ));
+        assert!(
+            output(Some(Doctype::System("dtd/coverage-04.dtd".to_string())))
+                .contains("<!DOCTYPE coverage SYSTEM 'dtd/coverage-04.dtd'>")
+        );
+        let results = output(Some(Doctype::Omit));
+        assert!(!results.contains("<!DOCTYPE"));
+        assert!(results.contains("<coverage "));
+    }
+
+    #[test]
+    fn test_cobertura_doctype_parse() {
+        assert_eq!("none".parse(), Ok(Doctype::Omit));
+        assert_eq!(
+            "dtd/coverage-04.dtd".parse(),
+            Ok(Doctype::System("dtd/coverage-04.dtd".to_string()))
+        );
+        assert!("a'.dtd".parse::<Doctype>().is_err());
+        assert!("a.dtd'> <!ENTITY x 'y".parse::<Doctype>().is_err());
+
+        // Nor can the DOCTYPE be broken when the configuration is built by hand.
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            coverage_result(Result::Main),
+        )];
+        let config = CoberturaConfig {
+            doctype: Some(Doctype::System("a.dtd'>".to_string())),
+            ..Default::default()
+        };
+        let written = write_cobertura(
+            Box::new(results.into_iter()),
+            Vec::new(),
+            DemangleConfig::name_only(),
+            &config,
+        );
+        assert_eq!(written.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cobertura_non_utf8_paths() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let results = vec![
+            (
+                PathBuf::from(OsStr::from_bytes(b"src/a\xff.rs")),
+                PathBuf::from(OsStr::from_bytes(b"src/a\xff.rs")),
+                coverage_result(Result::Main),
+            ),
+            (
+                PathBuf::from(OsStr::from_bytes(b"src/b\xfe.rs")),
+                PathBuf::from(OsStr::from_bytes(b"src/b\xfe.rs")),
+                coverage_result(Result::Test),
+            ),
+        ];
+        let coverage = get_coverage(
+            Box::new(results.into_iter()),
+            DemangleConfig::name_only(),
+            &CoberturaConfig::default(),
+        );
+
+        let classes: Vec<(&str, &str)> = coverage
+            .packages
+            .iter()
+            .flat_map(|p| &p.classes)
+            .map(|c| (c.name.as_str(), c.file_name.as_str()))
+            .collect();
+        assert_eq!(
+            classes,
+            vec![
+                ("a\u{fffd}", "src/a\u{fffd}.rs"),
+                ("b\u{fffd}", "src/b\u{fffd}.rs"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cobertura_crlf_line_endings() {
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            coverage_result(Result::Main),
+        )];
+        let mut bytes = Vec::new();
+        write_cobertura(
+            Box::new(results.into_iter()),
+            crate::CrlfWriter::new(&mut bytes),
+            DemangleConfig::name_only(),
+            &CoberturaConfig::default(),
+        )
+        .unwrap();
+        let results = String::from_utf8(bytes).unwrap();
+
+        assert!(results.contains("\r\n    <sources>"), "{}", results);
+        assert_eq!(
+            results.matches('\n').count(),
+            results.matches("\r\n").count()
+        );
+    }
+
+    #[test]
+    fn test_cobertura_indent() {
+        let output = |indent: Option<Indent>| {
+            let results = vec![(
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/main.rs"),
+                coverage_result(Result::Main),
+            )];
+            let config = CoberturaConfig {
+                indent,
+                ..Default::default()
+            };
+            let bytes = cobertura_to_bytes(
+                Box::new(results.into_iter()),
+                DemangleConfig::name_only(),
+                &config,
+            );
+            String::from_utf8(bytes).unwrap()
+        };
+
+        assert!(output(None).contains("\n    <sources>"));
+        assert!(output(Some(Indent::Spaces(2))).contains("\n  <sources>"));
+        assert!(output(Some(Indent::Tab)).contains("\n\t<sources>"));
+
+        let results = output(Some(Indent::Minified));
+        assert!(!results.contains('\n'), "{}", results);
+        assert!(results.contains("><sources><source>"), "{}", results);
+    }
+
+    #[test]
+    fn test_cobertura_rate_precision() {
+        assert_eq!(
+            format_rate(5.0 / 6.0, None, Rounding::HalfEven),
+            "0.8333333333333334"
+        );
+        assert_eq!(
+            format_rate(5.0 / 6.0, Some(4), Rounding::HalfEven),
+            "0.8333"
+        );
+        assert_eq!(format_rate(0.5, Some(4), Rounding::HalfEven), "0.5");
+        assert_eq!(format_rate(1.0, Some(4), Rounding::HalfEven), "1");
+        assert_eq!(format_rate(0.0, Some(4), Rounding::HalfEven), "0");
+        assert_eq!(format_rate(0.99999, Some(2), Rounding::HalfEven), "1");
+        assert_eq!(format_rate(0.25, Some(0), Rounding::HalfEven), "0");
+        assert_eq!(format_rate(0.865, Some(2), Rounding::HalfUp), "0.87");
+        assert_eq!(format_rate(0.865, Some(2), Rounding::HalfEven), "0.86");
+        assert_eq!(format_rate(0.869, Some(2), Rounding::Truncate), "0.86");
+
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_cobertura_rate_precision.xml");
+
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            coverage_result(Result::Test),
+        )];
+        let config = CoberturaConfig {
+            rate_precision: Some(2),
+            ..Default::default()
+        };
+        output_cobertura(
+            Box::new(results.into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            DemangleConfig::name_only(),
+            &config,
+        )
+        .unwrap();
+
+        let results = read_file(&file_path);
+        assert!(results.contains(r#"line-rate="0.88""#));
+        assert!(results.contains(r#"branch-rate="0.5""#));
+    }
+
+    #[test]
+    fn test_cobertura_timestamp() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_cobertura_timestamp.xml");
+
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            coverage_result(Result::Main),
+        )];
+        let config = CoberturaConfig {
+            timestamp: Some(1_600_000_000),
+            ..Default::default()
+        };
+        output_cobertura(
+            Box::new(results.into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            DemangleConfig::name_only(),
+            &config,
+        )
+        .unwrap();
+
+        assert!(read_file(&file_path).contains(r#"timestamp="1600000000""#));
+    }
+
+    #[test]
+    fn test_cobertura_special_characters() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_cobertura_special_characters.xml");
+
+        let mut result = coverage_result(Result::Main);
+        result.functions.clear();
+        for (name, start) in &[("foo::<Bar & Baz>", 1), ("bar\u{1}\"baz'", 3)] {
+            result.functions.insert(
+                name.to_string(),
+                Function {
+                    start: *start,
+                    executed: true,
+                    end: None,
+                },
+            );
+        }
+        let results = vec![(
+            PathBuf::from("src/<main> & co.rs"),
+            PathBuf::from("src/<main> & co.rs"),
+            result,
+        )];
+        output_cobertura(
+            Box::new(results.into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            DemangleConfig::name_only(),
+            &CoberturaConfig::default(),
+        )
+        .unwrap();
+
+        let results = read_file(&file_path);
+        let mut reader = quick_xml::Reader::from_str(&results);
+        let mut buf = Vec::new();
+        let mut names = Vec::new();
+        loop {
+            match reader.read_event(&mut buf).expect("Malformed XML") {
+                Event::Start(e) if e.name() == b"method" => {
+                    for attribute in e.attributes() {
+                        let attribute = attribute.unwrap();
+                        if attribute.key == b"name" {
+                            names.push(attribute.unescape_and_decode_value(&reader).unwrap());
+                        }
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+        assert_eq!(names, vec!["foo::<Bar & Baz>", "bar\u{fffd}\"baz'"]);
+        assert!(results.contains(r#"filename="src/&lt;main&gt; &amp; co.rs""#));
+    }
+
+    #[test]
+    fn test_cobertura_to_bytes() {
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            coverage_result(Result::Main),
+        )];
+        let bytes = cobertura_to_bytes(
+            Box::new(results.into_iter()),
+            DemangleConfig::name_only(),
+            &CoberturaConfig::default(),
+        );
+
+        let mut reader = quick_xml::Reader::from_reader(&bytes[..]);
+        let mut buf = Vec::new();
+        let mut classes = 0;
+        loop {
+            match reader.read_event(&mut buf).expect("Malformed XML") {
+                Event::Start(e) if e.name() == b"class" => classes += 1,
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+        assert_eq!(classes, 1);
+        assert!(String::from_utf8(bytes)
+            .unwrap()
+            .trim_end()
+            .ends_with("</coverage>"));
+    }
+
+    #[test]
+    fn test_cobertura_complete_demangling() {
+        let mut result = coverage_result(Result::Main);
+        result.functions.clear();
+        result.functions.insert(
+            "_Z3addIiET_S0_S0_".to_string(),
+            Function {
+                start: 1,
+                executed: true,
+                end: None,
+            },
+        );
+
+        let report = |demangle| {
+            let results = vec![(
+                PathBuf::from("src/main.cpp"),
+                PathBuf::from("src/main.cpp"),
+                result.clone(),
+            )];
+            let bytes = cobertura_to_bytes(
+                Box::new(results.into_iter()),
+                demangle,
+                &CoberturaConfig::default(),
+            );
+            String::from_utf8(bytes).unwrap()
+        };
+
+        assert!(report(DemangleConfig::name_only())
+            .contains(r#"<method name="add&lt;int&gt;" signature="(int, int)""#));
+        assert!(report(DemangleConfig::complete())
+            .contains(r#"<method name="int add&lt;int&gt;(int, int)""#));
+        assert!(report(DemangleConfig::default())
+            .contains(r#"<method name="_Z3addIiET_S0_S0_" signature="""#));
+    }
+
+    #[test]
+    fn test_cobertura_demangling_failures() {
+        let mut result = coverage_result(Result::Main);
+        result.functions.clear();
+        for (name, start) in &[
+            ("_ZN3foo3barEv", 1),
+            // Unmangled, e.g. a C function.
+            ("main", 3),
+            // Looks mangled, but is truncated.
+            ("_ZN3foo", 5),
+        ] {
+            result.functions.insert(
+                name.to_string(),
+                Function {
+                    start: *start,
+                    executed: true,
+                    end: None,
+                },
+            );
+        }
+        let coverage = |demangle| {
+            let results = vec![(
+                PathBuf::from("src/main.cpp"),
+                PathBuf::from("src/main.cpp"),
+                result.clone(),
+            )];
+            get_coverage(
+                Box::new(results.into_iter()),
+                demangle,
+                &CoberturaConfig::default(),
+            )
+        };
+
+        let demangled = coverage(DemangleConfig::name_only());
+        assert_eq!(demangled.demangling_failures, 1);
+        let mut names: Vec<&str> = demangled.packages[0].classes[0]
+            .methods
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect();
+        names.sort_unstable();
+        assert_eq!(names, vec!["_ZN3foo", "foo::bar", "main"]);
+
+        // Nothing is demangled, so nothing fails.
+        assert_eq!(coverage(DemangleConfig::default()).demangling_failures, 0);
+    }
+
+    #[test]
+    fn test_cobertura_demangle_language() {
+        let mut result = coverage_result(Result::Main);
+        result.functions.clear();
+        result.functions.insert(
+            "_ZN3foo3barEv".to_string(),
+            Function {
+                start: 1,
+                executed: true,
+                end: None,
+            },
+        );
+
+        let report = |demangle_language| {
+            let results = vec![(
+                PathBuf::from("src/main.cpp"),
+                PathBuf::from("src/main.cpp"),
+                result.clone(),
+            )];
+            let config = CoberturaConfig {
+                demangle_language,
+                ..Default::default()
+            };
+            let bytes = cobertura_to_bytes(
+                Box::new(results.into_iter()),
+                DemangleConfig::name_only(),
+                &config,
+            );
+            String::from_utf8(bytes).unwrap()
+        };
+
+        // The Itanium mangling is recognized without a hint.
+        assert!(report(Language::Unknown).contains(r#"<method name="foo::bar" signature="()""#));
+        assert!(report(Language::Cpp).contains(r#"<method name="foo::bar" signature="()""#));
+        // The name is left mangled when it doesn't match the language.
+        assert!(report(Language::Swift).contains(r#"<method name="_ZN3foo3barEv""#));
+    }
+
+    #[test]
+    fn test_cobertura_shared_start_lines() {
+        let mut result = coverage_result(Result::Main);
+        result.lines = (1..=7).map(|line| (line, 1)).collect();
+        result.branches.clear();
+        result.functions.clear();
+        for (name, start) in &[("a", 2), ("a::{{closure}}", 2), ("c", 5)] {
+            result.functions.insert(
+                name.to_string(),
+                Function {
+                    start: *start,
+                    executed: true,
+                    end: None,
+                },
+            );
+        }
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            result,
+        )];
+
+        let coverage = get_coverage(
+            Box::new(results.into_iter()),
+            DemangleConfig::default(),
+            &CoberturaConfig::default(),
+        );
+
+        let class = &coverage.packages[0].classes[0];
+        let methods: Vec<(&str, Vec<u32>)> = class
+            .methods
+            .iter()
+            .map(|m| (m.name.as_str(), m.lines.iter().map(Line::number).collect()))
+            .collect();
+        assert_eq!(methods, vec![("a", vec![2, 3, 4]), ("c", vec![5, 6, 7])]);
+        // The lines before the first function belong to the class.
+        assert_eq!(
+            class.lines.iter().map(Line::number).collect::<Vec<_>>(),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn test_cobertura_shared_start_lines_merged() {
+        let mut result = coverage_result(Result::Main);
+        result.lines = (1..=8).map(|line| (line, 1)).collect();
+        result.branches.clear();
+        result.functions.clear();
+        for (name, start, end, executed) in &[
+            ("a", 2, Some(4), false),
+            ("a::{{closure}}", 2, Some(7), true),
+            ("c", 7, None, true),
+        ] {
+            result.functions.insert(
+                name.to_string(),
+                Function {
+                    start: *start,
+                    executed: *executed,
+                    end: *end,
+                },
+            );
+        }
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            result,
+        )];
+
+        let coverage = get_coverage(
+            Box::new(results.into_iter()),
+            DemangleConfig::default(),
+            &CoberturaConfig::default(),
+        );
+
+        let class = &coverage.packages[0].classes[0];
+        let methods: Vec<(&str, Vec<u32>)> = class
+            .methods
+            .iter()
+            .map(|m| (m.name.as_str(), m.lines.iter().map(Line::number).collect()))
+            .collect();
+        // The lines of the closure are kept in the merged method.
+        assert_eq!(methods, vec![("a", vec![2, 3, 4, 5, 6]), ("c", vec![7, 8])]);
+        assert_eq!(
+            class.lines.iter().map(Line::number).collect::<Vec<_>>(),
+            vec![1]
+        );
+        // The merged method is counted once, executed as the closure was.
+        assert_eq!(class.functions_valid, 2);
+        assert_eq!(class.functions_covered, 2);
+    }
+
+    #[test]
+    fn test_cobertura_function_ends() {
+        let mut result = coverage_result(Result::Main);
+        result.lines = (1..=12).map(|line| (line, 1)).collect();
+        result.branches.clear();
+        result.functions.clear();
+        for (name, start, end) in &[
+            ("outer", 1, Some(10)),
+            ("outer::{{closure}}", 3, Some(5)),
+            ("after", 11, None),
+        ] {
+            result.functions.insert(
+                name.to_string(),
+                Function {
+                    start: *start,
+                    executed: true,
+                    end: *end,
+                },
+            );
+        }
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            result,
+        )];
+
+        let coverage = get_coverage(
+            Box::new(results.into_iter()),
+            DemangleConfig::default(),
+            &CoberturaConfig::default(),
+        );
+
+        // The lines of the closure only belong to it, not to the enclosing function.
+        let class = &coverage.packages[0].classes[0];
+        let methods: Vec<(&str, Vec<u32>)> = class
+            .methods
+            .iter()
+            .map(|m| (m.name.as_str(), m.lines.iter().map(Line::number).collect()))
+            .collect();
+        assert_eq!(
+            methods,
+            vec![
+                ("outer", vec![1, 2, 6, 7, 8, 9, 10]),
+                ("outer::{{closure}}", vec![3, 4, 5]),
+                ("after", vec![11, 12]),
+            ]
+        );
+        assert!(class.lines.is_empty());
+    }
+
+    #[test]
+    fn test_cobertura_absolute_filenames() {
+        let report = |sources: Vec<String>, absolute_filenames| {
+            let results = vec![
+                (
+                    PathBuf::from("/root/src/main.rs"),
+                    PathBuf::from("src/main.rs"),
+                    coverage_result(Result::Main),
+                ),
+                (
+                    PathBuf::from("/usr/include/stdio.h"),
+                    PathBuf::from("/usr/include/stdio.h"),
+                    coverage_result(Result::Main),
+                ),
+            ];
+            let config = CoberturaConfig {
+                sources,
+                absolute_filenames,
+                ..Default::default()
+            };
+            let bytes = cobertura_to_bytes(
+                Box::new(results.into_iter()),
+                DemangleConfig::default(),
+                &config,
+            );
+            String::from_utf8(bytes).unwrap()
+        };
+
+        let absolute = report(vec!["/root".to_string()], true);
+        assert!(absolute.contains("<source>/root</source>"));
+        assert!(absolute.contains(r#"filename="/root/src/main.rs""#));
+        assert!(absolute.contains(r#"filename="/usr/include/stdio.h""#));
+
+        // Without a root, the file names stay relative.
+        assert!(report(Vec::new(), true).contains(r#"filename="src/main.rs""#));
+        assert!(report(vec!["/root".to_string()], false).contains(r#"filename="src/main.rs""#));
+
+        assert_eq!(
+            absolute_file_name("C:\\build\\", "src\\main.rs"),
+            "C:/build/src/main.rs"
+        );
+    }
+
+    #[test]
+    fn test_cobertura_dead_function_lines() {
+        let mut result = coverage_result(Result::Main);
+        // A function which was never executed, without lines.
+        result.functions.insert(
+            "dead".to_string(),
+            Function {
+                start: 12,
+                executed: false,
+                end: Some(14),
+            },
+        );
+
+        let report = |dead_function_lines| {
+            let results = vec![(
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/main.rs"),
+                result.clone(),
+            )];
+            let config = CoberturaConfig {
+                dead_function_lines,
+                ..Default::default()
+            };
+            let coverage = get_coverage(
+                Box::new(results.into_iter()),
+                DemangleConfig::default(),
+                &config,
+            );
+            let class = &coverage.packages[0].classes[0];
+            let method = class.methods.iter().find(|m| m.name == "dead").unwrap();
+            (
+                method.lines.iter().map(Line::number).collect::<Vec<u32>>(),
+                class.get_stats().lines_valid,
+            )
+        };
+
+        assert_eq!(report(false), (vec![], 8.0));
+        assert_eq!(report(true), (vec![12], 9.0));
+    }
+
+    #[test]
+    fn test_cobertura_empty_function_lines() {
+        let mut result = coverage_result(Result::Main);
+        // Functions without lines, e.g. inlined away.
+        for (name, start, executed) in &[("dead", 12, false), ("inlined", 15, true)] {
+            result.functions.insert(
+                name.to_string(),
+                Function {
+                    start: *start,
+                    executed: *executed,
+                    end: Some(start + 1),
+                },
+            );
+        }
+        let functions = result.functions.len();
+
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            result,
+        )];
+        let config = CoberturaConfig {
+            empty_function_lines: true,
+            ..Default::default()
+        };
+        let coverage = get_coverage(
+            Box::new(results.into_iter()),
+            DemangleConfig::default(),
+            &config,
+        );
+
+        let class = &coverage.packages[0].classes[0];
+        assert_eq!(class.methods.len(), functions);
+        let lines = |name: &str| {
+            let method = class.methods.iter().find(|m| m.name == name).unwrap();
+            method
+                .lines
+                .iter()
+                .map(|line| match line {
+                    Line::Plain { number, hits } | Line::Branch { number, hits, .. } => {
+                        (*number, *hits)
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(lines("dead"), vec![(12, 0)]);
+        assert_eq!(lines("inlined"), vec![(15, 1)]);
+    }
+
+    #[test]
+    fn test_cobertura_package_names() {
+        let package_names = |package_per_directory, package_names| {
+            let results = vec![
+                (
+                    PathBuf::from("src/foo/a.rs"),
+                    PathBuf::from("src/foo/a.rs"),
+                    coverage_result(Result::Main),
+                ),
+                (
+                    PathBuf::from("src/foo/b.rs"),
+                    PathBuf::from("src/foo/b.rs"),
+                    coverage_result(Result::Test),
+                ),
+            ];
+            let config = CoberturaConfig {
+                package_per_directory,
+                package_names,
+                ..Default::default()
+            };
+            // The names are read back from the XML, which must stay well formed when the
+            // files share a package.
+            let mut bytes = Vec::new();
+            write_cobertura(
+                Box::new(results.into_iter()),
+                &mut bytes,
+                DemangleConfig::name_only(),
+                &config,
+            )
+            .unwrap();
+            let packages = read_packages(&bytes);
+            assert_eq!(
+                packages
+                    .iter()
+                    .map(|(_, classes)| classes.len())
+                    .sum::<usize>(),
+                2
+            );
+            packages
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            package_names(false, None),
+            vec!["src/foo/a.rs", "src/foo/b.rs"]
+        );
+        assert_eq!(package_names(true, None), vec!["src.foo"]);
+
+        let file_path = Some(PackageNames::FilePath);
+        assert_eq!(
+            package_names(false, file_path.clone()),
+            vec!["src/foo/a.rs", "src/foo/b.rs"]
+        );
+        assert_eq!(package_names(true, file_path), vec!["src/foo"]);
+
+        let dotted_dir = Some(PackageNames::DottedDir);
+        assert_eq!(
+            package_names(false, dotted_dir.clone()),
+            vec!["src.foo", "src.foo"]
+        );
+        assert_eq!(package_names(true, dotted_dir), vec!["src.foo"]);
+
+        let template = Some(PackageNames::CustomTemplate(
+            "com.example.{1}:{dir}:{path}".to_string(),
+        ));
+        assert_eq!(
+            package_names(false, template.clone()),
+            vec![
+                "com.example.foo:src/foo:src/foo/a.rs",
+                "com.example.foo:src/foo:src/foo/b.rs"
+            ]
+        );
+        assert_eq!(
+            package_names(true, template),
+            vec!["com.example.foo:src/foo:src/foo"]
+        );
+    }
+
+    #[test]
+    fn test_cobertura_excluded_methods() {
+        let mut result = coverage_result(Result::Main);
+        result.functions.insert(
+            "_ZN4core3ptr13drop_in_place17h1d3f3e3c1c0f0a0bE".to_string(),
+            Function {
+                start: 8,
+                executed: true,
+                end: Some(10),
+            },
+        );
+
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            result,
+        )];
+        let config = CoberturaConfig {
+            excluded_methods: Some(Regex::new("drop_in_place").unwrap()),
+            ..Default::default()
+        };
+        let coverage = get_coverage(
+            Box::new(results.into_iter()),
+            DemangleConfig::name_only(),
+            &config,
+        );
+
+        let class = &coverage.packages[0].classes[0];
+        assert_eq!(
+            class
+                .methods
+                .iter()
+                .map(|m| &m.name[..])
+                .collect::<Vec<_>>(),
+            vec!["cov_test::main"]
+        );
+        assert_eq!(class.functions_valid, 1);
+        let class_lines: Vec<u32> = class.lines.iter().map(Line::number).collect();
+        assert_eq!(class_lines, vec![8, 9]);
+        assert_eq!(class.get_lines().len(), 8);
+    }
+
+    #[test]
+    fn test_cobertura_max_hits() {
+        let mut result = coverage_result(Result::Main);
+        result.lines.insert(1, 5_000_000_000);
+        result.lines.insert(3, 0);
+
+        let report = |max_hits| {
+            let results = vec![(
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/main.rs"),
+                result.clone(),
+            )];
+            let config = CoberturaConfig {
+                max_hits,
+                ..Default::default()
+            };
+            String::from_utf8(cobertura_to_bytes(
+                Box::new(results.into_iter()),
+                DemangleConfig::name_only(),
+                &config,
+            ))
+            .unwrap()
+        };
+
+        assert!(report(None).contains(r#"<line number="1" hits="5000000000">"#));
+        let results = report(Some(i32::MAX as u64));
+        assert!(results.contains(r#"<line number="1" hits="2147483647">"#));
+        assert!(results.contains(r#"<line number="3" hits="0""#));
+        assert!(report(Some(0)).contains(r#"<line number="1" hits="1">"#));
+    }
+
+    #[test]
+    fn test_cobertura_omit_methods() {
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            coverage_result(Result::Test),
+        )];
+        let config = CoberturaConfig {
+            omit_methods: true,
+            ..Default::default()
+        };
+        let bytes = cobertura_to_bytes(
+            Box::new(results.into_iter()),
+            DemangleConfig::name_only(),
+            &config,
+        );
+
+        let results = String::from_utf8(bytes).unwrap();
+        assert!(!results.contains("<method "));
+        assert!(results.contains(r#"lines-valid="8""#));
+        assert!(results.contains(r#"line-rate="0.875""#));
+        assert!(results.contains(r#"branch-rate="0.5""#));
+        assert!(results.contains(r#"<line number="6" hits="2">"#));
+    }
+
+    #[test]
+    fn test_cobertura_excluded_lines() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        std::fs::write(
+            tmp_dir.path().join("main.rs"),
+            "fn main() {\n    unreachable!(); // grcov-excl-line\n}\n",
+        )
+        .unwrap();
+
+        let mut result_map: crate::CovResultMap = FxHashMap::default();
+        let mut result = coverage_result(Result::Main);
+        result.lines = [(1, 1), (2, 0), (3, 1)].iter().cloned().collect();
+        result.branches.clear();
+        result_map.insert("main.rs".to_string(), result);
+        let results = crate::rewrite_paths(
+            result_map,
+            None,
+            Some(tmp_dir.path()),
+            None,
+            false,
+            &[],
+            &[],
+            None,
+            false,
+            None,
+            crate::FileFilter::new(
+                Some(regex::Regex::new("grcov-excl-line").unwrap()),
+                None,
+                None,
+                None,
+                None,
+                None,
+                false,
+            ),
+        );
+        let bytes = cobertura_to_bytes(
+            results,
+            DemangleConfig::default(),
+            &CoberturaConfig::default(),
+        );
+
+        let results = String::from_utf8(bytes).unwrap();
+        assert!(results.contains(r#"lines-valid="2""#));
+        assert!(results.contains(r#"line-rate="1""#));
+        assert!(!results.contains(r#"<line number="2""#));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cobertura_prefix_dir() {
+        let mut result_map: crate::CovResultMap = FxHashMap::default();
+        result_map.insert(
+            "/home/runner/work/proj/src/lib.rs".to_string(),
+            coverage_result(Result::Main),
+        );
+        result_map.insert(
+            "/usr/include/stdio.h".to_string(),
+            coverage_result(Result::Main),
+        );
+        let results = crate::rewrite_paths(
+            result_map,
+            None,
+            None,
+            Some(Path::new("/home/runner/work/proj")),
+            false,
+            &[],
+            &[],
+            None,
+            false,
+            None,
+            Default::default(),
+        );
+        let bytes = cobertura_to_bytes(
+            results,
+            DemangleConfig::default(),
+            &CoberturaConfig::default(),
+        );
+
+        let results = String::from_utf8(bytes).unwrap();
+        assert!(results.contains(r#"<class name="lib" filename="src/lib.rs""#));
+        // The paths outside of the prefix are kept as they are.
+        assert!(results.contains(r#"<class name="stdio" filename="/usr/include/stdio.h""#));
+    }
+
+    #[test]
+    fn test_cobertura_merge_same_path() {
+        let mut first = coverage_result(Result::Main);
+        first.lines = [(1, 1), (2, 0), (3, 0)].iter().cloned().collect();
+        first.branches = [(2, vec![true, false])].iter().cloned().collect();
+        let mut second = first.clone();
+        second.lines = [(1, 2), (2, 0), (3, 4)].iter().cloned().collect();
+        second.branches = [(2, vec![false, true])].iter().cloned().collect();
+        let results = vec![
+            (
+                PathBuf::from("/a/src/main.rs"),
+                PathBuf::from("src/main.rs"),
+                first,
+            ),
+            (
+                PathBuf::from("/b/src/main.rs"),
+                PathBuf::from("src/main.rs"),
+                second,
+            ),
+        ];
+
+        let coverage = get_coverage(
+            Box::new(results.into_iter()),
+            DemangleConfig::default(),
+            &CoberturaConfig::default(),
+        );
+
+        assert_eq!(coverage.packages.len(), 1);
+        assert_eq!(coverage.packages[0].classes.len(), 1);
+        let lines = coverage.packages[0].classes[0].get_lines();
+        let hits = |number| match lines[&number] {
+            Line::Plain { hits, .. } | Line::Branch { hits, .. } => hits,
+        };
+        assert_eq!((hits(1), hits(2), hits(3)), (3, 0, 4));
+        let stats = coverage.get_stats();
+        assert_eq!(stats.lines_valid, 3.0);
+        assert_eq!(stats.lines_covered, 2.0);
+        assert_eq!(stats.branches_covered, 2.0);
+    }
+
+    #[test]
+    fn test_cobertura_version_and_complexity() {
+        let report = |config: &CoberturaConfig| {
+            let results = vec![(
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/main.rs"),
+                coverage_result(Result::Main),
+            )];
+            String::from_utf8(cobertura_to_bytes(
+                Box::new(results.into_iter()),
+                DemangleConfig::name_only(),
+                config,
+            ))
+            .unwrap()
+        };
+
+        let results = report(&CoberturaConfig::default());
+        assert!(results.contains(r#"version="1.9""#));
+        assert!(results.contains("complexity="));
+
+        let results = report(&CoberturaConfig {
+            version: Some("2.1.1".to_string()),
+            omit_complexity: true,
+            ..Default::default()
+        });
+        assert!(results.contains(r#"version="2.1.1""#));
+        assert!(!results.contains("version=\"1.9\""));
+        assert!(!results.contains("complexity="));
+    }
+
+    #[test]
+    fn test_cobertura_method_signature() {
+        assert_eq!(
+            method_signature("_Z3fooic", Language::Unknown),
+            "(int, char)"
+        );
+        assert_eq!(
+            method_signature("_ZNK12_GLOBAL__N_13Foo3barEPKc", Language::Unknown),
+            "(char const*) const"
+        );
+        assert_eq!(
+            method_signature("_ZN8cov_test4main17h7eb435a3fb3e6f20E", Language::Unknown),
+            ""
+        );
+        assert_eq!(method_signature("main", Language::Unknown), "");
+    }
+
+    #[test]
+    fn test_cobertura_ordering() {
+        let mut result = coverage_result(Result::Main);
+        result.functions.clear();
+        for (name, start) in &[("c", 9), ("b", 1), ("a", 5)] {
+            result.functions.insert(
+                name.to_string(),
+                Function {
+                    start: *start,
+                    executed: true,
+                    end: None,
+                },
+            );
+        }
+        let results = vec![
+            (
+                PathBuf::from("src/b.rs"),
+                PathBuf::from("src/b.rs"),
+                coverage_result(Result::Main),
+            ),
+            (PathBuf::from("src/a.rs"), PathBuf::from("src/a.rs"), result),
+        ];
+
+        let coverage = get_coverage(
+            Box::new(results.into_iter()),
+            DemangleConfig::default(),
+            &CoberturaConfig::default(),
+        );
+
+        let packages: Vec<&str> = coverage.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(packages, vec!["src/a.rs", "src/b.rs"]);
+        let methods: Vec<&str> = coverage.packages[0].classes[0]
+            .methods
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect();
+        assert_eq!(methods, vec!["b", "a", "c"]);
+    }
 }
@@ -43,6 +43,7 @@ impl CDFileStats {
             name,
             stats: CDStats::new(total, covered),
             coverage: lines,
+            excluded: false,
         }
     }
 
@@ -62,14 +63,18 @@ impl CDFileStats {
     }
 
     pub fn to_json(&self) -> serde_json::Value {
-        json!({
+        let mut json = json!({
             "name": self.name,
             "linesTotal": self.stats.total,
             "linesCovered": self.stats.covered,
             "linesMissed": self.stats.missed,
             "coveragePercent": self.stats.percent,
             "coverage": self.coverage,
-        })
+        });
+        if self.excluded {
+            json["excluded"] = json!(true);
+        }
+        json
     }
 }
 
@@ -84,7 +89,7 @@ impl CDDirStats {
     }
 
     pub fn set_stats(&mut self) {
-        for file in self.files.iter() {
+        for file in self.files.iter().filter(|file| !file.excluded) {
             self.stats.add(&file.stats);
         }
         for dir in self.dirs.iter() {
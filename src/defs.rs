@@ -2,23 +2,53 @@ use crossbeam::channel::{Receiver, Sender};
 use rustc_hash::FxHashMap;
 use serde::ser::{Serialize, Serializer};
 use std::cell::RefCell;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{Display, Formatter};
+use std::io;
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::Mutex;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Function {
     pub start: u32,
     pub executed: bool,
+    /// The last line of the function, when the format gives it. Otherwise, a function is
+    /// assumed to end where the next one starts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<u32>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct CovResult {
     pub lines: BTreeMap<u32, u64>,
     pub branches: BTreeMap<u32, Vec<bool>>,
+    /// The number of times each branch of `branches` was taken, when the input carries the
+    /// counts (e.g. lcov or gcov). It's empty otherwise.
+    pub branch_hits: BTreeMap<u32, Vec<u64>>,
     pub functions: FunctionMap,
+    /// The names of the tests which hit the file, when the input carries them (e.g. the
+    /// `TN:` records of lcov).
+    pub tests: BTreeSet<String>,
+    /// The file was excluded by the filters, it's only kept to be reported as such.
+    pub excluded: bool,
+}
+
+#[cfg(test)]
+impl CovResult {
+    /// Returns a result with the given hit counts of the lines and nothing else.
+    pub fn with_lines(lines: &[(u32, u64)]) -> Self {
+        CovResult {
+            lines: lines.iter().cloned().collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Tells whether the conditions of a line make it a branch. A single condition, e.g. an
+/// unconditional jump recorded by the compiler, doesn't: the reports leave such lines out.
+pub fn is_branch(taken: &[bool]) -> bool {
+    taken.len() > 1
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -27,6 +57,9 @@ pub enum ItemFormat {
     Profraw,
     Info,
     JacocoXml,
+    CoberturaXml,
+    GcovJson,
+    GrcovJson,
 }
 
 #[derive(Debug)]
@@ -59,6 +92,7 @@ pub type JobSender = Sender<Option<WorkItem>>;
 pub type CovResultMap = FxHashMap<String, CovResult>;
 pub type SyncCovResultMap = Mutex<CovResultMap>;
 pub type CovResultIter = Box<dyn Iterator<Item = (PathBuf, PathBuf, CovResult)>>;
+pub type OutputWriter<'a> = Box<dyn FnOnce(CovResultIter) -> io::Result<()> + Send + 'a>;
 
 #[derive(Debug, Default)]
 pub struct CDStats {
@@ -73,6 +107,8 @@ pub struct CDFileStats {
     pub name: String,
     pub stats: CDStats,
     pub coverage: Vec<i64>,
+    /// Excluded files are listed, but don't count in the stats of their directories.
+    pub excluded: bool,
 }
 
 #[derive(Debug, Default)]
@@ -0,0 +1,44 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use crate::parser::ParserError;
+
+/// Errors returned by the library functions. More kinds of errors may be added, so matching
+/// on them needs a wildcard arm.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Reading or writing a file failed.
+    Io(io::Error),
+    /// A coverage file couldn't be parsed: its name and the error.
+    Parse(String, ParserError),
+    /// The given options or inputs are not supported.
+    Config(String),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref err) => write!(f, "IO error: {}", err),
+            Error::Parse(ref name, ref err) => write!(f, "Error parsing file {}: {}", name, err),
+            Error::Config(ref s) => write!(f, "Configuration error: {}", s),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref err) => Some(err),
+            Error::Parse(_, ref err) => Some(err),
+            Error::Config(_) => None,
+        }
+    }
+}
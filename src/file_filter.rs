@@ -15,6 +15,7 @@ pub struct FileFilter {
     excl_br_line: Option<Regex>,
     excl_br_start: Option<Regex>,
     excl_br_stop: Option<Regex>,
+    excl_attributes: bool,
 }
 
 impl FileFilter {
@@ -25,6 +26,7 @@ impl FileFilter {
         excl_br_line: Option<Regex>,
         excl_br_start: Option<Regex>,
         excl_br_stop: Option<Regex>,
+        excl_attributes: bool,
     ) -> Self {
         Self {
             excl_line,
@@ -33,6 +35,7 @@ impl FileFilter {
             excl_br_line,
             excl_br_start,
             excl_br_stop,
+            excl_attributes,
         }
     }
 
@@ -41,6 +44,7 @@ impl FileFilter {
             && self.excl_start.is_none()
             && self.excl_br_line.is_none()
             && self.excl_br_start.is_none()
+            && !self.excl_attributes
         {
             return Vec::new();
         }
@@ -54,6 +58,7 @@ impl FileFilter {
 
         let mut ignore_br = false;
         let mut ignore = false;
+        let mut attribute_depth = 0i32;
 
         file.split('\n')
             .enumerate()
@@ -65,6 +70,24 @@ impl FileFilter {
                 // on Windows. Remove it.
                 let line = line.strip_suffix('\r').unwrap_or(line);
 
+                // Attributes like #[derive(...)] are expanded by the compiler, the coverage
+                // reported for them is meaningless. They may span multiple lines.
+                let is_attribute = self.excl_attributes && {
+                    let trimmed = line.trim_start();
+                    if attribute_depth > 0
+                        || trimmed.starts_with("#[")
+                        || trimmed.starts_with("#![")
+                    {
+                        attribute_depth += trimmed.matches('[').count() as i32
+                            - trimmed.matches(']').count() as i32;
+                        true
+                    } else {
+                        false
+                    }
+                };
+                let excl_line =
+                    is_attribute || self.excl_line.as_ref().map_or(false, |f| f.is_match(line));
+
                 // End a branch ignore region. Region endings are exclusive.
                 if ignore_br
                     && self
@@ -114,12 +137,12 @@ impl FileFilter {
                     // Single line exclusion. If single line exclusions occur
                     // inside a region they are meaningless (would be applied
                     // anway), so they are lower priority.
-                    if self.excl_line.as_ref().map_or(false, |f| f.is_match(line)) {
+                    if excl_line {
                         Some(FilterType::Both(number))
                     } else {
                         Some(FilterType::Branch(number))
                     }
-                } else if self.excl_line.as_ref().map_or(false, |f| f.is_match(line)) {
+                } else if excl_line {
                     Some(FilterType::Line(number))
                 } else {
                     None
@@ -128,3 +151,46 @@ impl FileFilter {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_excl_attributes() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let path = tmp_dir.path().join("lib.rs");
+        fs::write(
+            &path,
+            "#[derive(Debug)]
+struct Foo {
+    x: u32,
+}
+
+#[cfg_attr(
+    test,
+    derive(PartialEq)
+)]
+fn foo() -> Vec<u32> {
+    vec![1]
+}
+",
+        )
+        .unwrap();
+
+        let filter = FileFilter::new(None, None, None, None, None, None, false);
+        assert!(filter.create(&path).is_empty());
+
+        let filter = FileFilter::new(None, None, None, None, None, None, true);
+        let excluded: Vec<u32> = filter
+            .create(&path)
+            .into_iter()
+            .map(|f| match f {
+                FilterType::Line(n) => n,
+                _ => panic!("Only lines should be excluded"),
+            })
+            .collect();
+        assert_eq!(excluded, vec![1, 6, 7, 8, 9]);
+    }
+}
@@ -33,6 +33,7 @@ mod tests {
             Function {
                 start: 1,
                 executed: true,
+                end: None,
             },
         );
         functions.insert(
@@ -40,12 +41,13 @@ mod tests {
             Function {
                 start: 2,
                 executed: false,
+                end: None,
             },
         );
         let result = CovResult {
             lines: [(1, 21), (2, 7), (7, 0)].iter().cloned().collect(),
-            branches: [].iter().cloned().collect(),
             functions,
+            ..Default::default()
         };
 
         assert!(is_covered(&result));
@@ -55,8 +57,7 @@ mod tests {
     fn test_covered_no_functions() {
         let result = CovResult {
             lines: [(1, 21), (2, 7), (7, 0)].iter().cloned().collect(),
-            branches: [].iter().cloned().collect(),
-            functions: FxHashMap::default(),
+            ..Default::default()
         };
 
         assert!(is_covered(&result));
@@ -70,6 +71,7 @@ mod tests {
             Function {
                 start: 1,
                 executed: true,
+                end: None,
             },
         );
         functions.insert(
@@ -77,12 +79,12 @@ mod tests {
             Function {
                 start: 2,
                 executed: false,
+                end: None,
             },
         );
         let result = CovResult {
             lines: [(1, 0), (2, 0), (7, 0)].iter().cloned().collect(),
-            branches: [].iter().cloned().collect(),
-            functions: FxHashMap::default(),
+            ..Default::default()
         };
 
         assert!(!is_covered(&result));
@@ -96,6 +98,7 @@ mod tests {
             Function {
                 start: 1,
                 executed: true,
+                end: None,
             },
         );
         functions.insert(
@@ -103,12 +106,13 @@ mod tests {
             Function {
                 start: 2,
                 executed: true,
+                end: None,
             },
         );
         let result = CovResult {
             lines: [(1, 21), (2, 7), (7, 0)].iter().cloned().collect(),
-            branches: [].iter().cloned().collect(),
             functions,
+            ..Default::default()
         };
 
         assert!(is_covered(&result));
@@ -122,12 +126,13 @@ mod tests {
             Function {
                 start: 1,
                 executed: true,
+                end: None,
             },
         );
         let result = CovResult {
             lines: [(1, 21), (2, 7), (7, 0)].iter().cloned().collect(),
-            branches: [].iter().cloned().collect(),
             functions,
+            ..Default::default()
         };
 
         assert!(is_covered(&result));
@@ -141,6 +146,7 @@ mod tests {
             Function {
                 start: 1,
                 executed: true,
+                end: None,
             },
         );
         functions.insert(
@@ -148,12 +154,13 @@ mod tests {
             Function {
                 start: 7,
                 executed: false,
+                end: None,
             },
         );
         let result = CovResult {
             lines: [(1, 21), (2, 7), (7, 0)].iter().cloned().collect(),
-            branches: [].iter().cloned().collect(),
             functions,
+            ..Default::default()
         };
 
         assert!(!is_covered(&result));
@@ -0,0 +1,96 @@
+use std::cmp::Reverse;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use crate::defs::*;
+use crate::output::{finish_output, get_target_output_writable, LineEnding};
+
+/// Returns the `top` most executed lines, by descending hit counts, the ties being broken by
+/// path then by line number. The lines which were never executed are left out.
+fn hotspots(results: CovResultIter, top: usize) -> Vec<(PathBuf, u32, u64)> {
+    let mut lines: Vec<(PathBuf, u32, u64)> = results
+        .filter(|(_, _, result)| !result.excluded)
+        .flat_map(|(_, rel_path, result)| {
+            result
+                .lines
+                .into_iter()
+                .filter(|&(_, hits)| hits > 0)
+                .map(move |(line, hits)| (rel_path.clone(), line, hits))
+        })
+        .collect();
+    lines.sort_unstable_by(|(a_path, a_line, a_hits), (b_path, b_line, b_hits)| {
+        (Reverse(a_hits), a_path, a_line).cmp(&(Reverse(b_hits), b_path, b_line))
+    });
+    lines.truncate(top);
+    lines
+}
+
+/// Writes the most executed lines, one per line, e.g. `src/main.rs:12: 4000`, to find the
+/// hottest code paths without a profiler.
+pub fn output_hotspots(
+    results: CovResultIter,
+    output_file: Option<&str>,
+    line_ending: LineEnding,
+    top: usize,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(get_target_output_writable(output_file, line_ending)?);
+    for (path, line, hits) in hotspots(results, top) {
+        writeln!(writer, "{}:{}: {}", path.display(), line, hits)?;
+    }
+
+    finish_output(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_hotspots() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("hotspots.txt");
+
+        let results = vec![
+            (
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/main.rs"),
+                CovResult::with_lines(&[(1, 1), (2, 500), (3, 0), (7, 20)]),
+            ),
+            (
+                PathBuf::from("src/lib.rs"),
+                PathBuf::from("src/lib.rs"),
+                CovResult::with_lines(&[(4, 20), (5, 1000), (9, 20)]),
+            ),
+        ];
+        output_hotspots(
+            Box::new(results.into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            5,
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&file_path).unwrap(),
+            "src/lib.rs:5: 1000\n\
+             src/main.rs:2: 500\n\
+             src/lib.rs:4: 20\n\
+             src/lib.rs:9: 20\n\
+             src/main.rs:7: 20\n"
+        );
+    }
+
+    #[test]
+    fn test_hotspots_uncovered() {
+        let results = vec![(
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("src/main.rs"),
+            CovResult::with_lines(&[(1, 0), (2, 3)]),
+        )];
+        assert_eq!(
+            hotspots(Box::new(results.into_iter()), 10),
+            vec![(PathBuf::from("src/main.rs"), 2, 3)]
+        );
+    }
+}
@@ -5,12 +5,13 @@ use std::array;
 use std::collections::HashMap;
 use std::collections::{btree_map, BTreeMap};
 use std::fs::{self, File};
-use std::io::{BufRead, BufReader, Write};
+use std::io::{self, BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use tera::try_get_value;
 
 use crate::defs::*;
+use crate::output::{AtomicFile, OutputWrite};
 
 impl HtmlStats {
     #[inline(always)]
@@ -113,6 +114,19 @@ impl tera::Filter for Config {
     }
 }
 
+/// Writes a file of the report through a temporary file, renamed into place once complete.
+fn write_file(output_file: &Path, contents: &[u8]) -> io::Result<()> {
+    let file = AtomicFile::create(output_file)
+        .and_then(|mut file| file.write_all(contents).map(|_| file))
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Cannot write {}: {}", output_file.display(), e),
+            )
+        })?;
+    Box::new(file).finish()
+}
+
 fn create_parent(path: &Path) {
     let dest_parent = path.parent().unwrap();
     if !dest_parent.exists() && fs::create_dir_all(dest_parent).is_err() {
@@ -135,12 +149,9 @@ fn get_stats(result: &CovResult) -> HtmlStats {
     let covered_lines = result.lines.values().filter(|x| **x > 0).count();
     let total_funs = result.functions.len();
     let covered_funs = result.functions.values().filter(|f| f.executed).count();
-    let total_branches = result.branches.values().map(|v| v.len()).sum();
-    let covered_branches = result
-        .branches
-        .values()
-        .map(|v| v.iter().filter(|x| **x).count())
-        .sum();
+    let branches = || result.branches.values().filter(|v| is_branch(v));
+    let total_branches = branches().map(|v| v.len()).sum();
+    let covered_branches = branches().map(|v| v.iter().filter(|x| **x).count()).sum();
 
     HtmlStats {
         total_lines,
@@ -222,16 +233,9 @@ pub fn gen_index(
     conf: &Config,
     output: &Path,
     branch_enabled: bool,
-) {
+) -> io::Result<()> {
     let output_file = output.join("index.html");
     create_parent(&output_file);
-    let mut output_stream = match File::create(&output_file) {
-        Err(_) => {
-            eprintln!("Cannot create file {:?}", output_file);
-            return;
-        }
-        Ok(f) => f,
-    };
 
     let mut ctx = make_context();
     let empty: &[&str] = &[];
@@ -245,14 +249,13 @@ pub fn gen_index(
 
     let out = tera.render("index.html", &ctx).unwrap();
 
-    if output_stream.write_all(out.as_bytes()).is_err() {
-        eprintln!("Cannot write the file {:?}", output_file);
-        return;
-    }
+    write_file(&output_file, out.as_bytes())?;
 
     for (dir_name, dir_stats) in global.dirs.iter() {
-        gen_dir_index(tera, dir_name, dir_stats, conf, output, branch_enabled);
+        gen_dir_index(tera, dir_name, dir_stats, conf, output, branch_enabled)?;
     }
+
+    Ok(())
 }
 
 pub fn gen_dir_index(
@@ -262,17 +265,10 @@ pub fn gen_dir_index(
     conf: &Config,
     output: &Path,
     branch_enabled: bool,
-) {
+) -> io::Result<()> {
     let index = Path::new(dir_name).join("index.html");
     let output_file = output.join(&index);
     create_parent(&output_file);
-    let mut output = match File::create(&output_file) {
-        Err(_) => {
-            eprintln!("Cannot create file {:?}", output_file);
-            return;
-        }
-        Ok(f) => f,
-    };
 
     let mut ctx = make_context();
     ctx.insert("date", &conf.date);
@@ -286,9 +282,7 @@ pub fn gen_dir_index(
 
     let out = tera.render("index.html", &ctx).unwrap();
 
-    if output.write_all(out.as_bytes()).is_err() {
-        eprintln!("Cannot write the file {:?}", output_file);
-    }
+    write_file(&output_file, out.as_bytes())
 }
 
 fn gen_html(
@@ -300,15 +294,15 @@ fn gen_html(
     rel_path: &Path,
     global: Arc<Mutex<HtmlGlobalStats>>,
     branch_enabled: bool,
-) {
+) -> io::Result<()> {
     if !rel_path.is_relative() {
-        return;
+        return Ok(());
     }
 
     let f = match File::open(&path) {
         Err(_) => {
             //eprintln!("Warning: cannot open file {:?}", path);
-            return;
+            return Ok(());
         }
         Ok(f) => f,
     };
@@ -319,13 +313,6 @@ fn gen_html(
 
     let output_file = output.join(add_html_ext(rel_path));
     create_parent(&output_file);
-    let mut output = match File::create(&output_file) {
-        Err(_) => {
-            eprintln!("Cannot create file {:?}", output_file);
-            return;
-        }
-        Ok(f) => f,
-    };
     let base_url = get_base(rel_path);
     let filename = rel_path.file_name().unwrap().to_str().unwrap();
     let parent = rel_path.parent().unwrap().to_str().unwrap().to_string();
@@ -357,7 +344,14 @@ fn gen_html(
                 .map(|&v| v as i64)
                 .unwrap_or(-1);
 
-            (index, count, l.unwrap())
+            // The number of branches taken and the number of branches of the line.
+            let branches = result
+                .branches
+                .get(&(index as u32))
+                .filter(|taken| is_branch(taken))
+                .map(|taken| (taken.iter().filter(|&&t| t).count(), taken.len()));
+
+            (index, count, l.unwrap(), branches)
         })
         .collect::<Vec<_>>();
 
@@ -365,9 +359,7 @@ fn gen_html(
 
     let out = tera.render("file.html", &ctx).unwrap();
 
-    if output.write_all(out.as_bytes()).is_err() {
-        eprintln!("Cannot write the file {:?}", output_file);
-    }
+    write_file(&output_file, out.as_bytes())
 }
 
 pub fn consumer_html(
@@ -377,13 +369,15 @@ pub fn consumer_html(
     output: &Path,
     conf: Config,
     branch_enabled: bool,
-) {
+) -> io::Result<()> {
+    // The remaining files are still written after an error, the first one is returned.
+    let mut written = Ok(());
     while let Ok(job) = receiver.recv() {
         if job.is_none() {
             break;
         }
         let job = job.unwrap();
-        gen_html(
+        let file_written = gen_html(
             tera,
             &job.abs_path,
             &job.result,
@@ -393,7 +387,11 @@ pub fn consumer_html(
             global.clone(),
             branch_enabled,
         );
+        if written.is_ok() {
+            written = file_written;
+        }
     }
+    written
 }
 
 /// Different available styles to render badges with [`gen_badge`].
@@ -443,16 +441,15 @@ impl BadgeStyle {
 
 /// Generate coverage badges, typically for use in a README.md if the HTML output is hosted on a
 /// website like GitHub Pages.
-pub fn gen_badge(tera: &Tera, stats: &HtmlStats, conf: &Config, output: &Path, style: BadgeStyle) {
+pub fn gen_badge(
+    tera: &Tera,
+    stats: &HtmlStats,
+    conf: &Config,
+    output: &Path,
+    style: BadgeStyle,
+) -> io::Result<()> {
     let output_file = output.join(style.path());
     create_parent(&output_file);
-    let mut output_stream = match File::create(&output_file) {
-        Err(_) => {
-            eprintln!("Cannot create file {:?}", output_file);
-            return;
-        }
-        Ok(f) => f,
-    };
 
     let mut ctx = make_context();
     ctx.insert("current", &(stats.covered_lines * 100 / stats.total_lines));
@@ -461,9 +458,7 @@ pub fn gen_badge(tera: &Tera, stats: &HtmlStats, conf: &Config, output: &Path, s
 
     let out = tera.render(style.template_name(), &ctx).unwrap();
 
-    if output_stream.write_all(out.as_bytes()).is_err() {
-        eprintln!("Cannot write the file {:?}", output_file);
-    }
+    write_file(&output_file, out.as_bytes())
 }
 
 /// Generate a coverage.json file that can be used with shields.io/endpoint to dynamically create
@@ -479,7 +474,7 @@ pub fn gen_badge(tera: &Tera, stats: &HtmlStats, conf: &Config, output: &Path, s
 ///
 /// `<username>` and `<project>` should be replaced with a real username and project name
 /// respectively, for the URL to work.
-pub fn gen_coverage_json(stats: &HtmlStats, conf: &Config, output: &Path) {
+pub fn gen_coverage_json(stats: &HtmlStats, conf: &Config, output: &Path) -> io::Result<()> {
     #[derive(Serialize)]
     #[serde(rename_all = "camelCase")]
     struct CoverageData {
@@ -491,33 +486,22 @@ pub fn gen_coverage_json(stats: &HtmlStats, conf: &Config, output: &Path) {
 
     let output_file = output.join("coverage.json");
     create_parent(&output_file);
-    let mut output_stream = match File::create(&output_file) {
-        Err(_) => {
-            eprintln!("Cannot create file {:?}", output_file);
-            return;
-        }
-        Ok(f) => f,
-    };
 
     let coverage = stats.covered_lines * 100 / stats.total_lines;
 
-    let res = serde_json::to_writer(
-        &mut output_stream,
-        &CoverageData {
-            schema_version: 1,
-            label: "coverage",
-            message: format!("{}%", coverage),
-            color: if coverage as f64 >= conf.hi_limit {
-                "green"
-            } else if coverage as f64 >= conf.med_limit {
-                "yellow"
-            } else {
-                "red"
-            },
+    let out = serde_json::to_vec(&CoverageData {
+        schema_version: 1,
+        label: "coverage",
+        message: format!("{}%", coverage),
+        color: if coverage as f64 >= conf.hi_limit {
+            "green"
+        } else if coverage as f64 >= conf.med_limit {
+            "yellow"
+        } else {
+            "red"
         },
-    );
+    })
+    .unwrap();
 
-    if res.is_err() {
-        eprintln!("cannot write the file {:?}", output_file);
-    }
+    write_file(&output_file, &out)
 }
@@ -0,0 +1,118 @@
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::{self, BufWriter};
+
+use crate::cobertura::{get_coverage, CoberturaConfig, CoverageStats, DemangleConfig, Stats};
+use crate::defs::*;
+use crate::output::{finish_output, get_target_output_writable, LineEnding};
+
+fn stats_to_json(stats: &CoverageStats) -> Value {
+    json!({
+        "linesCovered": stats.lines_covered as u64,
+        "linesValid": stats.lines_valid as u64,
+        "lineRate": stats.line_rate(),
+        "branchesCovered": stats.branches_covered as u64,
+        "branchesValid": stats.branches_valid as u64,
+        "branchRate": stats.branch_rate(),
+        "functionsCovered": stats.functions_covered as u64,
+        "functionsValid": stats.functions_valid as u64,
+    })
+}
+
+/// Writes the line, branch and function coverage of the whole project and of each file,
+/// computed like in the Cobertura report, as a single JSON object.
+pub fn output_json_summary(
+    results: CovResultIter,
+    output_file: Option<&str>,
+    line_ending: LineEnding,
+) -> io::Result<()> {
+    let coverage = get_coverage(
+        results,
+        DemangleConfig::default(),
+        &CoberturaConfig::default(),
+    );
+
+    let files: BTreeMap<&str, Value> = coverage
+        .packages
+        .iter()
+        .flat_map(|package| &package.classes)
+        .map(|class| (class.file_name.as_str(), stats_to_json(&class.get_stats())))
+        .collect();
+    let summary = json!({
+        "totals": stats_to_json(&coverage.get_stats()),
+        "files": files,
+    });
+
+    let mut writer = BufWriter::new(get_target_output_writable(output_file, line_ending)?);
+    serde_json::to_writer(&mut writer, &summary)?;
+    finish_output(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_hash::FxHashMap;
+    use std::fs::File;
+    use std::path::PathBuf;
+
+    fn result(lines: &[(u32, u64)], branches: &[(u32, Vec<bool>)]) -> CovResult {
+        let mut functions = FxHashMap::default();
+        functions.insert(
+            "main".to_string(),
+            Function {
+                start: 1,
+                executed: true,
+                end: None,
+            },
+        );
+        CovResult {
+            lines: lines.iter().cloned().collect(),
+            branches: branches.iter().cloned().collect(),
+            functions,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_json_summary() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("summary.json");
+
+        let results = vec![
+            (
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/main.rs"),
+                result(&[(1, 1), (2, 1), (3, 0), (4, 1)], &[(2, vec![true, false])]),
+            ),
+            (
+                PathBuf::from("src/lib.rs"),
+                PathBuf::from("src/lib.rs"),
+                result(&[(1, 1), (2, 0)], &[]),
+            ),
+        ];
+        output_json_summary(
+            Box::new(results.into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+        )
+        .unwrap();
+
+        let summary: Value = serde_json::from_reader(File::open(&file_path).unwrap()).unwrap();
+        assert_eq!(
+            summary["totals"],
+            json!({
+                "linesCovered": 4,
+                "linesValid": 6,
+                "lineRate": 4.0 / 6.0,
+                "branchesCovered": 1,
+                "branchesValid": 2,
+                "branchRate": 0.5,
+                "functionsCovered": 2,
+                "functionsValid": 2,
+            })
+        );
+        assert_eq!(summary["files"]["src/main.rs"]["lineRate"], 0.75);
+        assert_eq!(summary["files"]["src/lib.rs"]["lineRate"], 0.5);
+        assert_eq!(summary["files"]["src/lib.rs"]["branchesValid"], 0);
+    }
+}
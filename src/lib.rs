@@ -23,6 +23,9 @@ extern crate zip;
 mod defs;
 pub use crate::defs::*;
 
+mod error;
+pub use crate::error::*;
+
 mod producer;
 pub use crate::producer::*;
 
@@ -53,14 +56,46 @@ pub use crate::reader::*;
 mod covdir;
 pub use crate::covdir::*;
 
+mod json_summary;
+pub use crate::json_summary::*;
+
+mod uncovered;
+pub use crate::uncovered::*;
+
+mod hotspots;
+pub use crate::hotspots::*;
+
+mod markdown;
+pub use crate::markdown::*;
+
+mod patch;
+pub use crate::patch::*;
+
+mod blame;
+pub use crate::blame::*;
+
+mod threshold;
+pub use crate::threshold::*;
+
 pub mod html;
 
 mod file_filter;
 pub use crate::file_filter::*;
 
+mod cache;
+pub use crate::cache::*;
+
+use crossbeam::channel::bounded;
+use globset::GlobMatcher;
 use log::{error, warn};
-use std::fs;
+use rustc_hash::FxHashMap;
+use serde_json::Value;
+use std::fs::{self, File};
 use std::io::{BufReader, Cursor};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
 use std::{
     collections::{btree_map, hash_map},
     path::Path,
@@ -104,6 +139,17 @@ pub fn merge_results(result: &mut CovResult, result2: CovResult) -> bool {
         };
     }
 
+    for (line_no, hits) in result2.branch_hits {
+        let v = result.branch_hits.entry(line_no).or_default();
+        for (x, y) in hits.iter().zip(v.iter_mut()) {
+            *y = y.saturating_add(*x);
+        }
+        let l = v.len();
+        if hits.len() > l {
+            v.extend(&hits[l..]);
+        }
+    }
+
     for (name, function) in result2.functions {
         match result.functions.entry(name) {
             hash_map::Entry::Occupied(f) => f.into_mut().executed |= function.executed,
@@ -113,6 +159,8 @@ pub fn merge_results(result: &mut CovResult, result2: CovResult) -> bool {
         };
     }
 
+    result.tests.extend(result2.tests);
+
     warn_overflow
 }
 
@@ -120,6 +168,7 @@ fn add_results(
     mut results: Vec<(String, CovResult)>,
     result_map: &SyncCovResultMap,
     source_dir: Option<&Path>,
+    normalize_paths: bool,
 ) {
     let mut map = result_map.lock().unwrap();
     let mut warn_overflow = false;
@@ -135,6 +184,13 @@ fn add_results(
             }
             None => result.0,
         };
+        let path = if normalize_paths {
+            // Merge results for paths like src/../src/lib.rs and src/lib.rs, even
+            // when the files can't be found on the disk.
+            collapse_path(&path).to_str().unwrap().to_string()
+        } else {
+            path
+        };
         match map.entry(path) {
             hash_map::Entry::Occupied(obj) => {
                 warn_overflow |= merge_results(obj.into_mut(), result.1);
@@ -173,17 +229,25 @@ enum GcovType {
 }
 
 macro_rules! try_parse {
-    ($v:expr, $f:expr) => {
+    ($v:expr, $f:expr, $strict:expr) => {
         match $v {
             Ok(val) => val,
             Err(err) => {
-                error!("Error parsing file {}: {}", $f, err);
+                let err = Error::Parse($f.clone(), err);
+                if $strict {
+                    return Err(err);
+                }
+                error!("{}", err);
                 continue;
             }
         }
     };
 }
 
+/// Parses the coverage files received until the channel is poisoned, and adds their results.
+///
+/// With `fail_on_parse_error`, the error of the first file which can't be parsed is returned,
+/// otherwise it's logged and the file is skipped.
 pub fn consumer(
     working_dir: &Path,
     source_dir: Option<&Path>,
@@ -192,7 +256,10 @@ pub fn consumer(
     branch_enabled: bool,
     guess_directory: bool,
     binary_path: Option<&Path>,
-) {
+    normalize_paths: bool,
+    fail_on_parse_error: bool,
+    cache_dir: Option<&Path>,
+) -> Result<(), Error> {
     let mut gcov_type = GcovType::Unknown;
 
     while let Ok(work_item) = receiver.recv() {
@@ -200,6 +267,20 @@ pub fn consumer(
             break;
         }
         let work_item = work_item.unwrap();
+
+        let key = match cache_dir {
+            Some(_) if work_item.format == ItemFormat::Gcno => {
+                cache_key(&work_item.item, branch_enabled, guess_directory)
+            }
+            _ => None,
+        };
+        if let (Some(cache_dir), Some(key)) = (cache_dir, &key) {
+            if let Some(new_results) = load_cached_results(cache_dir, key) {
+                add_results(new_results, result_map, source_dir, normalize_paths);
+                continue;
+            }
+        }
+
         let new_results = match work_item.format {
             ItemFormat::Gcno => {
                 match work_item.item {
@@ -230,7 +311,8 @@ pub fn consumer(
                                 } else {
                                     panic!("Invalid gcov extension: {}", gcov_ext);
                                 },
-                                work_item.name
+                                work_item.name,
+                                fail_on_parse_error
                             );
                             fs::remove_file(gcov_path).unwrap();
                             new_results
@@ -247,7 +329,8 @@ pub fn consumer(
                                     } else {
                                         parse_gcov(gcov_path)
                                     },
-                                    work_item.name
+                                    work_item.name,
+                                    fail_on_parse_error
                                 ));
 
                                 fs::remove_file(gcov_path).unwrap();
@@ -276,6 +359,15 @@ pub fn consumer(
                                 r
                             }
                             Err(e) => {
+                                if fail_on_parse_error {
+                                    return Err(Error::Parse(
+                                        buffers.stem,
+                                        ParserError::InvalidData(format!(
+                                            "Error in computing counters: {}",
+                                            e
+                                        )),
+                                    ));
+                                }
                                 // Just print the error, don't panic and continue
                                 error!("Error in computing counters: {}", e);
                                 Vec::new()
@@ -310,7 +402,8 @@ pub fn consumer(
                             for lcov in lcovs {
                                 new_results.append(&mut try_parse!(
                                     parse_lcov(lcov, branch_enabled),
-                                    work_item.name
+                                    work_item.name,
+                                    fail_on_parse_error
                                 ));
                             }
 
@@ -329,20 +422,296 @@ pub fn consumer(
             ItemFormat::Info | ItemFormat::JacocoXml => {
                 if let ItemType::Content(content) = work_item.item {
                     if work_item.format == ItemFormat::Info {
-                        try_parse!(parse_lcov(content, branch_enabled), work_item.name)
+                        try_parse!(
+                            parse_lcov(content, branch_enabled),
+                            work_item.name,
+                            fail_on_parse_error
+                        )
                     } else {
                         let buffer = BufReader::new(Cursor::new(content));
-                        try_parse!(parse_jacoco_xml_report(buffer), work_item.name)
+                        try_parse!(
+                            parse_jacoco_xml_report(buffer),
+                            work_item.name,
+                            fail_on_parse_error
+                        )
                     }
                 } else {
                     error!("Invalid content type");
                     continue;
                 }
             }
+            ItemFormat::CoberturaXml => {
+                if let ItemType::Content(content) = work_item.item {
+                    let buffer = BufReader::new(Cursor::new(content));
+                    try_parse!(
+                        parse_cobertura_xml_report(buffer),
+                        work_item.name,
+                        fail_on_parse_error
+                    )
+                } else {
+                    error!("Invalid content type");
+                    continue;
+                }
+            }
+            ItemFormat::GcovJson => {
+                if let ItemType::Content(content) = work_item.item {
+                    try_parse!(
+                        parse_gcov_gz_content(Cursor::new(content)),
+                        work_item.name,
+                        fail_on_parse_error
+                    )
+                } else {
+                    error!("Invalid content type");
+                    continue;
+                }
+            }
+            ItemFormat::GrcovJson => {
+                if let ItemType::Content(content) = work_item.item {
+                    try_parse!(
+                        parse_grcov_json(Cursor::new(content)),
+                        work_item.name,
+                        fail_on_parse_error
+                    )
+                } else {
+                    error!("Invalid content type");
+                    continue;
+                }
+            }
         };
 
-        add_results(new_results, result_map, source_dir);
+        if let (Some(cache_dir), Some(key)) = (cache_dir, &key) {
+            store_cached_results(cache_dir, key, &new_results);
+        }
+        add_results(new_results, result_map, source_dir, normalize_paths);
+    }
+
+    Ok(())
+}
+
+/// The options of the parsing of the coverage files, as given on the command line.
+pub struct ParseOptions {
+    /// The directory of the source files, the paths of the results are made relative to it.
+    pub source_dir: Option<PathBuf>,
+    /// The prefix removed from the absolute paths, defaults to the source directory.
+    pub prefix_dir: Option<PathBuf>,
+    /// The compiled binary, for the llvm profiles.
+    pub binary_path: Option<PathBuf>,
+    /// A JSON file mapping the paths of the results to the paths of the source files.
+    pub path_mapping_file: Option<PathBuf>,
+    /// Leaves out the files which can't be found on the disk.
+    pub ignore_not_existing: bool,
+    /// Leaves out the files matching these globs.
+    pub ignore_dirs: Vec<String>,
+    /// Keeps only the files matching these globs.
+    pub keep_dirs: Vec<String>,
+    /// Keeps only the covered files when `Some(true)`, the uncovered ones when `Some(false)`.
+    pub filter: Option<bool>,
+    /// Keeps the files left out by the options above, marked as excluded.
+    pub keep_excluded: bool,
+    /// Adds the source files matching this glob, relative to the source directory, which have
+    /// no coverage data, with their executable lines as uncovered.
+    pub untested_files: Option<GlobMatcher>,
+    /// The lines excluded from the results.
+    pub file_filter: FileFilter,
+    /// Collects the branch coverage too.
+    pub branch_enabled: bool,
+    /// Guesses the directory of the source files when the paths of the results aren't found.
+    pub guess_directory: bool,
+    /// Collapses the `.` and `..` components of the paths, to merge the results of the same file.
+    pub normalize_paths: bool,
+    /// Fails if any coverage file can't be parsed, instead of skipping it.
+    pub fail_on_parse_error: bool,
+    /// The coverage comes only from a llvm build, which speeds up the parsing.
+    pub is_llvm: bool,
+    /// The maximum number of coverage files to read.
+    pub max_files: usize,
+    /// The number of threads parsing the coverage files.
+    pub num_threads: usize,
+    /// The directory caching the results of the gcno files, for the unchanged ones not to be
+    /// parsed again on the next run.
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions {
+            source_dir: None,
+            prefix_dir: None,
+            binary_path: None,
+            path_mapping_file: None,
+            ignore_not_existing: false,
+            ignore_dirs: Vec::new(),
+            keep_dirs: Vec::new(),
+            filter: None,
+            keep_excluded: false,
+            untested_files: None,
+            file_filter: FileFilter::default(),
+            branch_enabled: false,
+            guess_directory: false,
+            normalize_paths: false,
+            fail_on_parse_error: false,
+            is_llvm: false,
+            max_files: 1_000_000,
+            num_threads: 1.max(num_cpus::get() - 1),
+            cache_dir: None,
+        }
+    }
+}
+
+/// Parses the coverage files found under the given paths, like the command line does, and
+/// returns the results of each source file: its absolute path, its path relative to the
+/// source directory and its coverage. The results can then be given to the `output_*`
+/// functions, or be iterated for a custom report.
+pub fn parse_results(
+    paths: &[String],
+    options: ParseOptions,
+) -> Result<Vec<(PathBuf, PathBuf, CovResult)>, Error> {
+    let source_root = match &options.source_dir {
+        Some(source_dir) => Some(canonicalize_path(source_dir)?),
+        None => None,
+    };
+    let prefix_dir = options.prefix_dir.or_else(|| source_root.clone());
+    if let Some(cache_dir) = &options.cache_dir {
+        fs::create_dir_all(cache_dir)?;
+    }
+
+    let tmp_dir = tempfile::tempdir()?;
+    let tmp_path = tmp_dir.path().to_owned();
+
+    let num_threads = options.num_threads.max(1);
+    let result_map: Arc<SyncCovResultMap> = Arc::new(Mutex::new(
+        FxHashMap::with_capacity_and_hasher(20_000, Default::default()),
+    ));
+    let (sender, receiver) = bounded(2 * num_threads);
+
+    let producer = {
+        let sender: JobSender = sender.clone();
+        let tmp_path = tmp_path.clone();
+        let paths = paths.to_vec();
+        let ignore_orphan_gcno = options.filter == Some(true);
+        let is_llvm = options.is_llvm;
+        let max_files = options.max_files;
+
+        thread::Builder::new()
+            .name(String::from("Producer"))
+            .spawn(move || {
+                producer(
+                    &tmp_path,
+                    &paths,
+                    &sender,
+                    ignore_orphan_gcno,
+                    is_llvm,
+                    max_files,
+                )
+            })?
+    };
+
+    let mut parsers = Vec::new();
+
+    for i in 0..num_threads {
+        let receiver = receiver.clone();
+        let result_map = Arc::clone(&result_map);
+        let working_dir = tmp_path.join(format!("{}", i));
+        let source_root = source_root.clone();
+        let binary_path = options.binary_path.clone();
+        let branch_enabled = options.branch_enabled;
+        let guess_directory = options.guess_directory;
+        let normalize_paths = options.normalize_paths;
+        let fail_on_parse_error = options.fail_on_parse_error;
+        let cache_dir = options.cache_dir.clone();
+
+        let t = thread::Builder::new()
+            .name(format!("Consumer {}", i))
+            .spawn(move || {
+                fs::create_dir(&working_dir).expect("Failed to create working directory");
+                let consumed = panic::catch_unwind(AssertUnwindSafe(|| {
+                    consumer(
+                        &working_dir,
+                        source_root.as_deref(),
+                        &result_map,
+                        receiver.clone(),
+                        branch_enabled,
+                        guess_directory,
+                        binary_path.as_deref(),
+                        normalize_paths,
+                        fail_on_parse_error,
+                        cache_dir.as_deref(),
+                    )
+                }));
+                // Keep taking the work items, up to the end, for the producer not to wait
+                // forever for the consumers.
+                if !matches!(consumed, Ok(Ok(()))) {
+                    while let Ok(Some(_)) = receiver.recv() {}
+                }
+                consumed
+            })?;
+
+        parsers.push(t);
+    }
+
+    let producer_path_mapping_buf = producer.join();
+
+    // Poison the receiver, now that the producer is finished.
+    for _ in 0..num_threads {
+        sender.send(None).unwrap();
+    }
+
+    // The first parse error is returned, the panics were already logged.
+    let mut consumed = true;
+    let mut parse_error = None;
+    for parser in parsers {
+        match parser.join() {
+            Ok(Ok(Ok(()))) => {}
+            Ok(Ok(Err(e))) => parse_error = parse_error.or(Some(e)),
+            _ => consumed = false,
+        }
+    }
+    if let Some(e) = parse_error {
+        return Err(e);
     }
+
+    let producer_path_mapping_buf = match producer_path_mapping_buf {
+        Ok(Err(e)) => return Err(e),
+        Ok(Ok(buf)) if consumed => buf,
+        _ => {
+            return Err(Error::Config(String::from(
+                "The coverage files couldn't be parsed, see the logged errors",
+            )))
+        }
+    };
+
+    let path_mapping: Option<Value> = match &options.path_mapping_file {
+        Some(path_mapping_file) => Some(
+            serde_json::from_reader(File::open(path_mapping_file)?)
+                .map_err(|e| Error::Config(format!("Invalid path mapping file: {}", e)))?,
+        ),
+        None => match producer_path_mapping_buf {
+            Some(buf) => Some(
+                serde_json::from_slice(&buf)
+                    .map_err(|e| Error::Config(format!("Invalid path mapping: {}", e)))?,
+            ),
+            None => None,
+        },
+    };
+
+    let result_map = Arc::try_unwrap(result_map).unwrap().into_inner().unwrap();
+
+    let ignore_dirs: Vec<&str> = options.ignore_dirs.iter().map(String::as_str).collect();
+    let keep_dirs: Vec<&str> = options.keep_dirs.iter().map(String::as_str).collect();
+    Ok(rewrite_paths(
+        result_map,
+        path_mapping,
+        source_root.as_deref(),
+        prefix_dir.as_deref(),
+        options.ignore_not_existing,
+        &ignore_dirs,
+        &keep_dirs,
+        options.filter,
+        options.keep_excluded,
+        options.untested_files.as_ref(),
+        options.file_filter,
+    )
+    .collect())
 }
 
 #[cfg(test)]
@@ -361,6 +730,7 @@ mod tests {
             Function {
                 start: 1,
                 executed: false,
+                end: None,
             },
         );
         functions1.insert(
@@ -368,6 +738,7 @@ mod tests {
             Function {
                 start: 2,
                 executed: false,
+                end: None,
             },
         );
         let mut result = CovResult {
@@ -380,7 +751,9 @@ mod tests {
             .iter()
             .cloned()
             .collect(),
+            branch_hits: [(1, vec![0, 0]), (2, vec![0, 3])].iter().cloned().collect(),
             functions: functions1,
+            ..Default::default()
         };
         let mut functions2: FunctionMap = FxHashMap::default();
         functions2.insert(
@@ -388,6 +761,7 @@ mod tests {
             Function {
                 start: 1,
                 executed: false,
+                end: None,
             },
         );
         functions2.insert(
@@ -395,6 +769,7 @@ mod tests {
             Function {
                 start: 2,
                 executed: true,
+                end: None,
             },
         );
         let result2 = CovResult {
@@ -410,7 +785,9 @@ mod tests {
             .iter()
             .cloned()
             .collect(),
+            branch_hits: [(2, vec![0, 4, 1]), (3, vec![5])].iter().cloned().collect(),
             functions: functions2,
+            ..Default::default()
         };
 
         merge_results(&mut result, result2);
@@ -433,6 +810,13 @@ mod tests {
             .cloned()
             .collect()
         );
+        assert_eq!(
+            result.branch_hits,
+            [(1, vec![0, 0]), (2, vec![0, 7, 1]), (3, vec![5])]
+                .iter()
+                .cloned()
+                .collect()
+        );
         assert!(result.functions.contains_key("f1"));
         assert!(result.functions.contains_key("f2"));
         let mut func = result.functions.get("f1").unwrap();
@@ -457,6 +841,7 @@ mod tests {
             results,
             &result_map,
             Some(Path::new("./test/relative_path")),
+            false,
         );
         let result_map = Arc::try_unwrap(result_map).unwrap().into_inner().unwrap();
 
@@ -487,9 +872,221 @@ mod tests {
         let result_map: Arc<SyncCovResultMap> = Arc::new(Mutex::new(
             FxHashMap::with_capacity_and_hasher(3, Default::default()),
         ));
-        add_results(results, &result_map, None);
+        add_results(results, &result_map, None, false);
         let result_map = Arc::try_unwrap(result_map).unwrap().into_inner().unwrap();
 
         assert!(result_map.len() == 3);
     }
+
+    #[test]
+    fn test_normalize_paths() {
+        let results = vec![
+            (
+                "src/../src/lib.rs".to_string(),
+                CovResult {
+                    lines: [(1, 1), (2, 0)].iter().cloned().collect(),
+                    ..Default::default()
+                },
+            ),
+            (
+                "src/lib.rs".to_string(),
+                CovResult {
+                    lines: [(2, 3)].iter().cloned().collect(),
+                    ..Default::default()
+                },
+            ),
+        ];
+        let result_map: Arc<SyncCovResultMap> = Arc::new(Mutex::new(
+            FxHashMap::with_capacity_and_hasher(1, Default::default()),
+        ));
+        add_results(results.clone(), &result_map, None, false);
+        assert_eq!(result_map.lock().unwrap().len(), 2);
+
+        let result_map: Arc<SyncCovResultMap> = Arc::new(Mutex::new(
+            FxHashMap::with_capacity_and_hasher(1, Default::default()),
+        ));
+        add_results(results, &result_map, None, true);
+        let result_map = Arc::try_unwrap(result_map).unwrap().into_inner().unwrap();
+
+        assert_eq!(result_map.len(), 1);
+        let cov_result = result_map.get("src/lib.rs").unwrap();
+        assert_eq!(cov_result.lines, [(1, 1), (2, 3)].iter().cloned().collect());
+    }
+
+    #[test]
+    fn test_mixed_input_formats() {
+        let (sender, receiver) = crossbeam::channel::unbounded();
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        producer(
+            tmp_dir.path(),
+            &["test/mixed".to_string()],
+            &sender,
+            false,
+            false,
+            usize::MAX,
+        )
+        .unwrap();
+        sender.send(None).unwrap();
+
+        let result_map: SyncCovResultMap = Mutex::new(FxHashMap::default());
+        consumer(
+            tmp_dir.path(),
+            None,
+            &result_map,
+            receiver,
+            true,
+            false,
+            None,
+            false,
+            false,
+            None,
+        )
+        .unwrap();
+        let result_map = result_map.into_inner().unwrap();
+
+        assert_eq!(result_map.len(), 2);
+        let rust = result_map.get("src/lib.rs").unwrap();
+        assert_eq!(
+            rust.lines,
+            [(1, 1), (2, 1), (3, 0)].iter().cloned().collect()
+        );
+        let cpp = result_map.get("src/foo.cpp").unwrap();
+        assert_eq!(
+            cpp.lines,
+            [(3, 2), (4, 2), (5, 0), (8, 2)].iter().cloned().collect()
+        );
+        assert_eq!(cpp.branches.get(&4), Some(&vec![true, false]));
+        assert!(cpp.functions.get("foo(int)").unwrap().executed);
+    }
+
+    #[test]
+    fn test_fail_on_parse_error() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let input_dir = tmp_dir.path().join("input");
+        fs::create_dir(&input_dir).unwrap();
+        fs::copy(
+            "test/invalid_DA_record.info",
+            input_dir.join("invalid_DA_record.info"),
+        )
+        .unwrap();
+
+        let run = |fail_on_parse_error: bool| {
+            let (sender, receiver) = crossbeam::channel::unbounded();
+            producer(
+                tmp_dir.path(),
+                &[input_dir.to_str().unwrap().to_string()],
+                &sender,
+                false,
+                false,
+                usize::MAX,
+            )
+            .unwrap();
+            sender.send(None).unwrap();
+
+            let result_map: SyncCovResultMap = Mutex::new(FxHashMap::default());
+            consumer(
+                tmp_dir.path(),
+                None,
+                &result_map,
+                receiver,
+                true,
+                false,
+                None,
+                false,
+                fail_on_parse_error,
+                None,
+            )
+            .map(|()| result_map.into_inner().unwrap())
+        };
+
+        // By default the malformed file is skipped.
+        assert!(run(false).unwrap().is_empty());
+
+        match run(true) {
+            Err(Error::Parse(name, ParserError::InvalidRecord(_))) => {
+                assert!(name.ends_with("invalid_DA_record.info"), "{}", name)
+            }
+            r => panic!("Parse error expected, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_parse_results() {
+        let results = parse_results(
+            &["test/prova.info".to_string()],
+            ParseOptions {
+                num_threads: 2,
+                ..ParseOptions::default()
+            },
+        )
+        .unwrap();
+
+        let (_, _, result) = results
+            .iter()
+            .find(|(_, rel_path, _)| rel_path.ends_with("MainProcessSingleton.js"))
+            .expect("The results should contain MainProcessSingleton.js");
+        assert!(result.functions["observe"].executed);
+        assert!(!result.functions["addSearchEngine"].executed);
+    }
+
+    #[test]
+    fn test_parse_results_skips_previous_reports() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        fs::copy("test/prova.info", tmp_dir.path().join("prova.info")).unwrap();
+        let parse = || {
+            let mut results = parse_results(
+                &[tmp_dir.path().to_str().unwrap().to_string()],
+                ParseOptions {
+                    num_threads: 2,
+                    ..ParseOptions::default()
+                },
+            )
+            .unwrap();
+            results.sort_by(|a, b| a.1.cmp(&b.1));
+            results
+        };
+
+        let results = parse();
+        // The reports of a previous run are written in the scanned directory.
+        let cobertura_path = tmp_dir.path().join("cobertura.xml");
+        output_cobertura(
+            Box::new(results.clone().into_iter()),
+            cobertura_path.to_str(),
+            LineEnding::Lf,
+            DemangleConfig::default(),
+            &CoberturaConfig::default(),
+        )
+        .unwrap();
+        let json_path = tmp_dir.path().join("grcov.json");
+        output_json(
+            Box::new(results.clone().into_iter()),
+            json_path.to_str(),
+            LineEnding::Lf,
+        )
+        .unwrap();
+
+        assert_eq!(parse(), results);
+    }
+
+    #[test]
+    fn test_parse_results_fail_on_parse_error() {
+        let parse = |fail_on_parse_error: bool| {
+            parse_results(
+                &["test/invalid_DA_record.info".to_string()],
+                ParseOptions {
+                    num_threads: 2,
+                    fail_on_parse_error,
+                    ..ParseOptions::default()
+                },
+            )
+        };
+
+        assert!(parse(false).unwrap().is_empty());
+        match parse(true) {
+            Err(Error::Parse(name, _)) => {
+                assert!(name.ends_with("invalid_DA_record.info"), "{}", name)
+            }
+            r => panic!("Parse error expected, got {:?}", r),
+        }
+    }
 }
@@ -7,25 +7,27 @@ static GLOBAL: TCMalloc = TCMalloc;
 
 extern crate clap;
 extern crate crossbeam;
+extern crate globset;
 extern crate grcov;
 extern crate num_cpus;
 extern crate rustc_hash;
 extern crate serde_json;
 extern crate simplelog;
+extern crate symbolic_common;
 extern crate tempfile;
 
 use clap::{crate_authors, crate_version, App, Arg, ArgGroup};
-use crossbeam::channel::bounded;
+use globset::Glob;
 use log::error;
-use rustc_hash::FxHashMap;
-use serde_json::Value;
 use simplelog::{ColorChoice, Config, LevelFilter, TermLogger, TerminalMode, WriteLogger};
+use std::cell::RefCell;
 use std::fs::{self, File};
 use std::ops::Deref;
 use std::panic;
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
-use std::{process, thread};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+use symbolic_common::Language;
 
 use grcov::*;
 
@@ -51,36 +53,56 @@ fn main() {
                                .takes_value(true))
 
                           .arg(Arg::with_name("output_type")
-                               .help("Sets a custom output type")
+                               .help("Sets a custom output type, several can be given to write them all into the output directory")
                                .long_help(
 "Sets a custom output type:
 - *html* for a HTML coverage report;
 - *coveralls* for the Coveralls specific format;
 - *lcov* for the lcov INFO format;
 - *covdir* for the covdir recursive JSON format;
+- *json-summary* for the line, branch and function coverage of the project and of each file in JSON;
+- *json* for the complete results in JSON, which grcov can read back as input;
+- *ndjson* for the results of each file on its own line of JSON, for streaming consumers;
+- *treemap* for the hit counts of the lines in a JSON hierarchy, for treemap viewers;
+- *tests* for the files hit by each test, when the inputs carry test names (e.g. lcov);
 - *coveralls+* for the Coveralls specific format with function information;
 - *ade* for the ActiveData-ETL specific format;
+- *markdown* for a Markdown summary table;
+- *uncovered* for the uncovered lines of each file, as ranges;
+- *hotspots* for the most executed lines, see --hotspots;
+- *patch* for the coverage of the lines changed by a --diff, in JSON;
 - *files* to only return a list of files.
+Several output types can be given at once, they are then written into the directory given by --output-path,
+with their default file names (e.g. lcov.info, cobertura.xml and html/), each at most once.
 ")
                                .short("t")
                                .long("output-type")
                                .value_name("OUTPUT TYPE")
                                .default_value("lcov")
-                               .possible_values(&["ade", "lcov", "coveralls", "coveralls+", "files", "covdir", "html", "cobertura"])
+                               .multiple(true)
+                               .number_of_values(1)
+                               .possible_values(&["ade", "lcov", "coveralls", "coveralls+", "files", "covdir", "json", "ndjson", "json-summary", "treemap", "tests", "html", "cobertura", "markdown", "uncovered", "hotspots", "patch"])
                                .takes_value(true)
                                .requires_ifs(&[
                                    ("coveralls", "coveralls_auth"),
-                                   ("coveralls+", "coveralls_auth")
+                                   ("coveralls+", "coveralls_auth"),
+                                   ("patch", "diff")
                                ]))
 
                           .arg(Arg::with_name("output_path")
-                               .help("Specifies the output path")
+                               .help("Specifies the output path, the output is compressed with gzip if it ends with .gz, or the output directory when several output types are given")
                                .short("o")
                                .long("output-path")
                                .alias("output-file")
                                .value_name("PATH")
                                .takes_value(true))
 
+                          .arg(Arg::with_name("baseline")
+                               .help("Sets a covdir report to compare the coverage against, in the 'markdown' format")
+                               .long("baseline")
+                               .value_name("PATH")
+                               .takes_value(true))
+
                           .arg(Arg::with_name("source_dir")
                                .help("Specifies the root directory of the source files")
                                .short("s")
@@ -88,6 +110,18 @@ fn main() {
                                .value_name("DIRECTORY")
                                .takes_value(true))
 
+                          .arg(Arg::with_name("path_prefix")
+                               .help("Prepends a prefix to the relative paths of the output, e.g. the subdirectory of the project when grcov is run from it")
+                               .long("path-prefix")
+                               .value_name("PATH")
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("untested_files")
+                               .help("Adds the source files matching this glob, relative to the source directory, which have no coverage data, with their non-blank and non-comment lines as uncovered. The directories matching --ignore, e.g. 'target', aren't walked")
+                               .long("untested-files")
+                               .value_name("GLOB")
+                               .takes_value(true))
+
                           .arg(Arg::with_name("prefix_dir")
                                .help("Specifies a prefix to remove from the paths (e.g. if grcov is run on a different machine than the one that generated the code coverage information)")
                                .short("p")
@@ -95,6 +129,11 @@ fn main() {
                                .value_name("PATH")
                                .takes_value(true))
 
+                          .arg(Arg::with_name("git_root")
+                               .help("Uses the root of the enclosing git repository as the source directory, the paths are made relative to it")
+                               .long("git-root")
+                               .conflicts_with("source_dir"))
+
                           .arg(Arg::with_name("ignore_not_existing")
                                .help("Ignore source files that can't be found on the disk")
                                .long("ignore-not-existing"))
@@ -115,6 +154,10 @@ fn main() {
                                .number_of_values(1)
                                .takes_value(true))
 
+                          .arg(Arg::with_name("normalize_paths")
+                               .help("Collapses '.' and '..' components of the source file paths, so that results for the same file are merged")
+                               .long("normalize-paths"))
+
                           .arg(Arg::with_name("path_mapping")
                                .long("path-mapping")
                                .value_name("PATH")
@@ -122,6 +165,12 @@ fn main() {
                                .number_of_values(1)
                                .takes_value(true))
 
+                          .arg(Arg::with_name("blame_since")
+                               .help("Only reports the lines committed since this date (Unix timestamp, YYYY-MM-DD or RFC 3339), according to git blame")
+                               .long("blame-since")
+                               .value_name("DATE")
+                               .takes_value(true))
+
                           .arg(Arg::with_name("branch")
                                .help("Enables parsing branch coverage information")
                                .long("branch"))
@@ -132,6 +181,70 @@ fn main() {
                                .possible_values(&["covered", "uncovered"])
                                .takes_value(true))
 
+                          .arg(Arg::with_name("sort_by")
+                               .help("Sets the order of the rows of the markdown summary: by path, by coverage (lowest first) or by number of lines (highest first)")
+                               .long("sort-by")
+                               .value_name("SORT BY")
+                               .default_value("path")
+                               .possible_values(&["path", "coverage", "lines"])
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("files_status")
+                               .help("Only lists the files whose lines are all covered, partially covered or not covered at all, in the 'files' format")
+                               .long("files-status")
+                               .value_name("STATUS")
+                               .possible_values(&["full", "partial", "zero"])
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("rounding")
+                               .help("Sets how the percentages, the thresholds and the cobertura rates are rounded")
+                               .long("rounding")
+                               .value_name("MODE")
+                               .default_value("half-even")
+                               .possible_values(&["half-up", "half-even", "truncate"])
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("line_endings")
+                               .help("Sets the line endings of the outputs, whatever the platform")
+                               .long("line-endings")
+                               .value_name("STYLE")
+                               .default_value("lf")
+                               .possible_values(&["lf", "crlf"])
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("fail_on_parse_error")
+                               .help("Aborts with an error if any coverage file can't be parsed, instead of skipping it")
+                               .long("fail-on-parse-error"))
+
+                          .arg(Arg::with_name("fail_under")
+                               .help("Exits with status 2 if the line coverage, in percent, is below this, whatever the output type")
+                               .long("fail-under")
+                               .value_name("PERCENT")
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("fail_under_branches")
+                               .help("Exits with status 2 if the branch coverage, in percent, is below this, whatever the output type")
+                               .long("fail-under-branches")
+                               .value_name("PERCENT")
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("fail_under_file")
+                               .help("Exits with status 2 if the line coverage of any file matching the glob, relative to the source directory, is below the percent, e.g. 'src/core/**=90'")
+                               .long("fail-under-file")
+                               .value_name("GLOB=PERCENT")
+                               .multiple(true)
+                               .number_of_values(1)
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("quiet")
+                               .help("Doesn't print the summary of the line and branch coverage to stderr after writing the output")
+                               .short("q")
+                               .long("quiet"))
+
+                          .arg(Arg::with_name("relative_only")
+                               .help("Fails if an absolute path would be written in the output, instead of a path relative to the source directory")
+                               .long("relative-only"))
+
                           .arg(Arg::with_name("llvm")
                                .help("Speeds-up parsing, when the code coverage information is exclusively coming from a llvm build")
                                .long("llvm"))
@@ -179,6 +292,7 @@ fn main() {
                                .long("parallel"))
 
                           .arg(Arg::with_name("threads")
+                               .help("Sets the number of threads parsing the coverage files, and rendering the pages of the 'html' format")
                                .long("threads")
                                .value_name("NUMBER")
                                .default_value(&default_num_threads)
@@ -237,10 +351,180 @@ fn main() {
                                 .value_name("regex")
                                 .takes_value(true))
 
+                            .arg(Arg::with_name("excl-attributes")
+                                .help("Excludes lines with attributes (e.g. #[derive(...)]), since their code is generated by the compiler.")
+                                .long("excl-attributes"))
+
                             .arg(Arg::with_name("no-demangle")
                                .help("No symbol demangling")
                                .long("no-demangle"))
 
+                          .arg(Arg::with_name("generated_prefix")
+                               .help("Files under this path prefix are grouped in a 'generated' package in the 'cobertura' format")
+                               .long("generated-prefix")
+                               .value_name("PATH")
+                               .multiple(true)
+                               .number_of_values(1)
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("function_stats")
+                               .help("Adds the number of covered and valid functions, and the function rate, to the 'cobertura' format")
+                               .long("function-stats"))
+
+                          .arg(Arg::with_name("absolute_filenames")
+                               .help("Writes the file names of the 'cobertura' format as absolute paths, joined onto the source directory if one is given")
+                               .long("absolute-filenames"))
+
+                          .arg(Arg::with_name("dead_function_lines")
+                               .help("Reports the start line of the functions which were never executed and have no lines as uncovered in the 'cobertura' format")
+                               .long("dead-function-lines"))
+
+                          .arg(Arg::with_name("empty_function_lines")
+                               .help("Reports the start line of all the functions which have no lines in the 'cobertura' format, as covered if they were executed")
+                               .long("empty-function-lines"))
+
+                          .arg(Arg::with_name("verify_filenames")
+                               .help("Warns about the file names of the 'cobertura' format which can't be found under the source directory")
+                               .long("verify-filenames")
+                               .requires("source_dir"))
+
+                          .arg(Arg::with_name("collapse_branches")
+                               .help("Merges the conditions of each line into one in the 'cobertura' format, covered if any (or all) of the branches were taken")
+                               .long("collapse-branches")
+                               .value_name("MODE")
+                               .possible_values(&["any", "all"])
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("branch_rate")
+                               .help("Computes the branch rates of the 'cobertura' format over the conditions, or over the lines with conditions, covered if all their conditions are")
+                               .long("branch-rate")
+                               .value_name("SEMANTICS")
+                               .default_value("condition")
+                               .possible_values(&["condition", "decision"])
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("class_names")
+                               .help("Sets how the class names of the 'cobertura' format are derived from the file paths: file stem, relative path, or relative path with dots as separators")
+                               .long("class-names")
+                               .value_name("MODE")
+                               .default_value("stem")
+                               .possible_values(&["stem", "path", "dotted"])
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("cobertura_indent")
+                               .help("Sets the indentation of the 'cobertura' format: a number of spaces, 'tab', or 'none' to minify it")
+                               .long("cobertura-indent")
+                               .value_name("INDENT")
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("exclude_methods")
+                               .help("Leaves the functions whose demangled names match out of the methods of the 'cobertura' format, e.g. 'drop_in_place', their lines are reported at the class level")
+                               .long("exclude-methods")
+                               .value_name("regex")
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("cobertura_version")
+                               .help("Sets the version attribute of the 'cobertura' format")
+                               .long("cobertura-version")
+                               .value_name("VERSION")
+                               .default_value("1.9")
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("no_complexity")
+                               .help("Leaves the complexity attributes out of the 'cobertura' format, for consumers which don't support them")
+                               .long("no-complexity"))
+
+                          .arg(Arg::with_name("demangling")
+                               .help("Sets how the method names of the 'cobertura' format are demangled: just the names, or completely, e.g. with the template arguments and the parameter types")
+                               .long("demangling")
+                               .value_name("MODE")
+                               .default_value("name-only")
+                               .possible_values(&["name-only", "complete"])
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("demangle_language")
+                               .help("Sets the language of the method names demangled for the 'cobertura' format, by default it's guessed from their mangling")
+                               .long("demangle-language")
+                               .value_name("LANGUAGE")
+                               .default_value("auto")
+                               .possible_values(&["auto", "cpp", "rust", "swift"])
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("diff")
+                               .help("Sets the changed lines reported by the 'patch' format, as a unified diff (e.g. from 'git diff') or as a list of 'path:line' pairs")
+                               .long("diff")
+                               .value_name("PATH")
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("dtd")
+                               .help("Sets the DTD referenced by the DOCTYPE of the 'cobertura' format, e.g. a local path for parsers without network access, or 'none' to omit the DOCTYPE. It can't contain a single quote or a '>'")
+                               .long("dtd")
+                               .value_name("PATH")
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("omit_methods")
+                               .help("Reports all the lines at the class level in the 'cobertura' format, without methods, for smaller reports")
+                               .long("omit-methods"))
+
+                          .arg(Arg::with_name("package_per_directory")
+                               .help("Groups the classes of each directory into one package in the 'cobertura' format, instead of writing one package per file")
+                               .long("package-per-directory"))
+
+                          .arg(Arg::with_name("package_names")
+                               .help("Sets the names of the packages of the 'cobertura' format: 'path', 'dotted-dir', or a template where {path}, {dir}, {dotted_dir} and {0}, {1}, ... are replaced by the relative path, the directory, the directory with dots as separators and its components")
+                               .long("package-names")
+                               .value_name("NAMES")
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("keep_excluded")
+                               .help("Lists the files excluded by --ignore, --keep-only or --filter in the 'covdir' format, marked as excluded and not counted in the totals")
+                               .long("keep-excluded"))
+
+                          .arg(Arg::with_name("cache_dir")
+                               .help("Caches the parsed results of the gcno and gcda files in this directory, for the unchanged ones not to be parsed again on the next run")
+                               .long("cache-dir")
+                               .value_name("DIRECTORY")
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("hotspots")
+                               .help("Sets the number of lines listed by the 'hotspots' format, the most executed first")
+                               .long("hotspots")
+                               .value_name("NUMBER")
+                               .default_value("20")
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("max_files")
+                               .help("Aborts if more coverage files than this are found in the inputs, to catch input paths pointing to huge directories by mistake")
+                               .long("max-files")
+                               .value_name("NUMBER")
+                               .default_value("1000000")
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("max_hits")
+                               .help("Caps the hit counts of the lines in the 'cobertura' format, e.g. at 2147483647 for consumers reading them into 32-bit integers")
+                               .long("max-hits")
+                               .value_name("NUMBER")
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("min_method_lines")
+                               .help("Methods with fewer lines than this are reported at the class level in the 'cobertura' format")
+                               .long("min-method-lines")
+                               .value_name("NUMBER")
+                               .default_value("0")
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("rate_precision")
+                               .help("Rounds the line and branch rates of the 'cobertura' format to this number of decimals")
+                               .long("rate-precision")
+                               .value_name("DIGITS")
+                               .takes_value(true))
+
+                          .arg(Arg::with_name("timestamp")
+                               .help("Sets the timestamp of the 'cobertura' format, in seconds since the Unix epoch. Defaults to SOURCE_DATE_EPOCH if set, to the current time otherwise")
+                               .long("timestamp")
+                               .value_name("SECONDS")
+                               .takes_value(true))
+
                           // This group requires that at least one of --token and --service-job-id
                           // be present. --service-job-id requires --service-name, so this
                           // effectively means we accept the following combinations:
@@ -254,9 +538,59 @@ fn main() {
     let paths: Vec<_> = matches.values_of("paths").unwrap().collect();
     let paths: Vec<String> = paths.iter().map(|s| s.to_string()).collect();
     let binary_path = matches.value_of("binary_path").map(PathBuf::from);
-    let output_type = matches.value_of("output_type").unwrap();
+    let mut output_types: Vec<OutputType> = Vec::new();
+    for name in matches.values_of("output_type").unwrap() {
+        let output_type = name.parse().unwrap_or_else(|e: String| {
+            clap::Error::with_description(&e, clap::ErrorKind::InvalidValue).exit()
+        });
+        // Each output type is written to its own file, so it can only be given once.
+        if output_types.contains(&output_type) {
+            clap::Error::with_description(
+                &format!("The output type {} is given several times", name),
+                clap::ErrorKind::ArgumentConflict,
+            )
+            .exit();
+        }
+        output_types.push(output_type);
+    }
     let output_path = matches.value_of("output_path");
-    let source_dir = matches.value_of("source_dir").unwrap_or("");
+    if output_types.len() > 1 && output_path.is_none() {
+        clap::Error::with_description(
+            "Several output types need an output directory, given by --output-path",
+            clap::ErrorKind::MissingRequiredArgument,
+        )
+        .exit();
+    }
+    let baseline = matches.value_of("baseline").map(PathBuf::from);
+    let sort_by = match matches.value_of("sort_by").unwrap() {
+        "coverage" => SortBy::Coverage,
+        "lines" => SortBy::Lines,
+        _ => SortBy::Path,
+    };
+    let files_status = matches.value_of("files_status").map(|status| match status {
+        "full" => FileStatus::Full,
+        "partial" => FileStatus::Partial,
+        _ => FileStatus::Zero,
+    });
+    let line_ending = match matches.value_of("line_endings").unwrap() {
+        "crlf" => LineEnding::Crlf,
+        _ => LineEnding::Lf,
+    };
+    let rounding = match matches.value_of("rounding").unwrap() {
+        "half-up" => Rounding::HalfUp,
+        "truncate" => Rounding::Truncate,
+        _ => Rounding::HalfEven,
+    };
+    let source_dir = match matches.value_of("source_dir") {
+        Some(source_dir) => source_dir.to_owned(),
+        // Outside of a git repository, the paths are left as they are.
+        None if matches.is_present("git_root") => std::env::current_dir()
+            .ok()
+            .and_then(find_git_root)
+            .map(|git_root| git_root.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        None => String::new(),
+    };
     let prefix_dir = matches.value_of("prefix_dir").unwrap_or("");
     let ignore_not_existing = matches.is_present("ignore_not_existing");
     let to_ignore_dirs = if let Some(to_ignore_dirs) = matches.values_of("ignore_dir") {
@@ -268,6 +602,7 @@ fn main() {
         .values_of("keep_dir")
         .map_or_else(Vec::new, |dirs| dirs.collect());
     let path_mapping_file = matches.value_of("path_mapping").unwrap_or("");
+    let normalize_paths = matches.is_present("normalize_paths");
     let branch_enabled = matches.is_present("branch");
     let filter_option = if let Some(filter) = matches.value_of("filter") {
         if filter == "covered" {
@@ -345,8 +680,96 @@ fn main() {
         excl_br_line,
         excl_br_start,
         excl_br_stop,
+        matches.is_present("excl-attributes"),
     );
     let demangle = !matches.is_present("no-demangle");
+    let demangle_config = DemangleConfig {
+        enabled: demangle,
+        full: matches.value_of("demangling") == Some("complete"),
+    };
+    let mut cobertura_config = CoberturaConfig {
+        min_method_lines: matches
+            .value_of("min_method_lines")
+            .unwrap()
+            .parse()
+            .expect("Minimum number of method lines should be a number"),
+        generated_prefixes: matches
+            .values_of("generated_prefix")
+            .map_or_else(Vec::new, |prefixes| prefixes.map(String::from).collect()),
+        function_stats: matches.is_present("function_stats"),
+        verify_root: if matches.is_present("verify_filenames") {
+            Some(PathBuf::from(&source_dir))
+        } else {
+            None
+        },
+        collapse_branches: matches
+            .value_of("collapse_branches")
+            .map(|collapse| match collapse {
+                "all" => BranchCollapse::All,
+                _ => BranchCollapse::Any,
+            }),
+        decision_branch_rate: matches.value_of("branch_rate") == Some("decision"),
+        class_names: match matches.value_of("class_names").unwrap() {
+            "path" => Some(ClassNames::Path),
+            "dotted" => Some(ClassNames::Dotted),
+            _ => None,
+        },
+        package_per_directory: matches.is_present("package_per_directory"),
+        package_names: matches
+            .value_of("package_names")
+            .map(|package_names| match package_names {
+                "path" => PackageNames::FilePath,
+                "dotted-dir" => PackageNames::DottedDir,
+                _ => PackageNames::CustomTemplate(package_names.to_string()),
+            }),
+        sources: Vec::new(),
+        doctype: matches.value_of("dtd").map(|dtd| {
+            dtd.parse().unwrap_or_else(|e| {
+                error!("Invalid --dtd: {}", e);
+                process::exit(1);
+            })
+        }),
+        rate_precision: matches.value_of("rate_precision").map(|precision| {
+            precision
+                .parse()
+                .expect("Precision of the rates should be a number")
+        }),
+        rounding,
+        timestamp: matches
+            .value_of("timestamp")
+            .map(|timestamp| timestamp.parse().expect("Timestamp should be a number")),
+        absolute_filenames: matches.is_present("absolute_filenames"),
+        dead_function_lines: matches.is_present("dead_function_lines"),
+        empty_function_lines: matches.is_present("empty_function_lines"),
+        demangle_language: match matches.value_of("demangle_language").unwrap() {
+            "cpp" => Language::Cpp,
+            "rust" => Language::Rust,
+            "swift" => Language::Swift,
+            _ => Language::Unknown,
+        },
+        omit_methods: matches.is_present("omit_methods"),
+        version: matches.value_of("cobertura_version").map(String::from),
+        omit_complexity: matches.is_present("no_complexity"),
+        indent: matches
+            .value_of("cobertura_indent")
+            .map(|indent| match indent {
+                "none" => Indent::Minified,
+                "tab" => Indent::Tab,
+                _ => Indent::Spaces(
+                    indent
+                        .parse()
+                        .expect("Indentation should be a number of spaces, 'tab' or 'none'"),
+                ),
+            }),
+        max_hits: matches.value_of("max_hits").map(|max_hits| {
+            max_hits
+                .parse()
+                .expect("Maximum number of hits should be a number")
+        }),
+        excluded_methods: matches
+            .value_of("exclude_methods")
+            .map(|f| regex::Regex::new(f).expect("invalid regex for exclude-methods.")),
+    };
 
     panic::set_hook(Box::new(|panic_info| {
         let (filename, line) = panic_info
@@ -373,6 +796,55 @@ fn main() {
         .parse()
         .expect("Number of threads should be a number");
     let guess_directory = matches.is_present("guess_directory");
+    let max_files: usize = matches
+        .value_of("max_files")
+        .unwrap()
+        .parse()
+        .expect("Maximum number of input files should be a number");
+    let hotspots: usize = matches
+        .value_of("hotspots")
+        .unwrap()
+        .parse()
+        .expect("Number of hotspots should be a number");
+    let fail_on_parse_error = matches.is_present("fail_on_parse_error");
+    let fail_under = matches.value_of("fail_under").map(|percent| {
+        percent
+            .parse::<f64>()
+            .expect("The minimum line coverage should be a number")
+    });
+    let fail_under_branches = matches.value_of("fail_under_branches").map(|percent| {
+        percent
+            .parse::<f64>()
+            .expect("The minimum branch coverage should be a number")
+    });
+    let file_thresholds: Vec<FileThreshold> =
+        matches
+            .values_of("fail_under_file")
+            .map_or_else(Vec::new, |rules| {
+                rules
+                    .map(|rule| {
+                        FileThreshold::parse(rule).expect(
+                            "--fail-under-file should be a glob and a percent, e.g. 'src/**=90'",
+                        )
+                    })
+                    .collect()
+            });
+    let changed_lines = matches.value_of("diff").map(|diff| {
+        read_changed_lines(Path::new(diff))
+            .unwrap_or_else(|e| panic!("Cannot read the changed lines from {}: {}", diff, e))
+    });
+    let blame_since = matches.value_of("blame_since").map(|since| {
+        parse_blame_since(since).expect("--blame-since should be a timestamp or a date")
+    });
+
+    let untested_files = matches.value_of("untested_files").map(|glob| {
+        Glob::new(glob)
+            .map(|glob| glob.compile_matcher())
+            .unwrap_or_else(|e| {
+                error!("Invalid glob {} for --untested-files: {}", glob, e);
+                process::exit(1);
+            })
+    });
 
     let source_root = if !source_dir.is_empty() {
         Some(canonicalize_path(&source_dir).expect("Source directory does not exist."))
@@ -380,117 +852,92 @@ fn main() {
         None
     };
 
-    let prefix_dir = if prefix_dir.is_empty() {
-        source_root.clone()
-    } else {
-        Some(PathBuf::from(prefix_dir))
-    };
-
-    let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
-    let tmp_path = tmp_dir.path().to_owned();
-    assert!(tmp_path.exists());
-
-    let result_map: Arc<SyncCovResultMap> = Arc::new(Mutex::new(
-        FxHashMap::with_capacity_and_hasher(20_000, Default::default()),
-    ));
-    let (sender, receiver) = bounded(2 * num_threads);
-    let path_mapping: Arc<Mutex<Option<Value>>> = Arc::new(Mutex::new(None));
-
-    let producer = {
-        let sender: JobSender = sender.clone();
-        let tmp_path = tmp_path.clone();
-        let path_mapping_file = path_mapping_file.to_owned();
-        let path_mapping = Arc::clone(&path_mapping);
-
-        thread::Builder::new()
-            .name(String::from("Producer"))
-            .spawn(move || {
-                let producer_path_mapping_buf = producer(
-                    &tmp_path,
-                    &paths,
-                    &sender,
-                    filter_option.is_some() && filter_option.unwrap(),
-                    is_llvm,
-                );
-
-                let mut path_mapping = path_mapping.lock().unwrap();
-                *path_mapping = if !path_mapping_file.is_empty() {
-                    let file = File::open(path_mapping_file).unwrap();
-                    Some(serde_json::from_reader(file).unwrap())
-                } else {
-                    producer_path_mapping_buf.map(|producer_path_mapping_buf| {
-                        serde_json::from_slice(&producer_path_mapping_buf).unwrap()
-                    })
-                };
-            })
-            .unwrap()
-    };
-
-    let mut parsers = Vec::new();
-
-    for i in 0..num_threads {
-        let receiver = receiver.clone();
-        let result_map = Arc::clone(&result_map);
-        let working_dir = tmp_path.join(format!("{}", i));
-        let source_root = source_root.clone();
-        let binary_path = binary_path.clone();
-
-        let t = thread::Builder::new()
-            .name(format!("Consumer {}", i))
-            .spawn(move || {
-                fs::create_dir(&working_dir).expect("Failed to create working directory");
-                consumer(
-                    &working_dir,
-                    source_root.as_deref(),
-                    &result_map,
-                    receiver,
-                    branch_enabled,
-                    guess_directory,
-                    binary_path.as_deref(),
-                );
-            })
-            .unwrap();
-
-        parsers.push(t);
-    }
-
-    if producer.join().is_err() {
-        process::exit(1);
-    }
-
-    // Poison the receiver, now that the producer is finished.
-    for _ in 0..num_threads {
-        sender.send(None).unwrap();
+    // The file names of the Cobertura report are relative to the source directory.
+    if let Some(source_root) = &source_root {
+        cobertura_config.sources = vec![source_root.to_string_lossy().into_owned()];
     }
 
-    for parser in parsers {
-        if parser.join().is_err() {
+    let options = ParseOptions {
+        source_dir: source_root.clone(),
+        prefix_dir: if prefix_dir.is_empty() {
+            None
+        } else {
+            Some(PathBuf::from(prefix_dir))
+        },
+        binary_path,
+        path_mapping_file: if path_mapping_file.is_empty() {
+            None
+        } else {
+            Some(PathBuf::from(path_mapping_file))
+        },
+        ignore_not_existing,
+        ignore_dirs: to_ignore_dirs.iter().map(|dir| dir.to_string()).collect(),
+        keep_dirs: to_keep_dirs.iter().map(|dir| dir.to_string()).collect(),
+        filter: filter_option,
+        // The other formats have no way to tell the excluded files apart.
+        keep_excluded: output_types.contains(&OutputType::Covdir)
+            && matches.is_present("keep_excluded"),
+        untested_files,
+        file_filter,
+        branch_enabled,
+        guess_directory,
+        normalize_paths,
+        fail_on_parse_error,
+        is_llvm,
+        max_files,
+        num_threads,
+        cache_dir: matches.value_of("cache_dir").map(PathBuf::from),
+    };
+    let results = match parse_results(&paths, options) {
+        Ok(results) => results,
+        Err(e) => {
+            error!("{}", e);
             process::exit(1);
         }
-    }
-
-    let result_map_mutex = Arc::try_unwrap(result_map).unwrap();
-    let result_map = result_map_mutex.into_inner().unwrap();
-
-    let path_mapping_mutex = Arc::try_unwrap(path_mapping).unwrap();
-    let path_mapping = path_mapping_mutex.into_inner().unwrap();
+    };
 
-    let iterator = rewrite_paths(
-        result_map,
-        path_mapping,
-        source_root.as_deref(),
-        prefix_dir.as_deref(),
-        ignore_not_existing,
-        &to_ignore_dirs,
-        &to_keep_dirs,
-        filter_option,
-        file_filter,
-    );
+    let iterator: CovResultIter = Box::new(results.into_iter());
+    let iterator = match blame_since {
+        Some(since) => filter_blame_since(iterator, since),
+        None => iterator,
+    };
+    let iterator = match matches.value_of("path_prefix") {
+        Some(prefix) => prepend_path_prefix(iterator, prefix),
+        None => iterator,
+    };
+    let iterator = if matches.is_present("relative_only") {
+        match check_relative_paths(iterator) {
+            Ok(iterator) => iterator,
+            Err(e) => {
+                error!("{}", e);
+                process::exit(1);
+            }
+        }
+    } else {
+        iterator
+    };
+    // The summary is counted like the totals of the Cobertura report, for them to agree, as
+    // the results are written.
+    let quiet = matches.is_present("quiet");
+    let totals = Rc::new(RefCell::new(CoverageTotals::default()));
+    let iterator = if quiet && fail_under.is_none() && fail_under_branches.is_none() {
+        iterator
+    } else {
+        count_totals(iterator, cobertura_config.clone(), totals.clone())
+    };
+    let file_failures = Rc::new(RefCell::new(Vec::new()));
+    let iterator = if file_thresholds.is_empty() {
+        iterator
+    } else {
+        check_file_thresholds(iterator, file_thresholds, rounding, file_failures.clone())
+    };
 
-    match output_type {
-        "ade" => output_activedata_etl(iterator, output_path, demangle),
-        "lcov" => output_lcov(iterator, output_path, demangle),
-        "coveralls" => output_coveralls(
+    let write_output = |output_type: OutputType,
+                        iterator: CovResultIter,
+                        output_path: Option<&str>| match output_type {
+        OutputType::Ade => output_activedata_etl(iterator, output_path, line_ending, demangle),
+        OutputType::Lcov => output_lcov(iterator, output_path, line_ending, demangle),
+        OutputType::Coveralls => output_coveralls(
             iterator,
             repo_token,
             service_name,
@@ -500,11 +947,12 @@ fn main() {
             commit_sha,
             false,
             output_path,
+            line_ending,
             vcs_branch,
             is_parallel,
             demangle,
         ),
-        "coveralls+" => output_coveralls(
+        OutputType::CoverallsPlus => output_coveralls(
             iterator,
             repo_token,
             service_name,
@@ -514,14 +962,87 @@ fn main() {
             commit_sha,
             true,
             output_path,
+            line_ending,
             vcs_branch,
             is_parallel,
             demangle,
         ),
-        "files" => output_files(iterator, output_path),
-        "covdir" => output_covdir(iterator, output_path),
-        "html" => output_html(iterator, output_path, num_threads, branch_enabled),
-        "cobertura" => output_cobertura(iterator, output_path, demangle),
-        _ => panic!("{} is not a supported output type", output_type),
+        OutputType::Files => output_files(iterator, output_path, line_ending, files_status),
+        OutputType::Covdir => output_covdir(iterator, output_path, line_ending),
+        OutputType::Json => output_json(iterator, output_path, line_ending),
+        OutputType::Ndjson => output_ndjson(iterator, output_path, line_ending),
+        OutputType::JsonSummary => output_json_summary(iterator, output_path, line_ending),
+        OutputType::Treemap => output_treemap(iterator, output_path, line_ending),
+        OutputType::Tests => output_tests(iterator, output_path, line_ending),
+        OutputType::Html => output_html(iterator, output_path, num_threads, branch_enabled),
+        OutputType::Cobertura => output_cobertura(
+            iterator,
+            output_path,
+            line_ending,
+            demangle_config,
+            &cobertura_config,
+        ),
+        OutputType::Markdown => output_markdown(
+            iterator,
+            output_path,
+            line_ending,
+            baseline.as_deref(),
+            sort_by,
+            rounding,
+        ),
+        OutputType::Uncovered => output_uncovered(iterator, output_path, line_ending),
+        OutputType::Hotspots => output_hotspots(iterator, output_path, line_ending, hotspots),
+        OutputType::Patch => output_patch_coverage(
+            iterator,
+            output_path,
+            line_ending,
+            changed_lines.as_ref().unwrap(),
+        ),
     };
+
+    let written = if let [output_type] = output_types[..] {
+        write_output(output_type, iterator, output_path)
+    } else {
+        // Each output consumes the results, they are collected once and replayed.
+        let results: Vec<_> = iterator.collect();
+        let output_dir = PathBuf::from(output_path.unwrap());
+        fs::create_dir_all(&output_dir).expect("Cannot create the output directory");
+        // The outputs are written concurrently, each to its file in the output directory.
+        let writers: Vec<OutputWriter> = output_types
+            .iter()
+            .map(|&output_type| {
+                let output_path = output_dir.join(output_type.file_name());
+                let write_output = &write_output;
+                let writer: OutputWriter = Box::new(move |iterator: CovResultIter| {
+                    // Only the covdir format tells the excluded files apart.
+                    let iterator: CovResultIter = if output_type == OutputType::Covdir {
+                        iterator
+                    } else {
+                        Box::new(iterator.filter(|(_, _, result)| !result.excluded))
+                    };
+                    write_output(output_type, iterator, output_path.to_str())
+                });
+                writer
+            })
+            .collect();
+        output_concurrently(results, writers)
+    };
+    if let Err(e) = written {
+        error!("Cannot write the output: {}", e);
+        process::exit(1);
+    }
+
+    let totals = *totals.borrow();
+    if !quiet {
+        eprintln!("{}", totals.summary(rounding));
+    }
+
+    let mut failures = totals.check(fail_under, fail_under_branches, rounding);
+    failures.append(&mut file_failures.borrow_mut());
+    if !failures.is_empty() {
+        for failure in failures {
+            error!("{}", failure);
+        }
+        process::exit(2);
+    }
 }
@@ -0,0 +1,362 @@
+use rustc_hash::FxHashMap;
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::defs::*;
+use crate::error::Error;
+use crate::output::{
+    finish_output, format_percent, format_percent_delta, get_target_output_writable, LineEnding,
+    Rounding,
+};
+use crate::parser::ParserError;
+use crate::threshold::CoverageTotals;
+
+fn collect_covdir_percents(node: &Value, path: PathBuf, percents: &mut FxHashMap<PathBuf, f64>) {
+    if let Some(children) = node["children"].as_object() {
+        for (name, child) in children {
+            collect_covdir_percents(child, path.join(name), percents);
+        }
+    }
+    if let Some(percent) = node["coveragePercent"].as_f64() {
+        percents.insert(path, percent);
+    }
+}
+
+/// Reads the line coverage percentage of each file and directory from a covdir report, the
+/// one of the whole report is keyed by the empty path.
+pub fn read_covdir_percents(path: &Path) -> Result<FxHashMap<PathBuf, f64>, Error> {
+    let file = File::open(path)?;
+    let covdir: Value = serde_json::from_reader(file).map_err(|e| {
+        Error::Parse(
+            path.display().to_string(),
+            ParserError::InvalidData(e.to_string()),
+        )
+    })?;
+
+    let mut percents = FxHashMap::default();
+    collect_covdir_percents(&covdir, PathBuf::new(), &mut percents);
+    Ok(percents)
+}
+
+/// Order of the rows in the Markdown summary.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortBy {
+    /// Alphabetical order of the file paths.
+    Path,
+    /// Lowest line coverage first.
+    Coverage,
+    /// Files with the most lines first.
+    Lines,
+}
+
+fn percent(covered: usize, valid: usize) -> Option<f64> {
+    if valid > 0 {
+        Some(CDStats::get_percent(covered, valid))
+    } else {
+        None
+    }
+}
+
+fn format_cell(percent: Option<f64>, rounding: Rounding) -> String {
+    percent.map_or_else(|| "n/a".to_string(), |p| format_percent(p, rounding))
+}
+
+/// Writes a Markdown table with the line and branch coverage of each file, followed by a
+/// total row. The percentages of files without lines or branches are shown as `n/a`.
+///
+/// When a baseline covdir report is given, a column with the line coverage delta is added.
+pub fn output_markdown(
+    results: CovResultIter,
+    output_file: Option<&str>,
+    line_ending: LineEnding,
+    baseline: Option<&Path>,
+    sort_by: SortBy,
+    rounding: Rounding,
+) -> io::Result<()> {
+    let baseline = baseline
+        .map(|path| {
+            read_covdir_percents(path).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Cannot read the covdir report {}: {}", path.display(), e),
+                )
+            })
+        })
+        .transpose()?;
+
+    let mut total = CoverageTotals::default();
+    let mut rows: Vec<(PathBuf, CoverageTotals)> = results
+        .map(|(_, rel_path, result)| {
+            let mut counts = CoverageTotals::default();
+            counts.add(&result);
+            total.add(&result);
+            (rel_path, counts)
+        })
+        .collect();
+    // The sort is stable, so rows with the same key stay ordered by path.
+    rows.sort_by(|a, b| a.0.cmp(&b.0));
+    match sort_by {
+        SortBy::Path => {}
+        // The files without lines come last.
+        SortBy::Coverage => rows.sort_by(|a, b| {
+            let line_percent = |counts: &CoverageTotals| {
+                percent(counts.lines_covered, counts.lines_valid).unwrap_or(f64::INFINITY)
+            };
+            line_percent(&a.1).partial_cmp(&line_percent(&b.1)).unwrap()
+        }),
+        SortBy::Lines => rows.sort_by_key(|row| Reverse(row.1.lines_valid)),
+    }
+
+    let mut writer = BufWriter::new(get_target_output_writable(output_file, line_ending)?);
+    if baseline.is_some() {
+        writeln!(writer, "| File | Lines | Branches | Delta |")?;
+        writeln!(writer, "| ---- | ----: | -------: | ----: |")?;
+    } else {
+        writeln!(writer, "| File | Lines | Branches |")?;
+        writeln!(writer, "| ---- | ----: | -------: |")?;
+    }
+
+    let mut write_row = |name: &str, key: &Path, counts: &CoverageTotals| -> io::Result<()> {
+        let lines = percent(counts.lines_covered, counts.lines_valid);
+        let branches = percent(counts.branches_covered, counts.branches_valid);
+        write!(
+            writer,
+            "| {} | {} | {} |",
+            name,
+            format_cell(lines, rounding),
+            format_cell(branches, rounding)
+        )?;
+        if let Some(baseline) = &baseline {
+            match (baseline.get(key), lines) {
+                (Some(old), Some(percent)) => write!(
+                    writer,
+                    " {} |",
+                    format_percent_delta(percent - old, rounding)
+                ),
+                (Some(_), None) => write!(writer, " n/a |"),
+                (None, _) => write!(writer, " new |"),
+            }?;
+        }
+        writeln!(writer)
+    };
+
+    for (path, counts) in &rows {
+        write_row(&path.display().to_string(), path, counts)?;
+    }
+    // The total is keyed by the empty path in the baseline, like the root of the covdir report.
+    write_row("TOTAL", Path::new(""), &total)?;
+
+    finish_output(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output_covdir;
+    use std::io::Read;
+
+    fn read_file(path: &Path) -> String {
+        let mut f =
+            File::open(path).unwrap_or_else(|_| panic!("{:?} file not found", path.file_name()));
+        let mut s = String::new();
+        f.read_to_string(&mut s).unwrap();
+        s
+    }
+
+    #[test]
+    fn test_markdown_delta() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let baseline_path = tmp_dir.path().join("baseline.json");
+        let file_path = tmp_dir.path().join("summary.md");
+
+        let baseline = vec![
+            (
+                PathBuf::from("foo/a.cpp"),
+                PathBuf::from("foo/a.cpp"),
+                CovResult::with_lines(&[(1, 1), (2, 0)]),
+            ),
+            (
+                PathBuf::from("foo/b.cpp"),
+                PathBuf::from("foo/b.cpp"),
+                CovResult::with_lines(&[(1, 1), (2, 1), (3, 1), (4, 1)]),
+            ),
+        ];
+        output_covdir(
+            Box::new(baseline.into_iter()),
+            Some(baseline_path.to_str().unwrap()),
+            LineEnding::Lf,
+        )
+        .unwrap();
+
+        let results = vec![
+            (
+                PathBuf::from("foo/b.cpp"),
+                PathBuf::from("foo/b.cpp"),
+                CovResult::with_lines(&[(1, 1), (2, 1), (3, 0), (4, 1)]),
+            ),
+            (
+                PathBuf::from("foo/a.cpp"),
+                PathBuf::from("foo/a.cpp"),
+                CovResult::with_lines(&[(1, 1), (2, 1)]),
+            ),
+            (
+                PathBuf::from("bar/c.cpp"),
+                PathBuf::from("bar/c.cpp"),
+                CovResult::with_lines(&[(1, 1), (2, 0), (3, 0)]),
+            ),
+        ];
+        output_markdown(
+            Box::new(results.into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            Some(&baseline_path),
+            SortBy::Path,
+            Rounding::HalfEven,
+        )
+        .unwrap();
+
+        assert_eq!(
+            read_file(&file_path),
+            "| File | Lines | Branches | Delta |
+| ---- | ----: | -------: | ----: |
+| bar/c.cpp | 33.3% | n/a | new |
+| foo/a.cpp | 100.0% | n/a | +50.0% |
+| foo/b.cpp | 75.0% | n/a | -25.0% |
+| TOTAL | 66.7% | n/a | -16.7% |
+"
+        );
+    }
+
+    #[test]
+    fn test_markdown_missing_baseline() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("summary.md");
+
+        let results = vec![(
+            PathBuf::from("a.cpp"),
+            PathBuf::from("a.cpp"),
+            CovResult::with_lines(&[(1, 1)]),
+        )];
+        let written = output_markdown(
+            Box::new(results.into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            Some(&tmp_dir.path().join("baseline.json")),
+            SortBy::Path,
+            Rounding::HalfEven,
+        );
+
+        assert_eq!(written.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_markdown_sort_by_coverage() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("summary.md");
+
+        let results = vec![
+            (
+                PathBuf::from("a.cpp"),
+                PathBuf::from("a.cpp"),
+                CovResult::with_lines(&[(1, 1), (2, 1)]),
+            ),
+            (
+                PathBuf::from("b.cpp"),
+                PathBuf::from("b.cpp"),
+                CovResult::with_lines(&[(1, 0), (2, 0), (3, 1)]),
+            ),
+            (
+                PathBuf::from("c.cpp"),
+                PathBuf::from("c.cpp"),
+                CovResult::with_lines(&[(1, 1), (2, 0)]),
+            ),
+        ];
+        output_markdown(
+            Box::new(results.clone().into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            None,
+            SortBy::Coverage,
+            Rounding::HalfEven,
+        )
+        .unwrap();
+        assert_eq!(
+            read_file(&file_path),
+            "| File | Lines | Branches |
+| ---- | ----: | -------: |
+| b.cpp | 33.3% | n/a |
+| c.cpp | 50.0% | n/a |
+| a.cpp | 100.0% | n/a |
+| TOTAL | 57.1% | n/a |
+"
+        );
+
+        output_markdown(
+            Box::new(results.into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            None,
+            SortBy::Lines,
+            Rounding::HalfEven,
+        )
+        .unwrap();
+        assert!(read_file(&file_path).contains(
+            "| b.cpp | 33.3% | n/a |
+| a.cpp | 100.0% | n/a |
+| c.cpp | 50.0% | n/a |
+"
+        ));
+    }
+
+    #[test]
+    fn test_markdown_branches() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("summary.md");
+
+        let mut with_branches = CovResult::with_lines(&[(1, 1), (2, 0)]);
+        with_branches
+            .branches
+            .insert(1, vec![true, false, false, false]);
+        let results = vec![
+            (
+                PathBuf::from("b.cpp"),
+                PathBuf::from("b.cpp"),
+                with_branches,
+            ),
+            (
+                PathBuf::from("a.h"),
+                PathBuf::from("a.h"),
+                CovResult::with_lines(&[]),
+            ),
+            (
+                PathBuf::from("c.cpp"),
+                PathBuf::from("c.cpp"),
+                CovResult::with_lines(&[(1, 1)]),
+            ),
+        ];
+        output_markdown(
+            Box::new(results.into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            None,
+            SortBy::Coverage,
+            Rounding::HalfEven,
+        )
+        .unwrap();
+
+        // The files without lines are listed last.
+        assert_eq!(
+            read_file(&file_path),
+            "| File | Lines | Branches |
+| ---- | ----: | -------: |
+| b.cpp | 50.0% | 25.0% |
+| c.cpp | 100.0% | n/a |
+| a.h | n/a | n/a |
+| TOTAL | 66.7% | 25.0% |
+"
+        );
+    }
+}
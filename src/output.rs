@@ -1,17 +1,19 @@
 use crossbeam::channel::unbounded;
+use flate2::{write::GzEncoder, Compression};
 use md5::{Digest, Md5};
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use serde_json::{self, json, Value};
 use std::cell::RefCell;
-use std::collections::{hash_map, BTreeSet};
-use std::ffi::OsStr;
-use std::fs::File;
+use std::collections::{hash_map, BTreeMap, BTreeSet};
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
 use std::io::{self, BufWriter, Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::{
-    process::{self, Command, Stdio},
+    process::{Command, Stdio},
     thread,
 };
 use symbolic_common::Name;
@@ -20,6 +22,7 @@ use uuid::Uuid;
 
 use crate::defs::*;
 use crate::html;
+use crate::path_rewriting::canonicalize_path;
 
 macro_rules! demangle {
     ($name: expr, $demangle: expr, $options: expr) => {{
@@ -35,41 +38,365 @@ macro_rules! demangle {
     }};
 }
 
-pub fn get_target_output_writable(output_file: Option<&str>) -> Box<dyn Write> {
-    let write_target: Box<dyn Write> = match output_file {
+/// How the percentages shown to users are rounded to their last decimal.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Rounding {
+    /// Ties are rounded away from zero.
+    HalfUp,
+    /// Ties are rounded to the even neighbour, so that they don't all go the same way.
+    #[default]
+    HalfEven,
+    /// The extra decimals are dropped.
+    Truncate,
+}
+
+impl Rounding {
+    /// Rounds a percentage like it's shown by `format_percent`, e.g. to compare it with a
+    /// threshold.
+    pub fn round_percent(self, percent: f64) -> f64 {
+        self.round(percent, 1)
+    }
+
+    /// Rounds `value` to `decimals` decimal places.
+    pub fn round(self, value: f64, decimals: i32) -> f64 {
+        let factor = 10f64.powi(decimals);
+        let mut scaled = value * factor;
+        // Most decimal values aren't exact in binary, e.g. 0.865 is stored as 0.86499999...:
+        // snap the values within rounding errors of a tie or an integer to it, so that the
+        // result doesn't depend on how the value was computed.
+        let snapped = (scaled * 2.0).round() / 2.0;
+        if (scaled - snapped).abs() < 1e-9 * scaled.abs().max(1.0) {
+            scaled = snapped;
+        }
+        let rounded = match self {
+            Rounding::HalfUp => scaled.round(),
+            Rounding::HalfEven if scaled.fract().abs() == 0.5 => {
+                let away = scaled.round();
+                if away % 2.0 == 0.0 {
+                    away
+                } else {
+                    away - scaled.signum()
+                }
+            }
+            Rounding::HalfEven => scaled.round(),
+            Rounding::Truncate => scaled.trunc(),
+        };
+        rounded / factor
+    }
+}
+
+/// Formats a number rounded to a number of decimals, e.g. a rate or a percentage.
+pub fn format_rounded(value: f64, decimals: usize, rounding: Rounding) -> String {
+    format!("{:.*}", decimals, rounding.round(value, decimals as i32))
+}
+
+/// Formats a percentage shown to users, e.g. `87.5%`.
+///
+/// The percentages of the text outputs, the summary and the threshold checks go through here;
+/// the HTML report rounds its own in the templates.
+pub fn format_percent(percent: f64, rounding: Rounding) -> String {
+    format!("{}%", format_rounded(percent, 1, rounding))
+}
+
+/// Formats the difference between two percentages, always with a sign, e.g. `+2.5%`.
+pub fn format_percent_delta(delta: f64, rounding: Rounding) -> String {
+    format!("{:+.1}%", rounding.round(delta, 1))
+}
+
+/// The line endings of the outputs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineEnding {
+    /// A line feed, whatever the platform.
+    Lf,
+    /// A carriage return and a line feed, e.g. for golden files checked out on Windows.
+    Crlf,
+}
+
+/// The formats of the outputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OutputType {
+    Ade,
+    Lcov,
+    Coveralls,
+    CoverallsPlus,
+    Files,
+    Covdir,
+    Json,
+    Ndjson,
+    JsonSummary,
+    Treemap,
+    Tests,
+    Html,
+    Cobertura,
+    Markdown,
+    Uncovered,
+    Hotspots,
+    Patch,
+}
+
+impl OutputType {
+    /// The name of the file (or of the directory for HTML) of the output, when several
+    /// outputs are written into a directory.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            OutputType::Ade => "ade.json",
+            OutputType::Lcov => "lcov.info",
+            OutputType::Coveralls => "coveralls.json",
+            OutputType::CoverallsPlus => "coveralls-plus.json",
+            OutputType::Files => "files.txt",
+            OutputType::Covdir => "covdir.json",
+            OutputType::Json => "grcov.json",
+            OutputType::Ndjson => "grcov.ndjson",
+            OutputType::JsonSummary => "summary.json",
+            OutputType::Treemap => "treemap.json",
+            OutputType::Tests => "tests.json",
+            OutputType::Html => "html",
+            OutputType::Cobertura => "cobertura.xml",
+            OutputType::Markdown => "coverage.md",
+            OutputType::Uncovered => "uncovered.txt",
+            OutputType::Hotspots => "hotspots.txt",
+            OutputType::Patch => "patch.json",
+        }
+    }
+}
+
+impl FromStr for OutputType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "ade" => OutputType::Ade,
+            "lcov" => OutputType::Lcov,
+            "coveralls" => OutputType::Coveralls,
+            "coveralls+" => OutputType::CoverallsPlus,
+            "files" => OutputType::Files,
+            "covdir" => OutputType::Covdir,
+            "json" => OutputType::Json,
+            "ndjson" => OutputType::Ndjson,
+            "json-summary" => OutputType::JsonSummary,
+            "treemap" => OutputType::Treemap,
+            "tests" => OutputType::Tests,
+            "html" => OutputType::Html,
+            "cobertura" => OutputType::Cobertura,
+            "markdown" => OutputType::Markdown,
+            "uncovered" => OutputType::Uncovered,
+            "hotspots" => OutputType::Hotspots,
+            "patch" => OutputType::Patch,
+            _ => return Err(format!("{} is not a supported output type", s)),
+        })
+    }
+}
+
+/// Writes a carriage return before each line feed which doesn't already follow one.
+pub struct CrlfWriter<W: Write> {
+    inner: W,
+    last: u8,
+}
+
+impl<W: Write> CrlfWriter<W> {
+    pub fn new(inner: W) -> Self {
+        CrlfWriter { inner, last: 0 }
+    }
+}
+
+impl<W: Write> Write for CrlfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut start = 0;
+        for (i, &byte) in buf.iter().enumerate() {
+            let previous = if i > 0 { buf[i - 1] } else { self.last };
+            if byte == b'\n' && previous != b'\r' {
+                self.inner.write_all(&buf[start..i])?;
+                self.inner.write_all(b"\r")?;
+                start = i;
+            }
+        }
+        self.inner.write_all(&buf[start..])?;
+        if let Some(&byte) = buf.last() {
+            self.last = byte;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A writer of an output, to finish once everything was written.
+pub trait OutputWrite: Write {
+    /// Flushes the output and, for the files written through a temporary file, renames it
+    /// into place.
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+impl OutputWrite for File {
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl OutputWrite for io::Stdout {
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl OutputWrite for GzEncoder<Box<dyn OutputWrite>> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        // The gzip trailer is written before the file is finished.
+        GzEncoder::finish(*self)?.finish()
+    }
+}
+
+impl OutputWrite for CrlfWriter<Box<dyn OutputWrite>> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        self.inner.finish()
+    }
+}
+
+/// Finishes an output written through a `BufWriter`.
+pub fn finish_output(writer: BufWriter<Box<dyn OutputWrite>>) -> io::Result<()> {
+    writer
+        .into_inner()
+        .map_err(io::IntoInnerError::into_error)?
+        .finish()
+}
+
+/// Writes a file through a temporary sibling file, renamed into place when the writer is
+/// finished, for the consumers watching the file to never read a partial output. The temporary
+/// file is removed instead if the writer is dropped before, e.g. on a write error.
+pub struct AtomicFile {
+    file: Option<File>,
+    tmp_path: PathBuf,
+    path: PathBuf,
+}
+
+impl AtomicFile {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file_name = path.file_name().unwrap_or_else(|| OsStr::new("output"));
+        let mut tmp_name = OsString::from(".");
+        tmp_name.push(file_name);
+        tmp_name.push(format!(".{}.tmp", Uuid::new_v4()));
+        let tmp_path = path.with_file_name(tmp_name);
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)?;
+        Ok(AtomicFile {
+            file: Some(file),
+            tmp_path,
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.as_mut().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.as_mut().unwrap().flush()
+    }
+}
+
+impl OutputWrite for AtomicFile {
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        let mut file = self.file.take().unwrap();
+        file.flush()?;
+        // Close the file before renaming it, which Windows requires.
+        drop(file);
+        fs::rename(&self.tmp_path, &self.path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Cannot write {}: {}", self.path.display(), e),
+            )
+        })
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        // The temporary file is left when it was renamed into place.
+        drop(self.file.take());
+        if self.tmp_path.exists() {
+            let _ = fs::remove_file(&self.tmp_path);
+        }
+    }
+}
+
+pub fn get_target_output_writable(
+    output_file: Option<&str>,
+    line_ending: LineEnding,
+) -> io::Result<Box<dyn OutputWrite>> {
+    let write_target: Box<dyn OutputWrite> = match output_file {
         Some(filename) => {
             let output = PathBuf::from(filename);
             if output.is_dir() {
-                panic!(
+                return Err(io::Error::other(format!(
                     "The output file {} is a directory, but must be a regular file.",
                     filename
-                )
+                )));
             }
-            Box::new(File::create(&output).unwrap_or_else(|_| {
-                let parent = output.parent();
-                if let Some(parent_path) = parent {
-                    if !parent_path.exists() {
-                        panic!(
-                            "Cannot create {} to dump coverage data, as {} doesn't exist",
-                            filename,
-                            parent_path.display()
+            // Create the missing parent directories, so that e.g. `-o build/reports/lcov.info`
+            // works without a separate mkdir step.
+            if let Some(parent_path) = output.parent() {
+                if !parent_path.as_os_str().is_empty() && !parent_path.exists() {
+                    fs::create_dir_all(parent_path).map_err(|e| {
+                        io::Error::new(
+                            e.kind(),
+                            format!(
+                                "Cannot create {} to dump coverage data, as the directory {} can't be created: {}",
+                                filename,
+                                parent_path.display(),
+                                e
+                            ),
                         )
-                    }
+                    })?;
                 }
-                panic!("Cannot create the file {} to dump coverage data.", filename)
-            }))
+            }
+            let cannot_create = |e: io::Error| {
+                io::Error::new(
+                    e.kind(),
+                    format!(
+                        "Cannot create the file {} to dump coverage data: {}",
+                        filename, e
+                    ),
+                )
+            };
+            // The special files, e.g. /dev/stdout or named pipes, are written directly.
+            let file: Box<dyn OutputWrite> = if fs::metadata(&output).map_or(true, |m| m.is_file())
+            {
+                Box::new(AtomicFile::create(&output).map_err(cannot_create)?)
+            } else {
+                Box::new(File::create(&output).map_err(cannot_create)?)
+            };
+            if output.extension() == Some(OsStr::new("gz")) {
+                Box::new(GzEncoder::new(file, Compression::default()))
+            } else {
+                file
+            }
         }
         None => {
             let stdout = io::stdout();
             Box::new(stdout)
         }
     };
-    write_target
+    if line_ending == LineEnding::Crlf {
+        Ok(Box::new(CrlfWriter::new(write_target)))
+    } else {
+        Ok(write_target)
+    }
 }
 
-pub fn output_activedata_etl(results: CovResultIter, output_file: Option<&str>, demangle: bool) {
+pub fn output_activedata_etl(
+    results: CovResultIter,
+    output_file: Option<&str>,
+    line_ending: LineEnding,
+    demangle: bool,
+) -> io::Result<()> {
     let demangle_options = DemangleOptions::name_only();
-    let mut writer = BufWriter::new(get_target_output_writable(output_file));
+    let mut writer = BufWriter::new(get_target_output_writable(output_file, line_ending)?);
 
     for (_, rel_path, result) in results {
         let covered: Vec<u32> = result
@@ -144,7 +471,7 @@ pub fn output_activedata_etl(results: CovResultIter, output_file: Option<&str>,
                         "percentage_covered": lines_covered.len() as f32 / (lines_covered.len() + lines_uncovered.len()) as f32,
                     }
                 })
-            ).unwrap();
+            )?;
         }
 
         let orphan_covered: Vec<u32> = orphan_covered.into_iter().collect();
@@ -173,12 +500,18 @@ pub fn output_activedata_etl(results: CovResultIter, output_file: Option<&str>,
                     "percentage_covered": orphan_covered.len() as f32 / (orphan_covered.len() + orphan_uncovered.len()) as f32,
                 }
             })
-        ).unwrap();
+        )?;
     }
+
+    finish_output(writer)
 }
 
-pub fn output_covdir(results: CovResultIter, output_file: Option<&str>) {
-    let mut writer = BufWriter::new(get_target_output_writable(output_file));
+pub fn output_covdir(
+    results: CovResultIter,
+    output_file: Option<&str>,
+    line_ending: LineEnding,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(get_target_output_writable(output_file, line_ending)?);
     let mut relative: FxHashMap<PathBuf, Rc<RefCell<CDDirStats>>> = FxHashMap::default();
     let global = Rc::new(RefCell::new(CDDirStats::new("".to_string())));
     relative.insert(PathBuf::from(""), global.clone());
@@ -221,91 +554,260 @@ pub fn output_covdir(results: CovResultIter, output_file: Option<&str>) {
             };
         }
 
-        prev_stats.borrow_mut().files.push(CDFileStats::new(
-            path.file_name().unwrap().to_str().unwrap().to_string(),
-            result.lines,
-        ));
+        prev_stats.borrow_mut().files.push(CDFileStats {
+            excluded: result.excluded,
+            ..CDFileStats::new(
+                path.file_name().unwrap().to_str().unwrap().to_string(),
+                result.lines,
+            )
+        });
     }
 
     let mut global = global.take();
     global.set_stats();
 
-    serde_json::to_writer(&mut writer, &global.into_json()).unwrap();
+    serde_json::to_writer(&mut writer, &global.into_json())?;
+    finish_output(writer)
+}
+
+/// Writes the complete results, in a format which grcov can read back as input.
+///
+/// The header comes first, so that the producer can recognize the file from its beginning,
+/// and the files are streamed to the writer as they are consumed.
+pub fn output_json(
+    results: CovResultIter,
+    output_file: Option<&str>,
+    line_ending: LineEnding,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(get_target_output_writable(output_file, line_ending)?);
+    write_json(results, &mut writer)?;
+    finish_output(writer)
+}
+
+pub(crate) fn write_json<W: Write>(results: CovResultIter, writer: &mut W) -> io::Result<()> {
+    write!(writer, "{{\"format\":\"grcov\",\"version\":1,\"files\":[")?;
+
+    for (i, (_, rel_path, result)) in results.enumerate() {
+        // Sort the functions, so that the output is reproducible.
+        let functions: BTreeMap<&String, &Function> = result.functions.iter().collect();
+        let mut file = json!({
+            "name": rel_path,
+            "lines": result.lines,
+            "branches": result.branches,
+            "functions": functions,
+            "tests": result.tests,
+        });
+        // The hit counts of the branches are only known for some inputs.
+        if !result.branch_hits.is_empty() {
+            file["branch_hits"] = json!(result.branch_hits);
+        }
+        if i > 0 {
+            write!(writer, ",")?;
+        }
+        serde_json::to_writer(&mut *writer, &file)?;
+    }
+
+    write!(writer, "]}}")
+}
+
+/// Writes one JSON object per file and per line, for the consumers which stream the results
+/// (e.g. data pipelines ingesting newline-delimited JSON).
+pub fn output_ndjson(
+    results: CovResultIter,
+    output_file: Option<&str>,
+    line_ending: LineEnding,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(get_target_output_writable(output_file, line_ending)?);
+
+    for (_, rel_path, result) in results {
+        // Sort the functions, so that the output is reproducible.
+        let functions: BTreeMap<&String, bool> = result
+            .functions
+            .iter()
+            .map(|(name, function)| (name, function.executed))
+            .collect();
+        let mut file = json!({
+            "name": rel_path,
+            "lines": result.lines,
+            "branches": result.branches,
+            "functions": functions,
+        });
+        // The hit counts of the branches are only known for some inputs.
+        if !result.branch_hits.is_empty() {
+            file["branch_hits"] = json!(result.branch_hits);
+        }
+        writeln!(writer, "{}", file)?;
+    }
+
+    finish_output(writer)
+}
+
+#[derive(Default)]
+struct TreemapNode {
+    children: BTreeMap<String, TreemapNode>,
+    lines: BTreeMap<u32, u64>,
+}
+
+impl TreemapNode {
+    fn into_json(self, name: String) -> Value {
+        let children: Vec<Value> = if self.children.is_empty() {
+            self.lines
+                .into_iter()
+                .map(|(line, hits)| json!({ "name": line.to_string(), "value": hits }))
+                .collect()
+        } else {
+            self.children
+                .into_iter()
+                .map(|(name, child)| child.into_json(name))
+                .collect()
+        };
+        json!({
+            "name": name,
+            "children": children,
+        })
+    }
+}
+
+/// Writes the hit counts as a hierarchy of directories, files and lines, in the format of the
+/// treemap and flamegraph viewers (e.g. d3-hierarchy): the hit count of each line is its weight.
+pub fn output_treemap(
+    results: CovResultIter,
+    output_file: Option<&str>,
+    line_ending: LineEnding,
+) -> io::Result<()> {
+    let mut root = TreemapNode::default();
+    for (_, rel_path, result) in results {
+        let mut node = &mut root;
+        for component in rel_path.iter() {
+            node = node
+                .children
+                .entry(component.to_string_lossy().into_owned())
+                .or_default();
+        }
+        node.lines = result.lines;
+    }
+
+    let mut writer = BufWriter::new(get_target_output_writable(output_file, line_ending)?);
+    serde_json::to_writer(&mut writer, &root.into_json("".to_string()))?;
+    finish_output(writer)
+}
+
+/// Writes the files hit by each test, for the inputs which carry test names.
+pub fn output_tests(
+    results: CovResultIter,
+    output_file: Option<&str>,
+    line_ending: LineEnding,
+) -> io::Result<()> {
+    let mut tests: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for (_, rel_path, result) in results {
+        for test in result.tests {
+            tests.entry(test).or_default().push(rel_path.clone());
+        }
+    }
+    for files in tests.values_mut() {
+        files.sort();
+    }
+
+    let mut writer = BufWriter::new(get_target_output_writable(output_file, line_ending)?);
+    serde_json::to_writer(&mut writer, &tests)?;
+    finish_output(writer)
 }
 
-pub fn output_lcov(results: CovResultIter, output_file: Option<&str>, demangle: bool) {
+pub fn output_lcov(
+    results: CovResultIter,
+    output_file: Option<&str>,
+    line_ending: LineEnding,
+    demangle: bool,
+) -> io::Result<()> {
     let demangle_options = DemangleOptions::name_only();
-    let mut writer = BufWriter::new(get_target_output_writable(output_file));
-    writer.write_all(b"TN:\n").unwrap();
+    let mut writer = BufWriter::new(get_target_output_writable(output_file, line_ending)?);
+    writer.write_all(b"TN:\n")?;
 
     for (_, rel_path, result) in results {
         // println!("{} {:?}", rel_path, result.lines);
 
-        writeln!(writer, "SF:{}", rel_path.display()).unwrap();
+        writeln!(writer, "SF:{}", rel_path.display())?;
 
-        for (name, function) in &result.functions {
+        // Sort the functions by their start, so that the output is reproducible.
+        let mut functions: Vec<(&String, &Function)> = result.functions.iter().collect();
+        functions.sort_by_key(|&(name, function)| (function.start, name));
+        for &(name, function) in &functions {
             writeln!(
                 writer,
                 "FN:{},{}",
                 function.start,
                 demangle!(name, demangle, demangle_options)
-            )
-            .unwrap();
+            )?;
         }
-        for (name, function) in &result.functions {
+        for &(name, function) in &functions {
             writeln!(
                 writer,
                 "FNDA:{},{}",
                 if function.executed { 1 } else { 0 },
                 demangle!(name, demangle, demangle_options)
-            )
-            .unwrap();
+            )?;
         }
         if !result.functions.is_empty() {
-            writeln!(writer, "FNF:{}", result.functions.len()).unwrap();
+            writeln!(writer, "FNF:{}", result.functions.len())?;
             writeln!(
                 writer,
                 "FNH:{}",
                 result.functions.values().filter(|x| x.executed).count()
-            )
-            .unwrap();
+            )?;
         }
 
         // branch coverage information
         let mut branch_count = 0;
         let mut branch_hit = 0;
-        for (line, taken) in &result.branches {
+        // Each line with branches gets its own block, in which the conditions are numbered.
+        let branches = result.branches.iter().filter(|(_, taken)| is_branch(taken));
+        for (block, (line, taken)) in branches.enumerate() {
             branch_count += taken.len();
             for (n, b_t) in taken.iter().enumerate() {
                 writeln!(
                     writer,
-                    "BRDA:{},0,{},{}",
+                    "BRDA:{},{},{},{}",
                     line,
+                    block,
                     n,
-                    if *b_t { "1" } else { "-" }
-                )
-                .unwrap();
+                    if *b_t {
+                        taken_count(&result, *line, n).to_string()
+                    } else {
+                        "-".to_string()
+                    }
+                )?;
                 if *b_t {
                     branch_hit += 1;
                 }
             }
         }
 
-        writeln!(writer, "BRF:{}", branch_count).unwrap();
-        writeln!(writer, "BRH:{}", branch_hit).unwrap();
+        writeln!(writer, "BRF:{}", branch_count)?;
+        writeln!(writer, "BRH:{}", branch_hit)?;
 
         for (line, execution_count) in &result.lines {
-            writeln!(writer, "DA:{},{}", line, execution_count).unwrap();
+            writeln!(writer, "DA:{},{}", line, execution_count)?;
         }
-        writeln!(writer, "LF:{}", result.lines.len()).unwrap();
+        writeln!(writer, "LF:{}", result.lines.len())?;
         writeln!(
             writer,
             "LH:{}",
             result.lines.values().filter(|&v| *v > 0).count()
-        )
-        .unwrap();
-        writer.write_all(b"end_of_record\n").unwrap();
+        )?;
+        writer.write_all(b"end_of_record\n")?;
     }
+
+    finish_output(writer)
+}
+
+/// Returns how many times a taken branch was taken: its hit count for the inputs which have
+/// them, else 1.
+fn taken_count(result: &CovResult, line: u32, n: usize) -> u64 {
+    result
+        .branch_hits
+        .get(&line)
+        .and_then(|hits| hits.get(n))
+        .map_or(1, |&hits| hits.max(1))
 }
 
 fn get_digest(path: PathBuf) -> String {
@@ -419,14 +921,41 @@ pub fn output_coveralls(
     commit_sha: &str,
     with_function_info: bool,
     output_file: Option<&str>,
+    line_ending: LineEnding,
     vcs_branch: &str,
     parallel: bool,
     demangle: bool,
-) {
+) -> io::Result<()> {
     let demangle_options = DemangleOptions::name_only();
-    let mut source_files = Vec::new();
+    let git = get_coveralls_git_info(commit_sha, vcs_branch);
 
-    for (abs_path, rel_path, result) in results {
+    let mut header = json!({
+        "git": git,
+        "service_number": service_number,
+        "service_pull_request": service_pull_request,
+        "parallel": parallel,
+    });
+
+    if let (Some(repo_token), Some(obj)) = (repo_token, header.as_object_mut()) {
+        obj.insert("repo_token".to_string(), json!(repo_token));
+    }
+
+    if let (Some(service_name), Some(obj)) = (service_name, header.as_object_mut()) {
+        obj.insert("service_name".to_string(), json!(service_name));
+    }
+
+    if let (Some(service_job_id), Some(obj)) = (service_job_id, header.as_object_mut()) {
+        obj.insert("service_job_id".to_string(), json!(service_job_id));
+    }
+
+    // The source files are streamed to the writer as they are consumed, rather than kept in
+    // memory. serde_json sorts the keys of objects, "source_files" comes last: the output is
+    // the same as serializing the whole object at once.
+    let header = serde_json::to_string(&header).unwrap();
+    let mut writer = BufWriter::new(get_target_output_writable(output_file, line_ending)?);
+    write!(writer, "{},\"source_files\":[", &header[..header.len() - 1])?;
+
+    for (i, (abs_path, rel_path, result)) in results.enumerate() {
         let end: u32 = result.lines.keys().last().unwrap_or(&0) + 1;
 
         let mut coverage = Vec::new();
@@ -440,22 +969,26 @@ pub fn output_coveralls(
         }
 
         let mut branches = Vec::new();
-        for (line, taken) in &result.branches {
+        for (line, taken) in result.branches.iter().filter(|(_, taken)| is_branch(taken)) {
             for (n, b_t) in taken.iter().enumerate() {
-                branches.push(*line);
+                branches.push(u64::from(*line));
                 branches.push(0);
-                branches.push(n as u32);
-                branches.push(if *b_t { 1 } else { 0 });
+                branches.push(n as u64);
+                branches.push(if *b_t {
+                    taken_count(&result, *line, n)
+                } else {
+                    0
+                });
             }
         }
 
-        if !with_function_info {
-            source_files.push(json!({
+        let source_file = if !with_function_info {
+            json!({
                 "name": rel_path,
                 "source_digest": get_digest(abs_path),
                 "coverage": coverage,
                 "branches": branches,
-            }));
+            })
         } else {
             let mut functions = Vec::new();
             for (name, function) in &result.functions {
@@ -466,47 +999,101 @@ pub fn output_coveralls(
                 }));
             }
 
-            source_files.push(json!({
+            json!({
                 "name": rel_path,
                 "source_digest": get_digest(abs_path),
                 "coverage": coverage,
                 "branches": branches,
                 "functions": functions,
-            }));
+            })
+        };
+
+        if i > 0 {
+            writer.write_all(b",")?;
         }
+        serde_json::to_writer(&mut writer, &source_file)?;
     }
 
-    let git = get_coveralls_git_info(commit_sha, vcs_branch);
-
-    let mut result = json!({
-        "git": git,
-        "source_files": source_files,
-        "service_number": service_number,
-        "service_pull_request": service_pull_request,
-        "parallel": parallel,
-    });
+    writer.write_all(b"]}")?;
+    finish_output(writer)
+}
 
-    if let (Some(repo_token), Some(obj)) = (repo_token, result.as_object_mut()) {
-        obj.insert("repo_token".to_string(), json!(repo_token));
-    }
+/// Line coverage status of a file, to restrict the list of files.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileStatus {
+    /// All the lines are covered.
+    Full,
+    /// Some of the lines are covered, but not all.
+    Partial,
+    /// None of the lines are covered, or the file has no instrumented lines.
+    Zero,
+}
 
-    if let (Some(service_name), Some(obj)) = (service_name, result.as_object_mut()) {
-        obj.insert("service_name".to_string(), json!(service_name));
+impl FileStatus {
+    pub fn of(result: &CovResult) -> FileStatus {
+        let covered = result.lines.values().filter(|&&v| v > 0).count();
+        if covered == 0 {
+            FileStatus::Zero
+        } else if covered == result.lines.len() {
+            FileStatus::Full
+        } else {
+            FileStatus::Partial
+        }
     }
+}
 
-    if let (Some(service_job_id), Some(obj)) = (service_job_id, result.as_object_mut()) {
-        obj.insert("service_job_id".to_string(), json!(service_job_id));
+pub fn output_files(
+    results: CovResultIter,
+    output_file: Option<&str>,
+    line_ending: LineEnding,
+    status: Option<FileStatus>,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(get_target_output_writable(output_file, line_ending)?);
+    for (_, rel_path, result) in results {
+        if status.is_none() || status == Some(FileStatus::of(&result)) {
+            writeln!(writer, "{}", rel_path.display())?;
+        }
     }
 
-    let mut writer = BufWriter::new(get_target_output_writable(output_file));
-    serde_json::to_writer(&mut writer, &result).unwrap();
+    finish_output(writer)
 }
 
-pub fn output_files(results: CovResultIter, output_file: Option<&str>) {
-    let mut writer = BufWriter::new(get_target_output_writable(output_file));
-    for (_, rel_path, _) in results {
-        writeln!(writer, "{}", rel_path.display()).unwrap();
-    }
+/// Writes the same results with several writers at once, each running in its own thread.
+///
+/// The results are shared between the threads, every writer gets its own iterator over them.
+/// All the writers run to their end, the first error is returned.
+pub fn output_concurrently(
+    results: Vec<(PathBuf, PathBuf, CovResult)>,
+    writers: Vec<OutputWriter>,
+) -> io::Result<()> {
+    let results = Arc::new(results);
+
+    // The writers may borrow e.g. the options of the outputs, they run in scoped threads.
+    thread::scope(|scope| {
+        let mut threads = Vec::with_capacity(writers.len());
+        for (i, writer) in writers.into_iter().enumerate() {
+            let results = Arc::clone(&results);
+            let t = thread::Builder::new()
+                .name(format!("Output {}", i))
+                .spawn_scoped(scope, move || {
+                    let iterator: CovResultIter =
+                        Box::new((0..results.len()).map(move |i| results[i].clone()));
+                    writer(iterator)
+                })
+                .unwrap();
+
+            threads.push(t);
+        }
+
+        let mut written = Ok(());
+        for t in threads {
+            let thread_written = t
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("An output writer panicked")));
+            written = written.and(thread_written);
+        }
+        written
+    })
 }
 
 pub fn output_html(
@@ -514,7 +1101,7 @@ pub fn output_html(
     output_dir: Option<&str>,
     num_threads: usize,
     branch_enabled: bool,
-) {
+) -> io::Result<()> {
     let output = if let Some(output_dir) = output_dir {
         PathBuf::from(output_dir)
     } else {
@@ -523,12 +1110,18 @@ pub fn output_html(
 
     if output.exists() {
         if !output.is_dir() {
-            eprintln!("{} is not a directory", output.to_str().unwrap());
-            return;
+            return Err(io::Error::other(format!(
+                "{} is not a directory",
+                output.display()
+            )));
         }
-    } else if std::fs::create_dir(&output).is_err() {
-        eprintln!("Cannot create directory {}", output.to_str().unwrap());
-        return;
+    } else {
+        fs::create_dir_all(&output).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Cannot create directory {}: {}", output.display(), e),
+            )
+        })?;
     }
 
     let (sender, receiver) = unbounded();
@@ -545,14 +1138,20 @@ pub fn output_html(
         let t = thread::Builder::new()
             .name(format!("Consumer HTML {}", i))
             .spawn(move || {
-                html::consumer_html(&tera, receiver, stats, &output, config, branch_enabled);
+                html::consumer_html(&tera, receiver, stats, &output, config, branch_enabled)
             })
             .unwrap();
 
         threads.push(t);
     }
 
+    // Several results can be the same file through symbolic links, annotate it only once.
+    let mut visited = FxHashSet::default();
     for (abs_path, rel_path, result) in results {
+        let canonical_path = canonicalize_path(&abs_path).unwrap_or_else(|_| abs_path.clone());
+        if !visited.insert(canonical_path) {
+            continue;
+        }
         sender
             .send(Some(HtmlItem {
                 abs_path,
@@ -566,21 +1165,34 @@ pub fn output_html(
         sender.send(None).unwrap();
     }
 
+    let mut written = Ok(());
     for t in threads {
-        if t.join().is_err() {
-            process::exit(1);
+        match t.join() {
+            Ok(file_written) => {
+                if written.is_ok() {
+                    written = file_written;
+                }
+            }
+            Err(_) => {
+                if written.is_ok() {
+                    written = Err(io::Error::other(
+                        "A thread writing the HTML report panicked.",
+                    ));
+                }
+            }
         }
     }
+    written?;
 
     let global = Arc::try_unwrap(stats).unwrap().into_inner().unwrap();
 
-    html::gen_index(&tera, &global, &config, &output, branch_enabled);
+    html::gen_index(&tera, &global, &config, &output, branch_enabled)?;
 
     for style in html::BadgeStyle::iter() {
-        html::gen_badge(&tera, &global.stats, &config, &output, style);
+        html::gen_badge(&tera, &global.stats, &config, &output, style)?;
     }
 
-    html::gen_coverage_json(&global.stats, &config, &output);
+    html::gen_coverage_json(&global.stats, &config, &output)
 }
 
 #[cfg(test)]
@@ -598,48 +1210,716 @@ mod tests {
     }
 
     #[test]
-    fn test_lcov_brf_brh() {
-        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
-        let file_name = "test_lcov_brf_brh.info";
-        let file_path = tmp_dir.path().join(&file_name);
-
-        let results = vec![(
-            PathBuf::from("foo/bar/a.cpp"),
-            PathBuf::from("foo/bar/a.cpp"),
-            CovResult {
-                lines: [(1, 10), (2, 11)].iter().cloned().collect(),
-                branches: {
-                    let mut map = BTreeMap::new();
-                    // 3 hit branches over 10
-                    map.insert(1, vec![true, false, false, true, false, false]);
-                    map.insert(2, vec![false, false, false, true]);
-                    map
-                },
-                functions: FxHashMap::default(),
-            },
-        )];
-
-        let results = Box::new(results.into_iter());
-        output_lcov(results, Some(file_path.to_str().unwrap()), false);
-
-        let results = read_file(&file_path);
+    fn test_format_percent() {
+        assert_eq!(format_percent(87.5, Rounding::HalfEven), "87.5%");
+        assert_eq!(format_percent(100.0, Rounding::HalfEven), "100.0%");
+        assert_eq!(format_percent(100.0 / 3.0, Rounding::HalfEven), "33.3%");
+        assert_eq!(format_percent_delta(2.25, Rounding::HalfEven), "+2.2%");
+        assert_eq!(format_percent_delta(-12.5, Rounding::HalfEven), "-12.5%");
+        assert_eq!(format_percent_delta(0.0, Rounding::HalfEven), "+0.0%");
+    }
 
-        assert!(results.contains("BRF:10\n"));
-        assert!(results.contains("BRH:3\n"));
+    #[test]
+    fn test_rounding() {
+        assert_eq!(Rounding::HalfUp.round(0.875, 2), 0.88);
+        assert_eq!(Rounding::HalfEven.round(0.875, 2), 0.88);
+        assert_eq!(Rounding::Truncate.round(0.875, 2), 0.87);
+
+        // 0.865 isn't exact in binary, it's still a tie.
+        assert_eq!(Rounding::HalfUp.round(0.865, 2), 0.87);
+        assert_eq!(Rounding::HalfEven.round(0.865, 2), 0.86);
+        assert_eq!(Rounding::Truncate.round(0.865, 2), 0.86);
+
+        assert_eq!(Rounding::HalfUp.round(-0.125, 2), -0.13);
+        assert_eq!(Rounding::HalfEven.round(-0.125, 2), -0.12);
+        assert_eq!(Rounding::Truncate.round(-0.125, 2), -0.12);
+
+        // Not a tie, and not below an integer because of a rounding error.
+        assert_eq!(Rounding::HalfEven.round(0.8751, 2), 0.88);
+        assert_eq!(Rounding::Truncate.round(0.29, 2), 0.29);
+
+        assert_eq!(format_percent(12.25, Rounding::HalfUp), "12.3%");
+        assert_eq!(format_percent(12.25, Rounding::HalfEven), "12.2%");
+        assert_eq!(format_percent(12.29, Rounding::Truncate), "12.2%");
     }
 
+    #[cfg(unix)]
     #[test]
-    fn test_lcov_demangle() {
+    fn test_html_symlinks_annotated_once() {
         let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
-        let file_name = "test_lcov_demangle";
-        let file_path = tmp_dir.path().join(&file_name);
+        let source_path = tmp_dir.path().join("a.cpp");
+        let link_path = tmp_dir.path().join("link.cpp");
+        fs::write(&source_path, "int main() {\n  return 0;\n}\n").unwrap();
+        std::os::unix::fs::symlink(&source_path, &link_path).unwrap();
+
+        let result = || CovResult::with_lines(&[(1, 1), (2, 1)]);
+        let results = vec![
+            (source_path, PathBuf::from("a.cpp"), result()),
+            (link_path, PathBuf::from("link.cpp"), result()),
+        ];
+
+        let output_dir = tmp_dir.path().join("html");
+        output_html(
+            Box::new(results.into_iter()),
+            Some(output_dir.to_str().unwrap()),
+            2,
+            false,
+        )
+        .unwrap();
+
+        assert!(output_dir.join("a.cpp.html").exists());
+        assert!(!output_dir.join("link.cpp.html").exists());
+    }
+
+    #[test]
+    fn test_html_not_a_directory() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let output_path = tmp_dir.path().join("html");
+        fs::write(&output_path, "").unwrap();
+
+        let results: Vec<(PathBuf, PathBuf, CovResult)> = Vec::new();
+        let written = output_html(
+            Box::new(results.into_iter()),
+            Some(output_path.to_str().unwrap()),
+            1,
+            false,
+        );
+
+        assert!(written.is_err());
+    }
+
+    #[test]
+    fn test_html_branches() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let source_path = tmp_dir.path().join("a.cpp");
+        fs::write(
+            &source_path,
+            "int main(int argc) {\n  if (argc > 1) return 1;\n  if (argc > 2) return 2;\n  return argc ? 0 : 3;\n}\n",
+        )
+        .unwrap();
+
+        let results = vec![(
+            source_path,
+            PathBuf::from("a.cpp"),
+            CovResult {
+                lines: [(1, 1), (2, 1), (3, 1), (4, 1)].iter().cloned().collect(),
+                branches: [
+                    (1, vec![true]),
+                    (2, vec![true, true]),
+                    (3, vec![true, false]),
+                    (4, vec![false, false]),
+                ]
+                .iter()
+                .cloned()
+                .collect(),
+                ..Default::default()
+            },
+        )];
+
+        let output_dir = tmp_dir.path().join("html");
+        output_html(
+            Box::new(results.into_iter()),
+            Some(output_dir.to_str().unwrap()),
+            1,
+            true,
+        )
+        .unwrap();
+
+        let html = read_file(&output_dir.join("a.cpp.html"));
+        assert!(html.contains(r#"class="tag is-success" title="2/2 branches taken""#));
+        assert!(html.contains(r#"class="tag is-warning" title="1/2 branches taken""#));
+        assert!(html.contains(r#"class="tag is-danger" title="0/2 branches taken""#));
+        // The line with a single condition has no branches.
+        assert!(!html.contains("1/1 branches taken"));
+    }
+
+    #[test]
+    fn test_crlf_writer() {
+        let mut bytes = Vec::new();
+        {
+            let mut writer = CrlfWriter::new(&mut bytes);
+            writer.write_all(b"SF:a.rs\nDA:1,1\r\n").unwrap();
+            // A carriage return and a line feed split between two writes.
+            writer.write_all(b"end_of_record\r").unwrap();
+            writer.write_all(b"\n\n").unwrap();
+        }
+        assert_eq!(bytes, b"SF:a.rs\r\nDA:1,1\r\nend_of_record\r\n\r\n");
+    }
+
+    #[test]
+    fn test_atomic_file() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("lcov.info");
+        fs::write(&file_path, "old").unwrap();
+        let entries = || fs::read_dir(tmp_dir.path()).unwrap().count();
+
+        let mut writer = AtomicFile::create(&file_path).unwrap();
+        writer.write_all(b"new").unwrap();
+        // The previous output is left as it is until the new one is complete.
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "old");
+        assert_eq!(entries(), 2);
+        Box::new(writer).finish().unwrap();
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "new");
+        assert_eq!(entries(), 1);
+
+        // The temporary file is removed when the writer isn't finished, e.g. on errors.
+        let mut writer = AtomicFile::create(&file_path).unwrap();
+        writer.write_all(b"partial").unwrap();
+        drop(writer);
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "new");
+        assert_eq!(entries(), 1);
+
+        // The rename errors are returned, e.g. over a directory which isn't empty.
+        let dir_path = tmp_dir.path().join("dir");
+        fs::create_dir(&dir_path).unwrap();
+        fs::write(dir_path.join("file"), "").unwrap();
+        let mut writer = AtomicFile::create(&dir_path).unwrap();
+        writer.write_all(b"new").unwrap();
+        assert!(Box::new(writer).finish().is_err());
+        assert_eq!(entries(), 2);
+    }
+
+    #[test]
+    fn test_output_gz() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("report.xml.gz");
+
+        let results = vec![(
+            PathBuf::from("foo/bar/a.cpp"),
+            PathBuf::from("foo/bar/a.cpp"),
+            CovResult {
+                lines: [(1, 10), (2, 0)].iter().cloned().collect(),
+                ..Default::default()
+            },
+        )];
+
+        let results = Box::new(results.into_iter());
+        crate::output_cobertura(
+            results,
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            crate::DemangleConfig::default(),
+            &crate::CoberturaConfig::default(),
+        )
+        .unwrap();
+
+        let mut xml = String::new();
+        flate2::read::GzDecoder::new(File::open(&file_path).unwrap())
+            .read_to_string(&mut xml)
+            .unwrap();
+        assert!(xml.starts_with("<?xml version=\"1.0\"?>"));
+        assert!(xml.contains(r#"filename="foo/bar/a.cpp""#));
+        assert!(xml.trim_end().ends_with("</coverage>"));
+    }
+
+    #[test]
+    fn test_output_creates_parent_directories() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir
+            .path()
+            .join("build")
+            .join("reports")
+            .join("lcov.info");
+
+        let results = vec![(
+            PathBuf::from("foo/bar/a.cpp"),
+            PathBuf::from("foo/bar/a.cpp"),
+            CovResult {
+                lines: [(1, 10), (2, 0)].iter().cloned().collect(),
+                ..Default::default()
+            },
+        )];
+
+        let results = Box::new(results.into_iter());
+        output_lcov(
+            results,
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            false,
+        )
+        .unwrap();
+
+        assert!(read_file(&file_path).contains("SF:foo/bar/a.cpp\n"));
+    }
+
+    #[test]
+    fn test_output_type_file_names() {
+        let names = [
+            "ade",
+            "lcov",
+            "coveralls",
+            "coveralls+",
+            "files",
+            "covdir",
+            "json",
+            "ndjson",
+            "json-summary",
+            "treemap",
+            "tests",
+            "html",
+            "cobertura",
+            "markdown",
+            "uncovered",
+            "hotspots",
+            "patch",
+        ];
+        let file_names: FxHashSet<&str> = names
+            .iter()
+            .map(|name| name.parse::<OutputType>().unwrap().file_name())
+            .collect();
+        assert_eq!(file_names.len(), names.len());
+
+        assert!("lcov+".parse::<OutputType>().is_err());
+    }
+
+    #[test]
+    fn test_output_to_a_directory() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+
+        let results: Vec<(PathBuf, PathBuf, CovResult)> = Vec::new();
+        let written = output_lcov(
+            Box::new(results.into_iter()),
+            Some(tmp_dir.path().to_str().unwrap()),
+            LineEnding::Lf,
+            false,
+        );
+
+        assert!(written.is_err());
+    }
+
+    #[test]
+    fn test_lcov_brf_brh() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_name = "test_lcov_brf_brh.info";
+        let file_path = tmp_dir.path().join(&file_name);
+
+        let results = vec![(
+            PathBuf::from("foo/bar/a.cpp"),
+            PathBuf::from("foo/bar/a.cpp"),
+            CovResult {
+                lines: [(1, 10), (2, 11)].iter().cloned().collect(),
+                branches: {
+                    let mut map = BTreeMap::new();
+                    // 3 hit branches over 10
+                    map.insert(1, vec![true, false, false, true, false, false]);
+                    map.insert(2, vec![false, false, false, true]);
+                    map
+                },
+                ..Default::default()
+            },
+        )];
+
+        let results = Box::new(results.into_iter());
+        output_lcov(
+            results,
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            false,
+        )
+        .unwrap();
+
+        let results = read_file(&file_path);
+
+        assert!(results.contains("BRF:10\n"));
+        assert!(results.contains("BRH:3\n"));
+    }
+
+    #[test]
+    fn test_lcov_functions() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_lcov_functions.info");
+
+        let mut functions: FunctionMap = FxHashMap::default();
+        for (name, start, executed) in &[("c", 9, false), ("a", 1, true), ("b", 5, true)] {
+            functions.insert(
+                name.to_string(),
+                Function {
+                    start: *start,
+                    executed: *executed,
+                    end: None,
+                },
+            );
+        }
+        let executed = functions.values().filter(|f| f.executed).count();
+        let results = vec![(
+            PathBuf::from("foo/bar/a.cpp"),
+            PathBuf::from("foo/bar/a.cpp"),
+            CovResult {
+                lines: [(1, 1), (5, 1), (9, 0)].iter().cloned().collect(),
+                functions,
+                ..Default::default()
+            },
+        )];
+
+        output_lcov(
+            Box::new(results.into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            false,
+        )
+        .unwrap();
+
+        let results = read_file(&file_path);
+        assert!(results.contains("FN:1,a\nFN:5,b\nFN:9,c\n"), "{}", results);
+        assert!(
+            results.contains("FNDA:1,a\nFNDA:1,b\nFNDA:0,c\n"),
+            "{}",
+            results
+        );
+        assert!(results.contains("FNF:3\n"));
+        assert!(results.contains(&format!("FNH:{}\n", executed)));
+    }
+
+    #[test]
+    fn test_lcov_branch_indices() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_lcov_branch_indices.info");
+
+        let results = vec![(
+            PathBuf::from("foo/bar/a.cpp"),
+            PathBuf::from("foo/bar/a.cpp"),
+            CovResult {
+                lines: [(1, 10), (2, 11)].iter().cloned().collect(),
+                branches: {
+                    let mut map = BTreeMap::new();
+                    map.insert(1, vec![true, false, false, true]);
+                    map.insert(2, vec![false, true]);
+                    map
+                },
+                // The branches of the second line have no hit counts.
+                branch_hits: [(1, vec![4, 0, 0, 2])].iter().cloned().collect(),
+                ..Default::default()
+            },
+        )];
+
+        let results = Box::new(results.into_iter());
+        output_lcov(
+            results,
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            false,
+        )
+        .unwrap();
+
+        let brda: Vec<String> = read_file(&file_path)
+            .lines()
+            .filter(|l| l.starts_with("BRDA:"))
+            .map(|l| l.to_string())
+            .collect();
+        assert_eq!(
+            brda,
+            vec![
+                "BRDA:1,0,0,4",
+                "BRDA:1,0,1,-",
+                "BRDA:1,0,2,-",
+                "BRDA:1,0,3,2",
+                "BRDA:2,1,0,-",
+                "BRDA:2,1,1,1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lcov_cobertura_branch_totals() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir
+            .path()
+            .join("test_lcov_cobertura_branch_totals.info");
+
+        let result = CovResult {
+            lines: [(1, 1), (2, 0)].iter().cloned().collect(),
+            branches: {
+                let mut map = BTreeMap::new();
+                map.insert(1, vec![true, false]);
+                map.insert(2, vec![false, false, false]);
+                // Not a branch.
+                map.insert(3, vec![true]);
+                map
+            },
+            ..Default::default()
+        };
+        let results = || {
+            Box::new(
+                vec![(
+                    PathBuf::from("foo/bar/a.cpp"),
+                    PathBuf::from("foo/bar/a.cpp"),
+                    result.clone(),
+                )]
+                .into_iter(),
+            )
+        };
+
+        output_lcov(
+            results(),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            false,
+        )
+        .unwrap();
+        let lcov = read_file(&file_path);
+        // One record per branch, the branches of a line which was never taken included.
+        assert_eq!(lcov.matches("BRDA:").count(), 5);
+        assert!(lcov.contains("BRDA:2,1,0,-\nBRDA:2,1,1,-\nBRDA:2,1,2,-\n"));
+        assert!(!lcov.contains("BRDA:3,"));
+        assert!(lcov.contains("BRF:5\n"));
+        assert!(lcov.contains("BRH:1\n"));
+
+        let cobertura = String::from_utf8(crate::cobertura_to_bytes(
+            results(),
+            crate::DemangleConfig::default(),
+            &crate::CoberturaConfig::default(),
+        ))
+        .unwrap();
+        assert!(cobertura.contains(r#"branches-valid="5""#));
+        assert!(cobertura.contains(r#"branches-covered="1""#));
+    }
+
+    #[test]
+    fn test_files_status() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_files_status.txt");
+
+        let results = vec![
+            (
+                PathBuf::from("full.cpp"),
+                PathBuf::from("full.cpp"),
+                CovResult::with_lines(&[(1, 3), (2, 1)]),
+            ),
+            (
+                PathBuf::from("partial.cpp"),
+                PathBuf::from("partial.cpp"),
+                CovResult::with_lines(&[(1, 0), (2, 7)]),
+            ),
+            (
+                PathBuf::from("zero.cpp"),
+                PathBuf::from("zero.cpp"),
+                CovResult::with_lines(&[(1, 0)]),
+            ),
+        ];
+
+        output_files(
+            Box::new(results.clone().into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            Some(FileStatus::Partial),
+        )
+        .unwrap();
+        assert_eq!(read_file(&file_path), "partial.cpp\n");
+
+        output_files(
+            Box::new(results.into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            None,
+        )
+        .unwrap();
+        assert_eq!(read_file(&file_path), "full.cpp\npartial.cpp\nzero.cpp\n");
+    }
+
+    #[test]
+    fn test_files_status_no_lines() {
+        let result = CovResult::with_lines(&[]);
+        assert_eq!(FileStatus::of(&result), FileStatus::Zero);
+    }
+
+    #[test]
+    fn test_tests() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_tests.json");
+
+        let lcov = b"TN:test_a
+SF:src/a.rs
+DA:1,1
+end_of_record
+SF:src/b.rs
+DA:1,0
+end_of_record
+TN:test_b
+SF:src/b.rs
+DA:1,2
+end_of_record
+SF:src/c.rs
+DA:1,3
+end_of_record
+"
+        .to_vec();
+        let mut result_map: CovResultMap = FxHashMap::default();
+        for (name, result) in crate::parse_lcov(lcov, false).unwrap() {
+            match result_map.entry(name) {
+                hash_map::Entry::Occupied(obj) => {
+                    crate::merge_results(obj.into_mut(), result);
+                }
+                hash_map::Entry::Vacant(v) => {
+                    v.insert(result);
+                }
+            }
+        }
+        let results: Vec<_> = result_map
+            .into_iter()
+            .map(|(name, result)| (PathBuf::from(&name), PathBuf::from(&name), result))
+            .collect();
+
+        output_tests(
+            Box::new(results.into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+        )
+        .unwrap();
+
+        let tests: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+        assert_eq!(
+            tests,
+            json!({
+                "test_a": ["src/a.rs"],
+                "test_b": ["src/b.rs", "src/c.rs"],
+            })
+        );
+    }
+
+    #[test]
+    fn test_treemap() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_treemap.json");
+
+        let results = vec![
+            (
+                PathBuf::from("foo/b.cpp"),
+                PathBuf::from("foo/b.cpp"),
+                CovResult::with_lines(&[(1, 3)]),
+            ),
+            (
+                PathBuf::from("bar/c.cpp"),
+                PathBuf::from("bar/c.cpp"),
+                CovResult::with_lines(&[(2, 0), (4, 7)]),
+            ),
+            (
+                PathBuf::from("foo/a.cpp"),
+                PathBuf::from("foo/a.cpp"),
+                CovResult::with_lines(&[(5, 1)]),
+            ),
+        ];
+        output_treemap(
+            Box::new(results.into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+        )
+        .unwrap();
+
+        let treemap: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+        assert_eq!(
+            treemap,
+            json!({
+                "name": "",
+                "children": [
+                    {
+                        "name": "bar",
+                        "children": [
+                            {
+                                "name": "c.cpp",
+                                "children": [
+                                    { "name": "2", "value": 0 },
+                                    { "name": "4", "value": 7 },
+                                ],
+                            },
+                        ],
+                    },
+                    {
+                        "name": "foo",
+                        "children": [
+                            {
+                                "name": "a.cpp",
+                                "children": [{ "name": "5", "value": 1 }],
+                            },
+                            {
+                                "name": "b.cpp",
+                                "children": [{ "name": "1", "value": 3 }],
+                            },
+                        ],
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_ndjson() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_ndjson.json");
+
+        let mut functions: FunctionMap = FxHashMap::default();
+        functions.insert(
+            "f".to_string(),
+            Function {
+                start: 1,
+                executed: true,
+                end: None,
+            },
+        );
+        let results = vec![
+            (
+                PathBuf::from("foo/a.cpp"),
+                PathBuf::from("foo/a.cpp"),
+                CovResult {
+                    lines: [(1, 3), (2, 0)].iter().cloned().collect(),
+                    branches: [(1, vec![true, false])].iter().cloned().collect(),
+                    branch_hits: [(1, vec![2, 0])].iter().cloned().collect(),
+                    functions,
+                    ..Default::default()
+                },
+            ),
+            (
+                PathBuf::from("foo/b.cpp"),
+                PathBuf::from("foo/b.cpp"),
+                CovResult {
+                    lines: [(5, 1)].iter().cloned().collect(),
+                    ..Default::default()
+                },
+            ),
+        ];
+        output_ndjson(
+            Box::new(results.into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+        )
+        .unwrap();
+
+        let output = read_file(&file_path);
+        let files: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                json!({
+                    "name": "foo/a.cpp",
+                    "lines": { "1": 3, "2": 0 },
+                    "branches": { "1": [true, false] },
+                    "branch_hits": { "1": [2, 0] },
+                    "functions": { "f": true },
+                }),
+                json!({
+                    "name": "foo/b.cpp",
+                    "lines": { "5": 1 },
+                    "branches": {},
+                    "functions": {},
+                }),
+            ]
+        );
+        // The hit counts are integers, not floats.
+        assert!(output.contains(r#""1":3"#), "{}", output);
+    }
+
+    #[test]
+    fn test_lcov_demangle() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_name = "test_lcov_demangle";
+        let file_path = tmp_dir.path().join(&file_name);
 
         let results = vec![(
             PathBuf::from("foo/bar/a.cpp"),
             PathBuf::from("foo/bar/a.cpp"),
             CovResult {
-                lines: BTreeMap::new(),
-                branches: BTreeMap::new(),
                 functions: {
                     let mut map = FxHashMap::default();
                     map.insert(
@@ -647,6 +1927,7 @@ mod tests {
                         Function {
                             start: 1,
                             executed: true,
+                            end: None,
                         },
                     );
                     map.insert(
@@ -654,6 +1935,7 @@ mod tests {
                         Function {
                             start: 2,
                             executed: true,
+                            end: None,
                         },
                     );
                     map.insert(
@@ -661,15 +1943,23 @@ mod tests {
                         Function {
                             start: 3,
                             executed: true,
+                            end: None,
                         },
                     );
                     map
                 },
+                ..Default::default()
             },
         )];
 
         let results = Box::new(results.into_iter());
-        output_lcov(results, Some(file_path.to_str().unwrap()), true);
+        output_lcov(
+            results,
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            true,
+        )
+        .unwrap();
 
         let results = read_file(&file_path);
 
@@ -690,8 +1980,7 @@ mod tests {
                 PathBuf::from("foo/bar/a.cpp"),
                 CovResult {
                     lines: [(1, 10), (2, 11)].iter().cloned().collect(),
-                    branches: BTreeMap::new(),
-                    functions: FxHashMap::default(),
+                    ..Default::default()
                 },
             ),
             (
@@ -699,8 +1988,7 @@ mod tests {
                 PathBuf::from("foo/bar/b.cpp"),
                 CovResult {
                     lines: [(1, 0), (2, 10), (4, 0)].iter().cloned().collect(),
-                    branches: BTreeMap::new(),
-                    functions: FxHashMap::default(),
+                    ..Default::default()
                 },
             ),
             (
@@ -708,8 +1996,7 @@ mod tests {
                 PathBuf::from("foo/c.cpp"),
                 CovResult {
                     lines: [(1, 10), (4, 1)].iter().cloned().collect(),
-                    branches: BTreeMap::new(),
-                    functions: FxHashMap::default(),
+                    ..Default::default()
                 },
             ),
             (
@@ -717,14 +2004,13 @@ mod tests {
                 PathBuf::from("/foo/d.cpp"),
                 CovResult {
                     lines: [(1, 10), (2, 0)].iter().cloned().collect(),
-                    branches: BTreeMap::new(),
-                    functions: FxHashMap::default(),
+                    ..Default::default()
                 },
             ),
         ];
 
         let results = Box::new(results.into_iter());
-        output_covdir(results, Some(file_path.to_str().unwrap()));
+        output_covdir(results, Some(file_path.to_str().unwrap()), LineEnding::Lf).unwrap();
 
         let results: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
         let expected_path = PathBuf::from("./test/").join(&file_name);
@@ -733,6 +2019,50 @@ mod tests {
         assert_eq!(results, expected);
     }
 
+    #[test]
+    fn test_covdir_keep_excluded() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_covdir_keep_excluded.json");
+
+        let mut result_map: CovResultMap = FxHashMap::default();
+        for (name, lines) in &[
+            ("src/a.cpp", vec![(1, 1), (2, 0)]),
+            ("gen/b.cpp", vec![(1, 0)]),
+        ] {
+            result_map.insert(
+                name.to_string(),
+                CovResult {
+                    lines: lines.iter().cloned().collect(),
+                    ..Default::default()
+                },
+            );
+        }
+        let results = crate::rewrite_paths(
+            result_map,
+            None,
+            None,
+            None,
+            false,
+            &["gen/*"],
+            &[],
+            None,
+            true,
+            None,
+            Default::default(),
+        );
+        output_covdir(results, Some(file_path.to_str().unwrap()), LineEnding::Lf).unwrap();
+
+        let covdir: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
+        assert_eq!(covdir["coveragePercent"], json!(50.0));
+        assert_eq!(covdir["linesTotal"], json!(2));
+        let excluded = &covdir["children"]["gen"]["children"]["b.cpp"];
+        assert_eq!(excluded["excluded"], json!(true));
+        assert_eq!(excluded["coveragePercent"], json!(0.0));
+        assert!(covdir["children"]["src"]["children"]["a.cpp"]
+            .get("excluded")
+            .is_none());
+    }
+
     #[test]
     fn test_coveralls_service_job_id() {
         let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
@@ -744,8 +2074,7 @@ mod tests {
             PathBuf::from("foo/bar/a.cpp"),
             CovResult {
                 lines: [(1, 10), (2, 11)].iter().cloned().collect(),
-                branches: BTreeMap::new(),
-                functions: FxHashMap::default(),
+                ..Default::default()
             },
         )];
 
@@ -763,16 +2092,141 @@ mod tests {
             "unused",
             with_function_info,
             Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
             "unused",
             parallel,
             false,
-        );
+        )
+        .unwrap();
 
         let results: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
 
         assert_eq!(results["service_job_id"], expected_service_job_id);
     }
 
+    #[test]
+    fn test_coveralls_parallel() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+
+        let coveralls = |parallel| {
+            let file_path = tmp_dir.path().join(format!("parallel_{}.json", parallel));
+            let results = vec![(
+                PathBuf::from("foo/bar/a.cpp"),
+                PathBuf::from("foo/bar/a.cpp"),
+                CovResult {
+                    lines: [(1, 10), (2, 0)].iter().cloned().collect(),
+                    ..Default::default()
+                },
+            )];
+            output_coveralls(
+                Box::new(results.into_iter()),
+                None,
+                Some("github"),
+                "42",
+                Some("4242"),
+                "unused",
+                "unused",
+                false,
+                Some(file_path.to_str().unwrap()),
+                LineEnding::Lf,
+                "unused",
+                parallel,
+                false,
+            )
+            .unwrap();
+            serde_json::from_str::<Value>(&read_file(&file_path)).unwrap()
+        };
+
+        // The shards of a parallel build are told apart by their job ids, and merged by
+        // their service number.
+        let results = coveralls(true);
+        assert_eq!(results["parallel"], true);
+        assert_eq!(results["service_number"], "42");
+        assert_eq!(results["service_job_id"], "4242");
+        assert_eq!(results["service_name"], "github");
+        assert_eq!(results["source_files"][0]["name"], "foo/bar/a.cpp");
+
+        assert_eq!(coveralls(false)["parallel"], false);
+    }
+
+    #[test]
+    fn test_coveralls_streaming() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("test_coveralls_streaming.json");
+
+        let result = |lines: &[(u32, u64)]| {
+            let mut functions: FunctionMap = FxHashMap::default();
+            functions.insert(
+                "f".to_string(),
+                Function {
+                    start: 1,
+                    executed: true,
+                    end: None,
+                },
+            );
+            let mut branches = BTreeMap::new();
+            branches.insert(1, vec![true, false]);
+            // A single condition, which isn't a branch.
+            branches.insert(2, vec![true]);
+            CovResult {
+                lines: lines.iter().cloned().collect(),
+                branches,
+                branch_hits: [(1, vec![3, 0])].iter().cloned().collect(),
+                functions,
+                ..Default::default()
+            }
+        };
+
+        for count in 0..3 {
+            let results: Vec<_> = vec![
+                (
+                    PathBuf::from("foo/bar/a.cpp"),
+                    PathBuf::from("foo/bar/a.cpp"),
+                    result(&[(1, 10), (2, 11)]),
+                ),
+                (
+                    PathBuf::from("foo/bar/b.cpp"),
+                    PathBuf::from("foo/bar/b.cpp"),
+                    result(&[(1, 0), (4, 1)]),
+                ),
+            ]
+            .into_iter()
+            .take(count)
+            .collect();
+
+            output_coveralls(
+                Box::new(results.into_iter()),
+                Some("token"),
+                Some("service"),
+                "1",
+                Some("2"),
+                "3",
+                "unused",
+                true,
+                Some(file_path.to_str().unwrap()),
+                LineEnding::Lf,
+                "unused",
+                false,
+                false,
+            )
+            .unwrap();
+
+            // The streamed output is the same as a buffered serialization of the whole object.
+            let output = read_file(&file_path);
+            let reference: Value = serde_json::from_str(&output).unwrap();
+            assert_eq!(serde_json::to_string(&reference).unwrap(), output);
+            assert_eq!(reference["source_files"].as_array().unwrap().len(), count);
+            assert_eq!(reference["repo_token"], "token");
+            if count > 0 {
+                // The taken branch is reported with its hit count, the single condition isn't.
+                assert_eq!(
+                    reference["source_files"][0]["branches"],
+                    json!([1, 0, 0, 3, 1, 0, 1, 0])
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_coveralls_token_field_is_absent_if_arg_is_none() {
         let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
@@ -784,8 +2238,7 @@ mod tests {
             PathBuf::from("foo/bar/a.cpp"),
             CovResult {
                 lines: [(1, 10), (2, 11)].iter().cloned().collect(),
-                branches: BTreeMap::new(),
-                functions: FxHashMap::default(),
+                ..Default::default()
             },
         )];
 
@@ -803,10 +2256,12 @@ mod tests {
             "unused",
             with_function_info,
             Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
             "unused",
             parallel,
             false,
-        );
+        )
+        .unwrap();
 
         let results: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
 
@@ -824,8 +2279,7 @@ mod tests {
             PathBuf::from("foo/bar/a.cpp"),
             CovResult {
                 lines: [(1, 10), (2, 11)].iter().cloned().collect(),
-                branches: BTreeMap::new(),
-                functions: FxHashMap::default(),
+                ..Default::default()
             },
         )];
 
@@ -844,14 +2298,188 @@ mod tests {
             "unused",
             with_function_info,
             Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
             "unused",
             parallel,
             false,
-        );
+        )
+        .unwrap();
 
         let results: Value = serde_json::from_str(&read_file(&file_path)).unwrap();
 
         assert_eq!(results.get("service_name"), None);
         assert_eq!(results.get("service_job_id"), None);
     }
+
+    #[test]
+    fn test_output_concurrently() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let path = |name: &str| tmp_dir.path().join(name).to_str().unwrap().to_string();
+
+        let results = vec![
+            (
+                PathBuf::from("foo/bar/a.cpp"),
+                PathBuf::from("foo/bar/a.cpp"),
+                CovResult {
+                    lines: [(1, 10), (2, 11)].iter().cloned().collect(),
+                    branches: [(1, vec![true, false])].iter().cloned().collect(),
+                    functions: {
+                        let mut map = FxHashMap::default();
+                        map.insert(
+                            "f".to_string(),
+                            Function {
+                                start: 1,
+                                executed: true,
+                                end: None,
+                            },
+                        );
+                        map
+                    },
+                    ..Default::default()
+                },
+            ),
+            (
+                PathBuf::from("foo/b.cpp"),
+                PathBuf::from("foo/b.cpp"),
+                CovResult {
+                    lines: [(1, 0), (2, 10), (4, 0)].iter().cloned().collect(),
+                    ..Default::default()
+                },
+            ),
+        ];
+
+        let (lcov, covdir, files, ade) = (
+            path("concurrent.info"),
+            path("concurrent.json"),
+            path("concurrent.txt"),
+            path("concurrent.ade"),
+        );
+        let writers: Vec<OutputWriter> = vec![
+            Box::new(move |results| output_lcov(results, Some(&lcov), LineEnding::Lf, false)),
+            Box::new(move |results| output_covdir(results, Some(&covdir), LineEnding::Lf)),
+            Box::new(move |results| output_files(results, Some(&files), LineEnding::Lf, None)),
+            Box::new(move |results| {
+                output_activedata_etl(results, Some(&ade), LineEnding::Lf, false)
+            }),
+        ];
+        output_concurrently(results.clone(), writers).unwrap();
+
+        output_lcov(
+            Box::new(results.clone().into_iter()),
+            Some(&path("serial.info")),
+            LineEnding::Lf,
+            false,
+        )
+        .unwrap();
+        output_covdir(
+            Box::new(results.clone().into_iter()),
+            Some(&path("serial.json")),
+            LineEnding::Lf,
+        )
+        .unwrap();
+        output_files(
+            Box::new(results.clone().into_iter()),
+            Some(&path("serial.txt")),
+            LineEnding::Lf,
+            None,
+        )
+        .unwrap();
+        output_activedata_etl(
+            Box::new(results.into_iter()),
+            Some(&path("serial.ade")),
+            LineEnding::Lf,
+            false,
+        )
+        .unwrap();
+
+        for ext in &["info", "json", "txt", "ade"] {
+            let concurrent = read_file(&tmp_dir.path().join(format!("concurrent.{}", ext)));
+            let serial = read_file(&tmp_dir.path().join(format!("serial.{}", ext)));
+            assert!(!concurrent.is_empty());
+            assert_eq!(concurrent, serial);
+        }
+    }
+    #[test]
+    fn test_output_concurrently_error() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("files.txt");
+
+        let results = vec![(
+            PathBuf::from("foo/b.cpp"),
+            PathBuf::from("foo/b.cpp"),
+            CovResult {
+                lines: [(1, 0), (2, 10)].iter().cloned().collect(),
+                ..Default::default()
+            },
+        )];
+        let output_path = file_path.to_str().unwrap().to_string();
+        let writers: Vec<OutputWriter> = vec![
+            Box::new(|_| Err(io::Error::other("Failed"))),
+            Box::new(move |results| {
+                output_files(results, Some(&output_path), LineEnding::Lf, None)
+            }),
+            Box::new(|_| panic!("Failed")),
+        ];
+        let err = output_concurrently(results, writers).unwrap_err();
+
+        // The error is returned after all the writers ran.
+        assert_eq!(err.to_string(), "Failed");
+        assert_eq!(read_file(&file_path), "foo/b.cpp\n");
+    }
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_output_write_error() {
+        let results = vec![(
+            PathBuf::from("foo/b.cpp"),
+            PathBuf::from("foo/b.cpp"),
+            CovResult {
+                lines: [(1, 0), (2, 10)].iter().cloned().collect(),
+                ..Default::default()
+            },
+        )];
+        let writers: Vec<OutputWriter> = vec![
+            Box::new(|results| output_lcov(results, Some("/dev/full"), LineEnding::Lf, false)),
+            Box::new(|results| output_files(results, Some("/dev/full"), LineEnding::Lf, None)),
+        ];
+        let err = output_concurrently(results, writers).unwrap_err();
+
+        // The writes to /dev/full fail with ENOSPC, which is returned rather than a panic.
+        assert_eq!(err.raw_os_error(), Some(28), "{}", err);
+    }
+    #[test]
+    fn test_output_line_endings() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let path = |name: &str| tmp_dir.path().join(name).to_str().unwrap().to_string();
+
+        let results: Vec<_> = ["foo/a.cpp", "foo/b.cpp"]
+            .iter()
+            .map(|name| {
+                (
+                    PathBuf::from(name),
+                    PathBuf::from(name),
+                    CovResult {
+                        lines: [(1, 1)].iter().cloned().collect(),
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect();
+
+        // The writers running at the same time each have their own line endings.
+        let (lf, crlf) = (path("lf.txt"), path("crlf.txt"));
+        let writers: Vec<OutputWriter> = vec![
+            Box::new(move |results| output_files(results, Some(&lf), LineEnding::Lf, None)),
+            Box::new(move |results| output_files(results, Some(&crlf), LineEnding::Crlf, None)),
+        ];
+        output_concurrently(results, writers).unwrap();
+
+        assert_eq!(
+            read_file(&tmp_dir.path().join("lf.txt")),
+            "foo/a.cpp\nfoo/b.cpp\n"
+        );
+        assert_eq!(
+            read_file(&tmp_dir.path().join("crlf.txt")),
+            "foo/a.cpp\r\nfoo/b.cpp\r\n"
+        );
+    }
 }
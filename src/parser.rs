@@ -1,7 +1,8 @@
 use flate2::read::GzDecoder;
 use serde::Deserialize;
 use std::cmp::Ordering;
-use std::collections::{btree_map, hash_map, BTreeMap};
+use std::collections::{btree_map, hash_map, BTreeMap, BTreeSet};
+use std::error;
 use std::fmt;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, Read};
@@ -17,6 +18,7 @@ use xml::Reader;
 use rustc_hash::FxHashMap;
 
 use crate::defs::*;
+use crate::error::Error;
 
 #[derive(Debug)]
 pub enum ParserError {
@@ -58,6 +60,15 @@ impl fmt::Display for ParserError {
     }
 }
 
+impl error::Error for ParserError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ParserError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
 macro_rules! try_parse {
     ($v:expr, $l:expr) => {
         match $v.parse() {
@@ -123,13 +134,31 @@ pub fn add_branch(branches: &mut BTreeMap<u32, Vec<bool>>, line_no: u32, no: u32
     };
 }
 
+/// Adds the hit count of a branch, summed with the ones of the same branch, e.g. of the
+/// instances of a template.
+pub fn add_branch_hits(
+    branch_hits: &mut BTreeMap<u32, Vec<u64>>,
+    line_no: u32,
+    no: u32,
+    hits: u64,
+) {
+    let v = branch_hits.entry(line_no).or_default();
+    let no = no as usize;
+    if no >= v.len() {
+        v.resize(no + 1, 0);
+    }
+    v[no] = v[no].saturating_add(hits);
+}
+
 pub fn parse_lcov(
     buffer: Vec<u8>,
     branch_enabled: bool,
 ) -> Result<Vec<(String, CovResult)>, ParserError> {
+    let mut cur_test = String::new();
     let mut cur_file = None;
     let mut cur_lines = BTreeMap::new();
     let mut cur_branches = BTreeMap::new();
+    let mut cur_branch_hits = BTreeMap::new();
     let mut cur_functions = FxHashMap::default();
 
     // We only log the duplicated FN error once per parse_lcov call.
@@ -138,6 +167,7 @@ pub fn parse_lcov(
     let mut results = Vec::new();
     let iter = &mut buffer.iter().peekable();
 
+    const TN: u32 = (b'T' as u32) * (1 << 8) + (b'N' as u32);
     const SF: u32 = (b'S' as u32) * (1 << 8) + (b'F' as u32);
     const DA: u32 = (b'D' as u32) * (1 << 8) + (b'A' as u32);
     const FN: u32 = (b'F' as u32) * (1 << 8) + (b'N' as u32);
@@ -157,18 +187,26 @@ pub fn parse_lcov(
         match *c {
             b'e' => {
                 // we've a end_of_record
+                let mut tests = BTreeSet::new();
+                if !cur_test.is_empty() && cur_lines.values().any(|&c| c > 0) {
+                    tests.insert(cur_test.clone());
+                }
                 results.push((
                     cur_file.unwrap(),
                     CovResult {
                         lines: cur_lines,
                         branches: cur_branches,
+                        branch_hits: cur_branch_hits,
                         functions: cur_functions,
+                        tests,
+                        ..Default::default()
                     },
                 ));
 
                 cur_file = None;
                 cur_lines = BTreeMap::new();
                 cur_branches = BTreeMap::new();
+                cur_branch_hits = BTreeMap::new();
                 cur_functions = FxHashMap::default();
                 iter.take_while(|&&c| c != b'\n').last();
             }
@@ -176,7 +214,7 @@ pub fn parse_lcov(
                 continue;
             }
             _ => {
-                if *c != b'S' && *c != b'D' && *c != b'F' && *c != b'B' {
+                if *c != b'T' && *c != b'S' && *c != b'D' && *c != b'F' && *c != b'B' {
                     iter.take_while(|&&c| c != b'\n').last();
                     continue;
                 }
@@ -185,6 +223,13 @@ pub fn parse_lcov(
                     .take_while(|&&c| c != b':')
                     .fold(*c as u32, |r, &x| r * (1 << 8) + u32::from(x));
                 match key {
+                    TN => {
+                        // TN:string, the test of the following records
+                        cur_test = iter
+                            .take_while(|&&c| c != b'\n' && c != b'\r')
+                            .map(|&c| c as char)
+                            .collect();
+                    }
                     SF => {
                         // SF:string
                         cur_file = Some(
@@ -241,6 +286,7 @@ pub fn parse_lcov(
                             Function {
                                 start,
                                 executed: false,
+                                end: None,
                             },
                         );
                     }
@@ -298,10 +344,16 @@ pub fn parse_lcov(
                                     line
                                 )));
                             }
-                            let taken = iter
+                            // The count is '-' when the branch was never evaluated.
+                            let hits = iter
                                 .take_while(|&&c| c != b'\n' && c != b'\r')
-                                .any(|&x| x != b'-');
+                                .filter(|x| x.is_ascii_digit())
+                                .fold(0u64, |hits, &x| {
+                                    hits.saturating_mul(10).saturating_add(u64::from(x - b'0'))
+                                });
+                            let taken = hits > 0;
                             add_branch(&mut cur_branches, line_no, branch_number, taken);
+                            add_branch_hits(&mut cur_branch_hits, line_no, branch_number, hits);
                         } else {
                             iter.take_while(|&&c| c != b'\n').last();
                         }
@@ -364,13 +416,53 @@ struct GcovFunction {
     execution_count: u64,
 }
 
+#[derive(Debug, Deserialize)]
+struct GrcovJson {
+    format: String,
+    version: u32,
+    files: Vec<GrcovJsonFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GrcovJsonFile {
+    name: String,
+    lines: BTreeMap<u32, u64>,
+    #[serde(default)]
+    branches: BTreeMap<u32, Vec<bool>>,
+    #[serde(default)]
+    branch_hits: BTreeMap<u32, Vec<u64>>,
+    #[serde(default)]
+    functions: FunctionMap,
+    #[serde(default)]
+    tests: BTreeSet<String>,
+}
+
+// The arcs leaving a conditional block are a jump and a fallthrough, in no particular order.
+// GCC usually lays the true arm of a condition out as the fallthrough, so put the fallthrough
+// first in each pair: the true arm is then the first condition and the false arm the second.
+fn order_gcov_branches(branches: &mut [GcovBr]) {
+    let mut i = 0;
+    while i + 1 < branches.len() {
+        let (jump, fallthrough) = (&branches[i], &branches[i + 1]);
+        if !jump.throw && !jump.fallthrough && !fallthrough.throw && fallthrough.fallthrough {
+            branches.swap(i, i + 1);
+            i += 2;
+        } else {
+            i += 1;
+        }
+    }
+}
+
 pub fn parse_gcov_gz(gcov_path: &Path) -> Result<Vec<(String, CovResult)>, ParserError> {
-    let f = File::open(&gcov_path)
-        .unwrap_or_else(|_| panic!("Failed to open gcov file {}", gcov_path.display()));
+    let f = File::open(&gcov_path)?;
+
+    parse_gcov_gz_content(BufReader::new(&f))
+}
 
-    let file = BufReader::new(&f);
-    let gz = GzDecoder::new(file);
-    let mut gcov: GcovJson = serde_json::from_reader(gz).unwrap();
+pub fn parse_gcov_gz_content<T: Read>(reader: T) -> Result<Vec<(String, CovResult)>, ParserError> {
+    let gz = GzDecoder::new(reader);
+    let mut gcov: GcovJson =
+        serde_json::from_reader(gz).map_err(|e| ParserError::InvalidData(e.to_string()))?;
     let mut results = Vec::new();
 
     if gcov.format_version != "1" {
@@ -383,13 +475,14 @@ pub fn parse_gcov_gz(gcov_path: &Path) -> Result<Vec<(String, CovResult)>, Parse
     for mut file in gcov.files.drain(..) {
         let mut lines = BTreeMap::new();
         let mut branches = BTreeMap::new();
+        let mut branch_hits = BTreeMap::new();
         for mut line in file.lines.drain(..) {
             lines.insert(line.line_number, line.count);
             if !line.branches.is_empty() {
-                branches.insert(
-                    line.line_number,
-                    line.branches.drain(..).map(|b| b.count > 0).collect(),
-                );
+                order_gcov_branches(&mut line.branches);
+                let counts: Vec<u64> = line.branches.drain(..).map(|b| b.count).collect();
+                branches.insert(line.line_number, counts.iter().map(|&c| c > 0).collect());
+                branch_hits.insert(line.line_number, counts);
             }
         }
         if lines.is_empty() {
@@ -397,11 +490,13 @@ pub fn parse_gcov_gz(gcov_path: &Path) -> Result<Vec<(String, CovResult)>, Parse
         }
         let mut functions = FxHashMap::default();
         for fun in file.functions.drain(..) {
+            let start = fun.start_line;
             functions.insert(
                 fun.demangled_name,
                 Function {
-                    start: fun.start_line,
+                    start,
                     executed: fun.execution_count > 0,
+                    end: Some(fun.end_line).filter(|&end| end >= start),
                 },
             );
         }
@@ -410,7 +505,9 @@ pub fn parse_gcov_gz(gcov_path: &Path) -> Result<Vec<(String, CovResult)>, Parse
             CovResult {
                 lines,
                 branches,
+                branch_hits,
                 functions,
+                ..Default::default()
             },
         ));
     }
@@ -425,8 +522,7 @@ pub fn parse_gcov(gcov_path: &Path) -> Result<Vec<(String, CovResult)>, ParserEr
     let mut cur_functions = FxHashMap::default();
     let mut results = Vec::new();
 
-    let f = File::open(&gcov_path)
-        .unwrap_or_else(|_| panic!("Failed to open gcov file {}", gcov_path.display()));
+    let f = File::open(&gcov_path)?;
 
     let mut file = BufReader::new(&f);
     let mut l = vec![];
@@ -456,6 +552,7 @@ pub fn parse_gcov(gcov_path: &Path) -> Result<Vec<(String, CovResult)>, ParserEr
                             lines: cur_lines,
                             branches: cur_branches,
                             functions: cur_functions,
+                            ..Default::default()
                         },
                     ));
                 }
@@ -470,7 +567,14 @@ pub fn parse_gcov(gcov_path: &Path) -> Result<Vec<(String, CovResult)>, ParserEr
                 let start = try_parse_next!(f_splits, l);
                 let executed = try_next!(f_splits, l) != "0";
                 let f_name = try_next!(f_splits, l);
-                cur_functions.insert(f_name.to_owned(), Function { start, executed });
+                cur_functions.insert(
+                    f_name.to_owned(),
+                    Function {
+                        start,
+                        executed,
+                        end: None,
+                    },
+                );
             }
             "lcount" => {
                 let mut values = value.splitn(2, ',');
@@ -507,6 +611,7 @@ pub fn parse_gcov(gcov_path: &Path) -> Result<Vec<(String, CovResult)>, ParserEr
                 lines: cur_lines,
                 branches: cur_branches,
                 functions: cur_functions,
+                ..Default::default()
             },
         ));
     }
@@ -610,7 +715,11 @@ fn parse_jacoco_report_method<T: BufRead>(
         buf.clear();
     }
 
-    Ok(Function { start, executed })
+    Ok(Function {
+        start,
+        executed,
+        end: None,
+    })
 }
 
 fn parse_jacoco_report_class<T: BufRead>(
@@ -675,8 +784,7 @@ fn parse_jacoco_report_package<T: BufRead>(
                             hash_map::Entry::Vacant(v) => {
                                 v.insert(CovResult {
                                     functions,
-                                    lines: BTreeMap::new(),
-                                    branches: BTreeMap::new(),
+                                    ..Default::default()
                                 });
                             }
                         };
@@ -695,9 +803,9 @@ fn parse_jacoco_report_package<T: BufRead>(
                             }
                             hash_map::Entry::Vacant(v) => {
                                 v.insert(CovResult {
-                                    functions: FxHashMap::default(),
                                     lines,
                                     branches,
+                                    ..Default::default()
                                 });
                             }
                         };
@@ -763,6 +871,223 @@ pub fn parse_jacoco_xml_report<T: Read>(
     Ok(results)
 }
 
+/// Parses the "taken/total" counts of a Cobertura `condition-coverage` or
+/// `coverage` attribute, e.g. "50% (1/2)".
+fn parse_cobertura_condition_coverage(coverage: &str) -> Option<Vec<bool>> {
+    let counts = coverage.split('(').nth(1)?.trim_end().strip_suffix(')')?;
+    let mut counts = counts.split('/');
+    let taken = counts.next()?.trim().parse::<usize>().ok()?;
+    let total = counts.next()?.trim().parse::<usize>().ok()?;
+    if taken > total {
+        return None;
+    }
+    let mut branches = vec![true; taken];
+    branches.extend(vec![false; total - taken]);
+    Some(branches)
+}
+
+/// Parses a Cobertura XML report, like the ones written by the `cobertura` output type.
+pub fn parse_cobertura_xml_report<T: Read>(
+    xml_reader: BufReader<T>,
+) -> Result<Vec<(String, CovResult)>, ParserError> {
+    let mut parser = Reader::from_reader(xml_reader);
+    parser.expand_empty_elements(true).trim_text(true);
+
+    let mut results: BTreeMap<String, CovResult> = BTreeMap::new();
+    let mut sources = Vec::new();
+    let mut in_source = false;
+    let mut file_name: Option<String> = None;
+    // The name of the current method, its first and last lines and whether it was executed.
+    type Method = (String, Option<(u32, u32)>, bool);
+    let mut method: Option<Method> = None;
+    // The current line, with the branches of its <condition> elements when it
+    // doesn't have a condition-coverage attribute.
+    let mut line: Option<(u32, Option<Vec<bool>>)> = None;
+    let mut buf = Vec::new();
+
+    loop {
+        match parser.read_event(&mut buf) {
+            Ok(Event::Start(ref e)) => match e.local_name() {
+                b"source" => in_source = true,
+                b"class" => {
+                    let name = get_xml_attribute(&parser, e, "filename")?;
+                    results.entry(name.clone()).or_default();
+                    file_name = Some(name);
+                }
+                b"method" => {
+                    let name = get_xml_attribute(&parser, e, "name")?;
+                    let signature = get_xml_attribute(&parser, e, "signature").unwrap_or_default();
+                    method = Some((format!("{}{}", name, signature), None, false));
+                }
+                b"line" => {
+                    let result = match file_name.as_ref().and_then(|f| results.get_mut(f)) {
+                        Some(result) => result,
+                        None => {
+                            return Err(ParserError::InvalidRecord(
+                                "Line outside of a class".to_string(),
+                            ))
+                        }
+                    };
+                    let number = get_xml_attribute(&parser, e, "number")?.parse::<u32>()?;
+                    let hits = get_xml_attribute(&parser, e, "hits")?.parse::<u64>()?;
+                    // Lines of methods are usually repeated in the lines of their class.
+                    result.lines.insert(number, hits);
+
+                    let is_branch =
+                        get_xml_attribute(&parser, e, "branch").ok().as_deref() == Some("true");
+                    let branches = if is_branch {
+                        get_xml_attribute(&parser, e, "condition-coverage")
+                            .ok()
+                            .and_then(|coverage| parse_cobertura_condition_coverage(&coverage))
+                    } else {
+                        None
+                    };
+                    line = match branches {
+                        Some(branches) => {
+                            result.branches.insert(number, branches);
+                            None
+                        }
+                        None if is_branch => Some((number, Some(Vec::new()))),
+                        None => Some((number, None)),
+                    };
+
+                    if let Some((_, ref mut range, ref mut executed)) = method {
+                        let (_, end) = range.get_or_insert((number, number));
+                        *end = (*end).max(number);
+                        *executed |= hits > 0;
+                    }
+                }
+                b"condition" => {
+                    if let Some((_, Some(ref mut branches))) = line {
+                        let coverage = get_xml_attribute(&parser, e, "coverage")?;
+                        match parse_cobertura_condition_coverage(&coverage) {
+                            Some(mut condition) => branches.append(&mut condition),
+                            None => branches.push(!coverage.starts_with("0%")),
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Ok(Event::Text(ref e)) if in_source => {
+                sources.push(e.unescape_and_decode(&parser)?);
+            }
+            Ok(Event::End(ref e)) => match e.local_name() {
+                b"source" => in_source = false,
+                b"class" => file_name = None,
+                b"method" => {
+                    if let (Some((name, Some((start, end)), executed)), Some(result)) = (
+                        method.take(),
+                        file_name.as_ref().and_then(|f| results.get_mut(f)),
+                    ) {
+                        result.functions.insert(
+                            name,
+                            Function {
+                                start,
+                                executed,
+                                end: Some(end),
+                            },
+                        );
+                    }
+                }
+                b"line" => {
+                    if let (Some((number, Some(branches))), Some(result)) = (
+                        line.take(),
+                        file_name.as_ref().and_then(|f| results.get_mut(f)),
+                    ) {
+                        if !branches.is_empty() {
+                            result.branches.insert(number, branches);
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(ParserError::Parse(e.to_string())),
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    // The file names are relative to the source directory, if there is one.
+    let source = sources
+        .into_iter()
+        .next()
+        .filter(|source| !source.is_empty() && source != ".");
+    Ok(results
+        .into_iter()
+        .map(|(name, result)| match source {
+            Some(ref source) if Path::new(&name).is_relative() => (
+                Path::new(source).join(&name).to_string_lossy().into_owned(),
+                result,
+            ),
+            _ => (name, result),
+        })
+        .collect())
+}
+
+/// Parses the results written by grcov with the `json` output type.
+pub fn parse_grcov_json<T: Read>(reader: T) -> Result<Vec<(String, CovResult)>, ParserError> {
+    let grcov: GrcovJson =
+        serde_json::from_reader(reader).map_err(|e| ParserError::InvalidData(e.to_string()))?;
+
+    if grcov.format != "grcov" || grcov.version != 1 {
+        return Err(ParserError::InvalidData(format!(
+            "Unsupported format {} version {}",
+            grcov.format, grcov.version
+        )));
+    }
+
+    Ok(grcov
+        .files
+        .into_iter()
+        .map(|file| {
+            (
+                file.name,
+                CovResult {
+                    lines: file.lines,
+                    branches: file.branches,
+                    branch_hits: file.branch_hits,
+                    functions: file.functions,
+                    tests: file.tests,
+                    ..Default::default()
+                },
+            )
+        })
+        .collect())
+}
+
+/// Parses a coverage file, the format is guessed from the file name.
+pub fn parse_file(path: &Path, branch_enabled: bool) -> Result<Vec<(String, CovResult)>, Error> {
+    let name = path.to_string_lossy();
+    let parse_error = |err| Error::Parse(name.clone().into_owned(), err);
+    if name.ends_with(".gcov.json.gz") {
+        parse_gcov_gz(path).map_err(parse_error)
+    } else if name.ends_with(".gcov") {
+        parse_gcov(path).map_err(parse_error)
+    } else if name.ends_with(".info") {
+        let mut buffer = Vec::new();
+        File::open(path)?.read_to_end(&mut buffer)?;
+        parse_lcov(buffer, branch_enabled).map_err(parse_error)
+    } else if name.ends_with(".xml") {
+        let mut buffer = Vec::new();
+        File::open(path)?.read_to_end(&mut buffer)?;
+        let head = String::from_utf8_lossy(&buffer[..buffer.len().min(1024)]).into_owned();
+        let reader = BufReader::new(io::Cursor::new(buffer));
+        if head.contains("<coverage") {
+            parse_cobertura_xml_report(reader).map_err(parse_error)
+        } else {
+            parse_jacoco_xml_report(reader).map_err(parse_error)
+        }
+    } else if name.ends_with(".json") {
+        parse_grcov_json(BufReader::new(File::open(path)?)).map_err(parse_error)
+    } else {
+        Err(Error::Config(format!(
+            "Unsupported coverage file: {}",
+            path.display()
+        )))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1167,6 +1492,244 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_lcov_parser_branch_hits() {
+        let buf =
+            b"SF:foo.c\nDA:3,4\nBRDA:3,0,0,3\nBRDA:3,0,1,1\nBRDA:3,0,2,-\nend_of_record\n".to_vec();
+        let results = parse_lcov(buf, true).unwrap();
+        let (_, ref result) = results[0];
+        assert_eq!(result.branches[&3], vec![true, true, false]);
+        assert_eq!(result.branch_hits[&3], vec![3, 1, 0]);
+    }
+
+    #[test]
+    fn test_lcov_parser_branch_zero_hits() {
+        let buf = b"SF:foo.c\nDA:3,4\nBRDA:3,0,0,4\nBRDA:3,0,1,0\nend_of_record\n".to_vec();
+        let results = parse_lcov(buf, true).unwrap();
+        let (_, ref result) = results[0];
+        assert_eq!(result.branches[&3], vec![true, false]);
+        assert_eq!(result.branch_hits[&3], vec![4, 0]);
+    }
+
+    #[test]
+    fn test_parser_gcov_gz_branch_order() {
+        use std::io::Write;
+
+        let json = r#"{
+            "format_version": "1",
+            "gcc_version": "10.2.0",
+            "current_working_directory": "/tmp",
+            "data_file": "foo.gcda",
+            "files": [{
+                "file": "foo.c",
+                "functions": [],
+                "lines": [
+                    {"line_number": 3, "count": 4, "unexecuted_block": false, "branches": [
+                        {"count": 0, "throw": false, "fallthrough": false},
+                        {"count": 4, "throw": false, "fallthrough": true}
+                    ]},
+                    {"line_number": 5, "count": 4, "unexecuted_block": false, "branches": [
+                        {"count": 1, "throw": false, "fallthrough": true},
+                        {"count": 0, "throw": false, "fallthrough": false},
+                        {"count": 0, "throw": true, "fallthrough": false},
+                        {"count": 0, "throw": false, "fallthrough": false},
+                        {"count": 2, "throw": false, "fallthrough": true}
+                    ]}
+                ]
+            }]
+        }"#;
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(json.as_bytes()).unwrap();
+        let buf = gz.finish().unwrap();
+
+        let results = parse_gcov_gz_content(buf.as_slice()).unwrap();
+        assert_eq!(results.len(), 1);
+        let (ref source_name, ref result) = results[0];
+        assert_eq!(source_name, "foo.c");
+        // The fallthrough (true) arm is the first condition, the jump (false) arm the second.
+        assert_eq!(result.branches.get(&3), Some(&vec![true, false]));
+        assert_eq!(
+            result.branches.get(&5),
+            Some(&vec![true, false, false, true, false])
+        );
+    }
+
+    #[test]
+    fn test_parser_gcov_gz_multiple_files() {
+        use std::io::Write;
+
+        let json = r#"{
+            "format_version": "1",
+            "gcc_version": "10.2.0",
+            "current_working_directory": "/tmp",
+            "data_file": "main.gcda",
+            "files": [{
+                "file": "main.c",
+                "functions": [{
+                    "name": "main", "demangled_name": "main",
+                    "start_line": 3, "start_column": 5, "end_line": 6, "end_column": 1,
+                    "blocks": 2, "blocks_executed": 2, "execution_count": 1
+                }],
+                "lines": [
+                    {"line_number": 4, "function_name": "main", "count": 1, "unexecuted_block": false, "branches": []},
+                    {"line_number": 5, "function_name": "main", "count": 1, "unexecuted_block": false, "branches": []}
+                ]
+            }, {
+                "file": "util.h",
+                "functions": [{
+                    "name": "_Z6unusedv", "demangled_name": "unused()",
+                    "start_line": 1, "start_column": 12, "end_line": 3, "end_column": 1,
+                    "blocks": 1, "blocks_executed": 0, "execution_count": 0
+                }],
+                "lines": [
+                    {"line_number": 2, "function_name": "_Z6unusedv", "count": 0, "unexecuted_block": true, "branches": []}
+                ]
+            }, {
+                "file": "empty.h",
+                "functions": [],
+                "lines": []
+            }]
+        }"#;
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(json.as_bytes()).unwrap();
+        let buf = gz.finish().unwrap();
+
+        // The files without lines are left out.
+        let results = parse_gcov_gz_content(buf.as_slice()).unwrap();
+        assert_eq!(results.len(), 2);
+
+        let (ref source_name, ref result) = results[0];
+        assert_eq!(source_name, "main.c");
+        assert_eq!(
+            result.lines,
+            [(4, 1), (5, 1)].iter().cloned().collect::<BTreeMap<_, _>>()
+        );
+        assert_eq!(
+            result.functions.get("main"),
+            Some(&Function {
+                start: 3,
+                executed: true,
+                end: Some(6),
+            })
+        );
+
+        let (ref source_name, ref result) = results[1];
+        assert_eq!(source_name, "util.h");
+        assert_eq!(
+            result.lines,
+            [(2, 0)].iter().cloned().collect::<BTreeMap<_, _>>()
+        );
+        assert_eq!(
+            result.functions.get("unused()"),
+            Some(&Function {
+                start: 1,
+                executed: false,
+                end: Some(3),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parser_cobertura_xml() {
+        let f = File::open("./test/cobertura.xml").expect("Failed to open xml file");
+        let results = parse_cobertura_xml_report(BufReader::new(&f)).unwrap();
+
+        // The classes sharing a file name are merged.
+        assert_eq!(results.len(), 1);
+        let (ref source_name, ref result) = results[0];
+        assert_eq!(source_name, "/home/user/project/src/main.cpp");
+        assert_eq!(
+            result.lines,
+            [
+                (3, 1),
+                (4, 1),
+                (5, 1),
+                (9, 0),
+                (12, u64::MAX),
+                (20, 2),
+                (21, 0)
+            ]
+            .iter()
+            .cloned()
+            .collect()
+        );
+        assert_eq!(
+            result.branches,
+            [(4, vec![true, false]), (20, vec![true, false, true, false])]
+                .iter()
+                .cloned()
+                .collect()
+        );
+        assert_eq!(result.functions.len(), 2);
+        assert_eq!(
+            result.functions.get("main"),
+            Some(&Function {
+                start: 3,
+                executed: true,
+                end: Some(5),
+            })
+        );
+        assert_eq!(
+            result.functions.get("unused(int)"),
+            Some(&Function {
+                start: 9,
+                executed: false,
+                end: Some(9),
+            })
+        );
+
+        // The report is sniffed among the XML formats.
+        assert_eq!(
+            parse_file(Path::new("./test/cobertura.xml"), true).unwrap(),
+            results
+        );
+    }
+
+    #[test]
+    fn test_parser_cobertura_xml_round_trip() {
+        let results = parse_gcov(Path::new("./test/prova.gcov")).unwrap();
+        let (name, result) = results[0].clone();
+        let results: CovResultIter = Box::new(std::iter::once((
+            std::path::PathBuf::from(&name),
+            std::path::PathBuf::from(&name),
+            result.clone(),
+        )));
+        let xml = crate::cobertura_to_bytes(
+            results,
+            crate::DemangleConfig::default(),
+            &Default::default(),
+        );
+
+        let parsed = parse_cobertura_xml_report(BufReader::new(xml.as_slice())).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].0, name);
+        assert_eq!(parsed[0].1.lines, result.lines);
+        // Only the number of taken branches survives, not their order.
+        let taken = |branches: &BTreeMap<u32, Vec<bool>>| {
+            branches
+                .iter()
+                .map(|(line, b)| (*line, b.iter().filter(|&&t| t).count(), b.len()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(taken(&parsed[0].1.branches), taken(&result.branches));
+    }
+
+    #[test]
+    fn test_parse_file_errors() {
+        match parse_file(Path::new("./test/invalid_DA_record.info"), true) {
+            Err(Error::Parse(name, _)) => assert_eq!(name, "./test/invalid_DA_record.info"),
+            r => panic!("Parse error expected, got {:?}", r),
+        }
+        match parse_file(Path::new("./test/not_existing.info"), true) {
+            Err(Error::Io(_)) => {}
+            r => panic!("IO error expected, got {:?}", r),
+        }
+        match parse_file(Path::new("./test/prova.gcda"), true) {
+            Err(Error::Config(_)) => {}
+            r => panic!("Config error expected, got {:?}", r),
+        }
+    }
+
     #[test]
     fn test_parser() {
         let results = parse_gcov(Path::new("./test/prova.gcov")).unwrap();
@@ -1725,6 +2288,7 @@ mod tests {
             Function {
                 executed: false,
                 start: 1,
+                end: None,
             },
         );
         functions.insert(
@@ -1732,6 +2296,7 @@ mod tests {
             Function {
                 executed: true,
                 start: 3,
+                end: None,
             },
         );
         let mut branches: BTreeMap<u32, Vec<bool>> = BTreeMap::new();
@@ -1742,6 +2307,7 @@ mod tests {
                 lines,
                 branches,
                 functions,
+                ..Default::default()
             },
         )];
 
@@ -1780,7 +2346,14 @@ mod tests {
             ),
             ("Person#setAge", 22, false),
         ] {
-            functions.insert(String::from(name), Function { start, executed });
+            functions.insert(
+                String::from(name),
+                Function {
+                    start,
+                    executed,
+                    end: None,
+                },
+            );
         }
         let branches: BTreeMap<u32, Vec<bool>> = BTreeMap::new();
         let expected = vec![(
@@ -1789,6 +2362,7 @@ mod tests {
                 lines,
                 branches,
                 functions,
+                ..Default::default()
             },
         )];
 
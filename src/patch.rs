@@ -0,0 +1,306 @@
+use rustc_hash::FxHashMap;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+use crate::defs::*;
+use crate::error::Error;
+use crate::output::{finish_output, get_target_output_writable, LineEnding};
+
+/// The lines changed in each file, as read from a unified diff or from a list of
+/// `path:line` pairs.
+pub type ChangedLines = FxHashMap<PathBuf, BTreeSet<u32>>;
+
+fn diff_path(path: &str) -> Option<PathBuf> {
+    // The timestamp, if any, is separated by a tab.
+    let path = path.split('\t').next().unwrap().trim_end();
+    if path == "/dev/null" {
+        return None;
+    }
+    let path = path
+        .strip_prefix("b/")
+        .or_else(|| path.strip_prefix("a/"))
+        .unwrap_or(path);
+    Some(PathBuf::from(path))
+}
+
+/// Parses a `start[,count]` range of a hunk header.
+fn hunk_range(range: &str) -> Option<(u32, u32)> {
+    let mut parts = range.splitn(2, ',');
+    let start = parts.next()?.parse().ok()?;
+    let count = match parts.next() {
+        Some(count) => count.parse().ok()?,
+        None => 1,
+    };
+    Some((start, count))
+}
+
+fn parse_diff(diff: &str) -> Result<ChangedLines, Error> {
+    let mut changed = ChangedLines::default();
+    let mut file: Option<PathBuf> = None;
+    // The number of the next line of the new version, and the numbers of lines of the old
+    // and new versions left in the current hunk.
+    let mut hunk: Option<(u32, u32, u32)> = None;
+
+    for line in diff.lines() {
+        if let Some((next, old_left, new_left)) = hunk.as_mut() {
+            match line.chars().next() {
+                Some('+') => {
+                    if let Some(file) = &file {
+                        changed.entry(file.clone()).or_default().insert(*next);
+                    }
+                    *next += 1;
+                    *new_left = new_left.saturating_sub(1);
+                }
+                Some('-') => *old_left = old_left.saturating_sub(1),
+                Some('\\') => {}
+                // Some tools strip the space of the empty context lines.
+                Some(' ') | None => {
+                    *next += 1;
+                    *old_left = old_left.saturating_sub(1);
+                    *new_left = new_left.saturating_sub(1);
+                }
+                Some(_) => {
+                    return Err(Error::Config(format!(
+                        "Invalid line '{}' in a diff hunk",
+                        line
+                    )))
+                }
+            }
+            if *old_left == 0 && *new_left == 0 {
+                hunk = None;
+            }
+        } else if let Some(path) = line.strip_prefix("+++ ") {
+            file = diff_path(path);
+        } else if let Some(header) = line.strip_prefix("@@ ") {
+            // e.g. `@@ -10,7 +10,8 @@ fn main() {`
+            let mut ranges = header.split_whitespace();
+            let old = ranges.next().and_then(|r| r.strip_prefix('-'));
+            let new = ranges.next().and_then(|r| r.strip_prefix('+'));
+            match (old.and_then(hunk_range), new.and_then(hunk_range)) {
+                (Some((_, old_count)), Some((start, new_count))) => {
+                    if old_count > 0 || new_count > 0 {
+                        hunk = Some((start, old_count, new_count));
+                    }
+                }
+                _ => return Err(Error::Config(format!("Invalid hunk header '{}'", line))),
+            }
+        }
+    }
+
+    Ok(changed)
+}
+
+fn parse_pairs(pairs: &str) -> Result<ChangedLines, Error> {
+    let mut changed = ChangedLines::default();
+    for pair in pairs.lines().map(str::trim).filter(|pair| !pair.is_empty()) {
+        let number = pair
+            .rfind(':')
+            .and_then(|colon| Some((&pair[..colon], pair[colon + 1..].parse::<u32>().ok()?)));
+        match number {
+            Some((path, number)) => {
+                changed
+                    .entry(PathBuf::from(path))
+                    .or_default()
+                    .insert(number);
+            }
+            None => {
+                return Err(Error::Config(format!(
+                    "Invalid changed line '{}', expected 'path:line'",
+                    pair
+                )))
+            }
+        }
+    }
+    Ok(changed)
+}
+
+/// Parses the changed lines from a unified diff (e.g. `git diff`), the lines added in the
+/// new version of each file, or from a list of `path:line` pairs, one per line.
+pub fn parse_changed_lines(input: &str) -> Result<ChangedLines, Error> {
+    if input
+        .lines()
+        .any(|line| line.starts_with("+++ ") || line.starts_with("@@ "))
+    {
+        parse_diff(input)
+    } else {
+        parse_pairs(input)
+    }
+}
+
+/// Reads the changed lines from a file, see `parse_changed_lines`.
+pub fn read_changed_lines(path: &Path) -> Result<ChangedLines, Error> {
+    parse_changed_lines(&fs::read_to_string(path)?)
+}
+
+/// Writes the coverage of the changed lines, the "patch coverage": for each file, the changed
+/// lines which are covered and the ones which aren't, and the percentage of covered lines.
+/// The changed lines without coverage data (e.g. comments) are left out.
+pub fn output_patch_coverage(
+    results: CovResultIter,
+    output_file: Option<&str>,
+    line_ending: LineEnding,
+    changed: &ChangedLines,
+) -> io::Result<()> {
+    let mut files = Map::new();
+    let (mut lines_covered, mut lines_valid) = (0, 0);
+
+    let mut results: Vec<_> = results
+        .filter_map(|(_, rel_path, result)| {
+            changed
+                .get(&rel_path)
+                .map(|changed_lines| (rel_path, result, changed_lines))
+        })
+        .collect();
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (rel_path, result, changed_lines) in results {
+        let (covered, uncovered): (Vec<u32>, Vec<u32>) = changed_lines
+            .iter()
+            .filter(|line| result.lines.contains_key(line))
+            .partition(|line| result.lines[line] > 0);
+        if covered.is_empty() && uncovered.is_empty() {
+            continue;
+        }
+        lines_covered += covered.len();
+        lines_valid += covered.len() + uncovered.len();
+        files.insert(
+            rel_path.to_string_lossy().into_owned(),
+            json!({
+                "covered": covered,
+                "uncovered": uncovered,
+            }),
+        );
+    }
+
+    let patch_coverage = if lines_valid > 0 {
+        lines_covered as f64 * 100.0 / lines_valid as f64
+    } else {
+        100.0
+    };
+    let report = json!({
+        "linesCovered": lines_covered as u64,
+        "linesValid": lines_valid as u64,
+        "patchCoverage": patch_coverage,
+        "files": Value::Object(files),
+    });
+
+    let mut writer = BufWriter::new(get_target_output_writable(output_file, line_ending)?);
+    serde_json::to_writer(&mut writer, &report)?;
+    finish_output(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    fn lines(changed: &ChangedLines, path: &str) -> Vec<u32> {
+        changed[Path::new(path)].iter().cloned().collect()
+    }
+
+    #[test]
+    fn test_parse_changed_lines_diff() {
+        let diff = "diff --git a/src/main.rs b/src/main.rs
+index 1111111..2222222 100644
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -1,4 +1,5 @@
+ fn main() {
+-    old();
++    new();
++    other();
+ }
+
+@@ -10,2 +11,3 @@ fn foo() {
+ a
++b
+ c
+diff --git a/src/removed.rs b/src/removed.rs
+deleted file mode 100644
+--- a/src/removed.rs
++++ /dev/null
+@@ -1,2 +0,0 @@
+-a
+-b
+diff --git a/src/new.rs b/src/new.rs
+new file mode 100644
+--- /dev/null
++++ b/src/new.rs
+@@ -0,0 +1,2 @@
++a
++b
+\\ No newline at end of file
+";
+        let changed = parse_changed_lines(diff).unwrap();
+        assert_eq!(changed.len(), 2);
+        assert_eq!(lines(&changed, "src/main.rs"), vec![2, 3, 12]);
+        assert_eq!(lines(&changed, "src/new.rs"), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_parse_changed_lines_pairs() {
+        let changed =
+            parse_changed_lines("src/main.rs:3\nsrc/main.rs:1\n\nsrc/lib.rs:7\n").unwrap();
+        assert_eq!(lines(&changed, "src/main.rs"), vec![1, 3]);
+        assert_eq!(lines(&changed, "src/lib.rs"), vec![7]);
+
+        assert!(parse_changed_lines("src/main.rs").is_err());
+        assert!(parse_changed_lines("+++ b/a.rs\n@@ -1 +x @@\n").is_err());
+        assert!(parse_changed_lines("+++ b/a.rs\n@@ -1 +1 @@\n?\n").is_err());
+    }
+
+    #[test]
+    fn test_output_patch_coverage() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("patch.json");
+
+        let results = vec![
+            (
+                PathBuf::from("/src/main.rs"),
+                PathBuf::from("src/main.rs"),
+                CovResult::with_lines(&[(1, 1), (2, 0), (3, 4), (5, 0)]),
+            ),
+            (
+                PathBuf::from("/src/lib.rs"),
+                PathBuf::from("src/lib.rs"),
+                CovResult::with_lines(&[(1, 0)]),
+            ),
+            (
+                PathBuf::from("/src/comments.rs"),
+                PathBuf::from("src/comments.rs"),
+                CovResult::with_lines(&[(1, 1)]),
+            ),
+        ];
+        // Line 4 of main.rs isn't executable, lib.rs isn't changed.
+        let changed =
+            parse_changed_lines("src/main.rs:2\nsrc/main.rs:3\nsrc/main.rs:4\nsrc/comments.rs:9\n")
+                .unwrap();
+
+        output_patch_coverage(
+            Box::new(results.into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+            &changed,
+        )
+        .unwrap();
+
+        let report: Value = serde_json::from_reader(File::open(&file_path).unwrap()).unwrap();
+        assert_eq!(
+            report,
+            json!({
+                "linesCovered": 1,
+                "linesValid": 2,
+                "patchCoverage": 50.0,
+                "files": {
+                    "src/main.rs": {
+                        "covered": [3],
+                        "uncovered": [2],
+                    },
+                },
+            })
+        );
+    }
+}
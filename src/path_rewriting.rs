@@ -1,6 +1,6 @@
-use globset::{Glob, GlobSet, GlobSetBuilder};
+use globset::{Glob, GlobMatcher, GlobSet, GlobSetBuilder};
 use rayon::prelude::*;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use serde_json::Value;
 use std::collections::hash_map;
 use std::fs;
@@ -9,6 +9,7 @@ use std::path::{Component, Path, PathBuf};
 use walkdir::{DirEntry, WalkDir};
 
 use crate::defs::*;
+use crate::error::Error;
 use crate::filter::*;
 
 fn to_lowercase_first(s: &str) -> String {
@@ -39,6 +40,14 @@ pub fn has_no_parent(path: &str) -> bool {
     PathBuf::from(path).parent() == Some(&PathBuf::from(""))
 }
 
+/// Returns the root of the git repository enclosing the directory, if any.
+pub fn find_git_root<P: AsRef<Path>>(dir: P) -> Option<PathBuf> {
+    let dir = canonicalize_path(dir).ok()?;
+    dir.ancestors()
+        .find(|ancestor| ancestor.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
 pub fn normalize_path<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
     // Copied from Cargo sources: https://github.com/rust-lang/cargo/blob/911f0b94e5c10f514b13affbeccd5fd2661a32d9/src/cargo/util/paths.rs#L60
     let mut components = path.as_ref().components().peekable();
@@ -73,6 +82,29 @@ pub fn normalize_path<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
     Some(ret)
 }
 
+/// Collapses `.` and `..` components of a path without touching the file system.
+///
+/// Unlike `normalize_path`, leading `..` components which can't be collapsed are kept.
+pub fn collapse_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut components: Vec<Component> = Vec::new();
+
+    for component in path.as_ref().components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match components.last() {
+                Some(Component::Normal(_)) => {
+                    components.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => components.push(component),
+            },
+            _ => components.push(component),
+        }
+    }
+
+    components.iter().collect()
+}
+
 // Search the source file's path in the mapping.
 fn apply_mapping(mapping: &Option<Value>, path: &str) -> PathBuf {
     if let Some(mapping) = mapping {
@@ -215,6 +247,13 @@ fn is_symbolic_link(entry: &DirEntry) -> bool {
     entry.path_is_symlink()
 }
 
+fn is_ignored(entry: &DirEntry, root: &Path, to_ignore_globset: &GlobSet) -> bool {
+    match entry.path().strip_prefix(root) {
+        Ok(rel_path) => to_ignore_globset.is_match(rel_path),
+        Err(_) => false,
+    }
+}
+
 fn to_globset(dirs: &[&str]) -> GlobSet {
     let mut glob_builder = GlobSetBuilder::new();
 
@@ -234,6 +273,8 @@ pub fn rewrite_paths(
     to_ignore_dirs: &[&str],
     to_keep_dirs: &[&str],
     filter_option: Option<bool>,
+    keep_excluded: bool,
+    untested_files: Option<&GlobMatcher>,
     file_filter: crate::FileFilter,
 ) -> CovResultIter {
     let to_ignore_globset = to_globset(to_ignore_dirs);
@@ -273,9 +314,9 @@ pub fn rewrite_paths(
         }
     }
 
-    let results = result_map
+    let mut results: Vec<(PathBuf, PathBuf, CovResult)> = result_map
         .into_par_iter()
-        .filter_map(move |(path, mut result)| {
+        .filter_map(|(path, result)| {
             let path = path.replace("\\", "/");
 
             // Get path from the mapping.
@@ -293,12 +334,28 @@ pub fn rewrite_paths(
 
             // Get absolute path to the source file.
             let (abs_path, rel_path) = get_abs_path(source_dir, rel_path)?;
+            Some((abs_path, rel_path, result))
+        })
+        .collect();
 
-            if to_ignore_globset.is_match(&rel_path) {
-                return None;
-            }
+    // The untested files are looked for before the filtering, for the files left out on
+    // purpose not to come back as uncovered. They are filtered like the other files.
+    if let Some(matcher) = untested_files {
+        let tested: FxHashSet<&Path> = results
+            .iter()
+            .map(|(abs_path, _, _)| abs_path.as_path())
+            .collect();
+        let untested = find_untested_files(source_dir, matcher, &to_ignore_globset, &tested);
+        results.extend(untested);
+    }
 
-            if !to_keep_globset.is_empty() && !to_keep_globset.is_match(&rel_path) {
+    let results = results
+        .into_par_iter()
+        .filter_map(move |(abs_path, rel_path, mut result)| {
+            // The excluded files are dropped, unless they must be reported as such.
+            let mut excluded = to_ignore_globset.is_match(&rel_path)
+                || (!to_keep_globset.is_empty() && !to_keep_globset.is_match(&rel_path));
+            if excluded && !keep_excluded {
                 return None;
             }
 
@@ -313,6 +370,7 @@ pub fn rewrite_paths(
                 match filter {
                     crate::FilterType::Both(number) => {
                         result.branches.remove(&number);
+                        result.branch_hits.remove(&number);
                         result.lines.remove(&number);
                     }
                     crate::FilterType::Line(number) => {
@@ -320,23 +378,19 @@ pub fn rewrite_paths(
                     }
                     crate::FilterType::Branch(number) => {
                         result.branches.remove(&number);
+                        result.branch_hits.remove(&number);
                     }
                 }
             }
 
-            match filter_option {
-                Some(true) => {
-                    if !is_covered(&result) {
-                        return None;
-                    }
-                }
-                Some(false) => {
-                    if is_covered(&result) {
-                        return None;
-                    }
-                }
-                None => (),
+            excluded |= match filter_option {
+                Some(covered) => is_covered(&result) != covered,
+                None => false,
             };
+            if excluded && !keep_excluded {
+                return None;
+            }
+            result.excluded = excluded;
 
             Some((abs_path, rel_path, result))
         });
@@ -348,11 +402,155 @@ pub fn rewrite_paths(
     )
 }
 
+/// Makes sure that no absolute path is emitted, since some consumers (e.g. GitLab) can't
+/// handle them. Returns an error for the first absolute path, which is usually a sign that
+/// the source directory or the prefix to remove are misconfigured.
+pub fn check_relative_paths(results: CovResultIter) -> Result<CovResultIter, Error> {
+    let results = results
+        .map(|(abs_path, rel_path, result)| {
+            if rel_path.is_absolute() || rel_path.has_root() {
+                Err(Error::Config(format!(
+                    "The path {} is absolute, check the source directory (-s) and the prefix to remove (-p).",
+                    rel_path.display()
+                )))
+            } else {
+                Ok((abs_path, rel_path, result))
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Box::new(results.into_iter()))
+}
+
+/// Prepends a prefix to the relative paths, e.g. the subdirectory of the project grcov is run
+/// from, for the paths to be relative to the root of the repository. Both slashes and
+/// backslashes separate the components of the prefix, and the empty components are skipped.
+pub fn prepend_path_prefix(results: CovResultIter, prefix: &str) -> CovResultIter {
+    let prefix: PathBuf = prefix
+        .split(['/', '\\'])
+        .filter(|component| !component.is_empty())
+        .collect();
+    Box::new(results.map(move |(abs_path, rel_path, result)| {
+        if rel_path.is_relative() {
+            (abs_path, prefix.join(rel_path), result)
+        } else {
+            (abs_path, rel_path, result)
+        }
+    }))
+}
+
+/// Returns the line numbers of a source file which look executable, i.e. which are neither
+/// blank nor only made of `//` or `/* */` comments.
+fn executable_lines(source: &str) -> Vec<u32> {
+    let mut in_comment = false;
+    let mut lines = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let mut line = line.trim();
+        loop {
+            if in_comment {
+                match line.find("*/") {
+                    Some(end) => {
+                        in_comment = false;
+                        line = line[end + 2..].trim_start();
+                    }
+                    None => {
+                        line = "";
+                        break;
+                    }
+                }
+            } else if line.starts_with("/*") {
+                in_comment = true;
+                line = &line[2..];
+            } else {
+                break;
+            }
+        }
+        if !line.is_empty() && !line.starts_with("//") {
+            lines.push(i as u32 + 1);
+            // A comment may start after the code, e.g. `foo(); /* bar`.
+            if let Some(start) = line.rfind("/*") {
+                in_comment = !line[start..].contains("*/");
+            }
+        }
+    }
+    lines
+}
+
+/// Returns the source files matching the glob, relative to the source directory (or to the
+/// current directory), which aren't in the results, e.g. because they were never compiled.
+/// Their executable lines are reported as uncovered, for the totals not to overstate the
+/// coverage. The hidden directories are skipped, as well as the files and the directories
+/// matching the ignore globs, e.g. the build outputs or the dependencies.
+fn find_untested_files(
+    source_dir: Option<&Path>,
+    matcher: &GlobMatcher,
+    to_ignore_globset: &GlobSet,
+    tested: &FxHashSet<&Path>,
+) -> Vec<(PathBuf, PathBuf, CovResult)> {
+    let root = match source_dir {
+        Some(source_dir) => source_dir.to_path_buf(),
+        None => match std::env::current_dir().and_then(canonicalize_path) {
+            Ok(current_dir) => current_dir,
+            Err(_) => return Vec::new(),
+        },
+    };
+
+    let mut untested = Vec::new();
+    for entry in WalkDir::new(&root)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_entry(|e| {
+            e.depth() == 0
+                || !(is_hidden(e) || is_symbolic_link(e) || is_ignored(e, &root, to_ignore_globset))
+        })
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+    {
+        let abs_path = entry.path();
+        let rel_path = match abs_path.strip_prefix(&root) {
+            Ok(rel_path) => rel_path.to_path_buf(),
+            Err(_) => continue,
+        };
+        if !matcher.is_match(&rel_path) || tested.contains(abs_path) {
+            continue;
+        }
+        let source = match fs::read_to_string(abs_path) {
+            Ok(source) => source,
+            Err(_) => continue,
+        };
+        let result = CovResult {
+            lines: executable_lines(&source)
+                .into_iter()
+                .map(|line| (line, 0))
+                .collect(),
+            ..Default::default()
+        };
+        untested.push((abs_path.to_path_buf(), rel_path, result));
+    }
+    untested
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use serde_json::json;
-    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_find_git_root() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let root = canonicalize_path(tmp_dir.path()).unwrap();
+        let nested = root.join("src").join("core");
+        fs::create_dir_all(&nested).unwrap();
+
+        // Not in a repository under the temporary directory.
+        assert!(find_git_root(&nested)
+            .filter(|git_root| git_root.starts_with(&root))
+            .is_none());
+
+        fs::create_dir(root.join(".git")).unwrap();
+        assert_eq!(find_git_root(&nested), Some(root.clone()));
+        assert_eq!(find_git_root(&root), Some(root.clone()));
+        assert_eq!(find_git_root(root.join("not_existing")), None);
+    }
 
     #[test]
     fn test_to_lowercase_first() {
@@ -380,11 +578,7 @@ mod tests {
 
     macro_rules! empty_result {
         () => {{
-            CovResult {
-                lines: BTreeMap::new(),
-                branches: BTreeMap::new(),
-                functions: FxHashMap::default(),
-            }
+            CovResult::default()
         }};
     }
 
@@ -392,8 +586,7 @@ mod tests {
         () => {{
             CovResult {
                 lines: [(42, 1)].iter().cloned().collect(),
-                branches: BTreeMap::new(),
-                functions: FxHashMap::default(),
+                ..Default::default()
             }
         }};
     }
@@ -402,8 +595,7 @@ mod tests {
         () => {{
             CovResult {
                 lines: [(42, 0)].iter().cloned().collect(),
-                branches: BTreeMap::new(),
-                functions: FxHashMap::default(),
+                ..Default::default()
             }
         }};
     }
@@ -432,6 +624,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -461,6 +655,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -490,6 +686,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -519,6 +717,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -548,6 +748,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -575,6 +777,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -607,6 +811,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -635,6 +841,8 @@ mod tests {
             &["mydir/*"],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -662,6 +870,8 @@ mod tests {
             &["mydir/*"],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -693,6 +903,8 @@ mod tests {
                 &ignore_dirs,
                 &[],
                 None,
+                false,
+                None,
                 Default::default(),
             );
             let mut count = 0;
@@ -726,6 +938,8 @@ mod tests {
                 &ignore_dirs,
                 &[],
                 None,
+                false,
+                None,
                 Default::default(),
             );
             let mut count = 0;
@@ -755,6 +969,8 @@ mod tests {
             &[],
             &["mydir/*"],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -782,6 +998,8 @@ mod tests {
             &[""; 0],
             &["mydir/*"],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -813,6 +1031,8 @@ mod tests {
                 &[],
                 &keep_only_dirs,
                 None,
+                false,
+                None,
                 Default::default(),
             );
             let mut count = 0;
@@ -846,6 +1066,8 @@ mod tests {
                 &[],
                 &keep_only_dirs,
                 None,
+                false,
+                None,
                 Default::default(),
             );
             let mut count = 0;
@@ -877,6 +1099,8 @@ mod tests {
             &["foo/bar_*.rs"],
             &["foo/*.rs"],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -906,6 +1130,8 @@ mod tests {
             &["foo/bar_*.rs"],
             &["foo/*.rs"],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -918,6 +1144,77 @@ mod tests {
         assert_eq!(count, 1);
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_rewrite_paths_ignore_cobertura() {
+        let mut result_map: CovResultMap = FxHashMap::default();
+        result_map.insert("src/main.rs".to_string(), covered_result!());
+        result_map.insert("target/debug/build/out.rs".to_string(), covered_result!());
+        result_map.insert("third_party/zlib/a.c".to_string(), covered_result!());
+        result_map.insert("third_party/zlib/ab.c".to_string(), covered_result!());
+        let results = rewrite_paths(
+            result_map,
+            None,
+            None,
+            None,
+            false,
+            &["target/**", "third_party/*/?.c"],
+            &[],
+            None,
+            false,
+            None,
+            Default::default(),
+        );
+
+        let report = String::from_utf8(crate::cobertura_to_bytes(
+            results,
+            crate::DemangleConfig::default(),
+            &Default::default(),
+        ))
+        .unwrap();
+        assert!(report.contains(r#"filename="src/main.rs""#));
+        assert!(report.contains(r#"filename="third_party/zlib/ab.c""#));
+        assert!(!report.contains("target/debug"));
+        assert!(!report.contains(r#"filename="third_party/zlib/a.c""#));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rewrite_paths_keep_only_cobertura() {
+        let mut result_map: CovResultMap = FxHashMap::default();
+        result_map.insert("src/main.rs".to_string(), covered_result!());
+        result_map.insert("src/gen/bindings.rs".to_string(), covered_result!());
+        result_map.insert("build.rs".to_string(), covered_result!());
+        result_map.insert("vendor/src/lib.rs".to_string(), covered_result!());
+        // The kept files are then filtered by the ignored ones.
+        let results = rewrite_paths(
+            result_map,
+            None,
+            None,
+            None,
+            false,
+            &["src/gen/**"],
+            &["src/**"],
+            None,
+            false,
+            None,
+            Default::default(),
+        );
+
+        let report = String::from_utf8(crate::cobertura_to_bytes(
+            results,
+            crate::DemangleConfig::default(),
+            &Default::default(),
+        ))
+        .unwrap();
+        let file_names: Vec<&str> = report
+            .split(r#"filename=""#)
+            .skip(1)
+            .map(|rest| &rest[..rest.find('"').unwrap()])
+            .collect();
+        assert_eq!(file_names, vec!["src/main.rs"]);
+    }
+
     #[test]
     #[should_panic]
     fn test_rewrite_paths_rewrite_path_using_relative_source_directory() {
@@ -931,6 +1228,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         )
         .any(|_| false);
@@ -951,6 +1250,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -979,6 +1280,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -1006,6 +1309,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -1033,6 +1338,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -1061,6 +1368,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut results: Vec<(PathBuf, PathBuf, CovResult)> = results.collect();
@@ -1088,6 +1397,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut results: Vec<(PathBuf, PathBuf, CovResult)> = results.collect();
@@ -1114,6 +1425,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -1141,6 +1454,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -1171,6 +1486,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -1202,6 +1519,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -1229,6 +1548,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -1255,6 +1576,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -1284,6 +1607,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -1314,6 +1639,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -1344,6 +1671,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -1377,6 +1706,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -1406,6 +1737,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -1435,6 +1768,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -1464,6 +1799,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -1494,6 +1831,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -1524,6 +1863,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -1551,6 +1892,8 @@ mod tests {
             &[],
             &[],
             Some(true),
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -1577,6 +1920,8 @@ mod tests {
             &[],
             &[],
             Some(false),
+            false,
+            None,
             Default::default(),
         );
         let mut count = 0;
@@ -1611,6 +1956,80 @@ mod tests {
         assert!(normalize_path("bar/foo/../../../oof/").is_none());
     }
 
+    #[test]
+    fn test_collapse_path() {
+        assert_eq!(collapse_path("./foo/bar"), PathBuf::from("foo/bar"));
+        assert_eq!(
+            collapse_path("src/../src/lib.rs"),
+            PathBuf::from("src/lib.rs")
+        );
+        assert_eq!(collapse_path("/foo/../../bar"), PathBuf::from("/bar"));
+        assert_eq!(collapse_path("../foo/./../bar"), PathBuf::from("../bar"));
+        assert_eq!(collapse_path("foo/../../bar"), PathBuf::from("../bar"));
+    }
+
+    #[test]
+    fn test_check_relative_paths() {
+        let results = vec![(
+            PathBuf::from("/home/user/src/lib.rs"),
+            PathBuf::from("src/lib.rs"),
+            empty_result!(),
+        )];
+        let results: Vec<_> = check_relative_paths(Box::new(results.into_iter()))
+            .unwrap()
+            .collect();
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn test_check_relative_paths_absolute() {
+        let results = vec![(
+            PathBuf::from("/home/user/src/lib.rs"),
+            PathBuf::from("/home/user/src/lib.rs"),
+            empty_result!(),
+        )];
+        match check_relative_paths(Box::new(results.into_iter())) {
+            Err(Error::Config(message)) => assert!(message.contains("is absolute"), "{}", message),
+            _ => panic!("A configuration error expected"),
+        }
+    }
+
+    #[test]
+    fn test_prepend_path_prefix() {
+        let results = || {
+            let results = vec![
+                (
+                    PathBuf::from("/home/user/crates/foo/src/lib.rs"),
+                    PathBuf::from("src/lib.rs"),
+                    covered_result!(),
+                ),
+                (
+                    PathBuf::from("/usr/include/stdio.h"),
+                    PathBuf::from("/usr/include/stdio.h"),
+                    covered_result!(),
+                ),
+            ];
+            Box::new(results.into_iter())
+        };
+
+        let rewritten: Vec<_> = prepend_path_prefix(results(), "crates\\foo//").collect();
+        assert_eq!(rewritten[0].1, PathBuf::from("crates/foo/src/lib.rs"));
+        // The absolute paths are left as they are.
+        assert_eq!(rewritten[1].1, PathBuf::from("/usr/include/stdio.h"));
+
+        let bytes = crate::cobertura_to_bytes(
+            prepend_path_prefix(results(), "crates/foo"),
+            crate::DemangleConfig::default(),
+            &crate::CoberturaConfig::default(),
+        );
+        let report = String::from_utf8(bytes).unwrap();
+        assert!(
+            report.contains(r#"filename="crates/foo/src/lib.rs""#),
+            "{}",
+            report
+        );
+    }
+
     #[test]
     fn test_has_no_parent() {
         assert!(has_no_parent("foo.bar"));
@@ -1624,6 +2043,67 @@ mod tests {
         assert!(!has_no_parent("/foo/bar.oof"));
     }
 
+    #[test]
+    fn test_rewrite_paths_filter_branches_cobertura_rates() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let source_dir = canonicalize_path(tmp_dir.path()).unwrap();
+        fs::write(
+            source_dir.join("main.rs"),
+            "fn main() {\n    if cfg!(debug_assertions) { // debug-only\n        println!(\"debug\");\n    }\n    if x() { y() }\n}\n",
+        )
+        .unwrap();
+
+        let report = |excl_br_line: Option<&str>| {
+            let mut result_map: CovResultMap = FxHashMap::default();
+            result_map.insert(
+                "main.rs".to_string(),
+                CovResult {
+                    lines: [(1, 1), (2, 1), (3, 1), (5, 1)].iter().cloned().collect(),
+                    branches: [(2, vec![true, false]), (5, vec![true, true])]
+                        .iter()
+                        .cloned()
+                        .collect(),
+                    ..Default::default()
+                },
+            );
+            let results = rewrite_paths(
+                result_map,
+                None,
+                Some(&source_dir),
+                None,
+                false,
+                &[],
+                &[],
+                None,
+                false,
+                None,
+                crate::FileFilter::new(
+                    None,
+                    None,
+                    None,
+                    excl_br_line.map(|excl| regex::Regex::new(excl).unwrap()),
+                    None,
+                    None,
+                    false,
+                ),
+            );
+            let bytes = crate::cobertura_to_bytes(
+                Box::new(results.collect::<Vec<_>>().into_iter()),
+                crate::DemangleConfig::default(),
+                &crate::CoberturaConfig::default(),
+            );
+            String::from_utf8(bytes).unwrap()
+        };
+
+        let report_all = report(None);
+        assert!(report_all.contains(r#"branches-valid="4" branch-rate="0.75""#));
+
+        // The excluded line still counts for the line rate, not for the branch rate.
+        let report_excluded = report(Some("debug-only"));
+        assert!(report_excluded.contains(r#"lines-valid="4" line-rate="1""#));
+        assert!(report_excluded.contains(r#"branches-valid="2" branch-rate="1""#));
+    }
+
     #[cfg(unix)]
     #[test]
     fn test_rewrite_paths_filter_lines_and_branches() {
@@ -1638,6 +2118,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             crate::FileFilter::new(
                 Some(regex::Regex::new("excluded line").unwrap()),
                 Some(regex::Regex::new("skip line start").unwrap()),
@@ -1645,6 +2127,7 @@ mod tests {
                 Some(regex::Regex::new("excluded branch").unwrap()),
                 Some(regex::Regex::new("skip branch start").unwrap()),
                 Some(regex::Regex::new("skip branch end").unwrap()),
+                false,
             ),
         );
         let mut count = 0;
@@ -1681,6 +2164,8 @@ mod tests {
             &[],
             &[],
             None,
+            false,
+            None,
             crate::FileFilter::new(
                 Some(regex::Regex::new("excluded line").unwrap()),
                 Some(regex::Regex::new("skip line start").unwrap()),
@@ -1688,6 +2173,7 @@ mod tests {
                 Some(regex::Regex::new("excluded branch").unwrap()),
                 Some(regex::Regex::new("skip branch start").unwrap()),
                 Some(regex::Regex::new("skip branch end").unwrap()),
+                false,
             ),
         );
         let mut count = 0;
@@ -1709,4 +2195,140 @@ mod tests {
         }
         assert_eq!(count, 1);
     }
+
+    #[test]
+    fn test_executable_lines() {
+        let source = "// Header\n\
+                      \n\
+                      fn main() {\n\
+                      \x20   /* A\n\
+                      \x20      comment */\n\
+                      \x20   foo(); /* bar\n\
+                      \x20   baz */\n\
+                      \x20   /* qux */ quux();\n\
+                      }\n";
+        assert_eq!(executable_lines(source), vec![3, 6, 8, 9]);
+    }
+
+    fn rewrite_untested_paths(
+        result_map: CovResultMap,
+        source_dir: &Path,
+        to_ignore_dirs: &[&str],
+        glob: &str,
+    ) -> Vec<(PathBuf, PathBuf, CovResult)> {
+        let matcher = Glob::new(glob).unwrap().compile_matcher();
+        let mut results: Vec<_> = rewrite_paths(
+            result_map,
+            None,
+            Some(source_dir),
+            None,
+            false,
+            to_ignore_dirs,
+            &[],
+            None,
+            false,
+            Some(&matcher),
+            Default::default(),
+        )
+        .collect();
+        results.sort_by(|a, b| a.1.cmp(&b.1));
+        results
+    }
+
+    #[test]
+    fn test_add_untested_files() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let source_dir = canonicalize_path(tmp_dir.path()).unwrap();
+        fs::create_dir_all(source_dir.join("src").join("feature")).unwrap();
+        fs::write(source_dir.join("src").join("main.rs"), "fn main() {}\n").unwrap();
+        fs::write(
+            source_dir.join("src").join("feature").join("mod.rs"),
+            "// Disabled.\n\npub fn f() {\n    g();\n}\n",
+        )
+        .unwrap();
+        fs::write(source_dir.join("README.md"), "# Readme\n").unwrap();
+
+        let result = CovResult {
+            lines: [(1, 1)].iter().cloned().collect(),
+            ..Default::default()
+        };
+        // The tested file is known by its absolute path.
+        let mut result_map: CovResultMap = FxHashMap::default();
+        result_map.insert(
+            source_dir
+                .join("src")
+                .join("main.rs")
+                .to_str()
+                .unwrap()
+                .to_string(),
+            result.clone(),
+        );
+        let results = rewrite_untested_paths(result_map, &source_dir, &[], "src/**/*.rs");
+
+        assert_eq!(results.len(), 2);
+        let (abs_path, rel_path, untested) = &results[0];
+        assert_eq!(
+            abs_path,
+            &source_dir.join("src").join("feature").join("mod.rs")
+        );
+        assert_eq!(rel_path, &PathBuf::from("src/feature/mod.rs"));
+        assert_eq!(
+            untested.lines,
+            [(3, 0), (4, 0), (5, 0)].iter().cloned().collect()
+        );
+        assert_eq!(results[1].1, PathBuf::from("src/main.rs"));
+        assert_eq!(results[1].2, result);
+    }
+
+    #[test]
+    fn test_add_untested_files_filtered() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let source_dir = canonicalize_path(tmp_dir.path()).unwrap();
+        for dir in &["src", "target/debug", "build", ".cargo", "vendor"] {
+            fs::create_dir_all(source_dir.join(dir)).unwrap();
+        }
+        for file in &[
+            "src/main.rs",
+            "src/lib.rs",
+            "target/debug/build.rs",
+            "build/generated.rs",
+            ".cargo/config.rs",
+            "vendor/dep.rs",
+        ] {
+            fs::write(source_dir.join(file), "fn f() {}\n").unwrap();
+        }
+
+        let mut result_map: CovResultMap = FxHashMap::default();
+        result_map.insert("src/main.rs".to_string(), empty_result!());
+        // The ignored files and directories, tested or not, aren't added back, nor the files
+        // of the hidden directories. The other directories, e.g. build, are walked.
+        let results = rewrite_untested_paths(
+            result_map,
+            &source_dir,
+            &["src/main.rs", "target", "vendor/*"],
+            "**/*.rs",
+        );
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].1, PathBuf::from("build/generated.rs"));
+        assert_eq!(results[1].1, PathBuf::from("src/lib.rs"));
+        assert_eq!(results[1].2.lines, [(1, 0)].iter().cloned().collect());
+    }
+
+    #[test]
+    fn test_add_untested_files_block_comment() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let source_dir = canonicalize_path(tmp_dir.path()).unwrap();
+        fs::write(
+            source_dir.join("lib.rs"),
+            "/* A comment\n   on two lines. */\npub fn f() {}\n",
+        )
+        .unwrap();
+
+        let results = rewrite_untested_paths(FxHashMap::default(), &source_dir, &[], "*.rs");
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].1, PathBuf::from("lib.rs"));
+        assert_eq!(results[0].2.lines, [(3, 0)].iter().cloned().collect());
+    }
 }
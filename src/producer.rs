@@ -1,7 +1,7 @@
 extern crate tempfile;
 
 use rustc_hash::FxHashMap;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::env;
 use std::fs::{self, File};
 use std::io::{self, BufReader, Read};
@@ -11,6 +11,7 @@ use walkdir::WalkDir;
 use zip::ZipArchive;
 
 use crate::defs::*;
+use crate::error::Error;
 
 #[derive(Debug)]
 pub enum ArchiveType {
@@ -62,52 +63,104 @@ impl Archive {
         profraws: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
         infos: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
         xmls: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
+        cobertura_xmls: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
+        gcov_jsons: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
+        grcov_jsons: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
         linked_files_maps: &RefCell<FxHashMap<String, &'a Archive>>,
         is_llvm: bool,
-    ) {
-        if let Some(ext) = path.extension() {
-            match ext.to_str().unwrap() {
-                "gcno" => {
-                    let llvm = is_llvm || Archive::check_file(file, &Archive::is_gcno_llvm);
-                    let filename = clean_path(&path.with_extension(""));
-                    gcno_stem_archives.borrow_mut().insert(
-                        GCNOStem {
-                            stem: filename,
-                            llvm,
-                        },
-                        self,
-                    );
+        files_left: &Cell<usize>,
+    ) -> Result<(), Error> {
+        // The Cobertura and grcov reports are only read when given explicitly: found in a
+        // directory, they could be the reports of a previous run, whose counts would be added
+        // again.
+        let explicit = matches!(*self.item.borrow(), ArchiveType::Plain(_));
+
+        let recognized = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gcno") => {
+                let llvm = is_llvm || Archive::check_file(file, &Archive::is_gcno_llvm);
+                let filename = clean_path(&path.with_extension(""));
+                gcno_stem_archives.borrow_mut().insert(
+                    GCNOStem {
+                        stem: filename,
+                        llvm,
+                    },
+                    self,
+                );
+                true
+            }
+            Some("gcda") => {
+                let filename = clean_path(&path.with_extension(""));
+                self.insert_vec(filename, gcda_stem_archives);
+                true
+            }
+            Some("profraw") => {
+                let filename = clean_path(path);
+                self.insert_vec(filename, profraws);
+                true
+            }
+            Some("info") => {
+                let info = Archive::check_file(file, &Archive::is_info);
+                if info {
+                    let filename = clean_path(path);
+                    self.insert_vec(filename, infos);
                 }
-                "gcda" => {
-                    let filename = clean_path(&path.with_extension(""));
-                    self.insert_vec(filename, gcda_stem_archives);
+                info
+            }
+            Some("xml") => {
+                // Both JaCoCo and Cobertura reports are XML files, sniff the header once.
+                let mut header = Vec::new();
+                if let Some(file) = file {
+                    let _ = file.take(1024).read_to_end(&mut header);
                 }
-                "profraw" => {
+                if Archive::is_jacoco(&mut header.as_slice()) {
                     let filename = clean_path(path);
-                    self.insert_vec(filename, profraws);
-                }
-                "info" => {
-                    if Archive::check_file(file, &Archive::is_info) {
-                        let filename = clean_path(path);
-                        self.insert_vec(filename, infos);
-                    }
+                    self.insert_vec(filename, xmls);
+                    true
+                } else if explicit && Archive::is_cobertura(&mut header.as_slice()) {
+                    let filename = clean_path(path);
+                    self.insert_vec(filename, cobertura_xmls);
+                    true
+                } else {
+                    false
                 }
-                "xml" => {
-                    if Archive::check_file(file, &Archive::is_jacoco) {
-                        let filename = clean_path(path);
-                        self.insert_vec(filename, xmls);
-                    }
+            }
+            Some("gz") => {
+                let filename = clean_path(path);
+                let gcov_json = filename.ends_with(".gcov.json.gz");
+                if gcov_json {
+                    self.insert_vec(filename, gcov_jsons);
                 }
-                "json" => {
-                    let filename = path.file_name().unwrap();
-                    if filename == "linked-files-map.json" {
-                        let filename = clean_path(path);
-                        linked_files_maps.borrow_mut().insert(filename, self);
-                    }
+                gcov_json
+            }
+            Some("json") => {
+                let filename = path.file_name().unwrap();
+                if filename == "linked-files-map.json" {
+                    let filename = clean_path(path);
+                    linked_files_maps.borrow_mut().insert(filename, self);
+                    false
+                } else if explicit && Archive::check_file(file, &Archive::is_grcov_json) {
+                    let filename = clean_path(path);
+                    self.insert_vec(filename, grcov_jsons);
+                    true
+                } else {
+                    false
                 }
-                _ => {}
             }
+            _ => false,
+        };
+
+        // Guard against input paths pointing to huge directories by mistake. Only the coverage
+        // files count, not the sources or the build outputs next to them.
+        if recognized {
+            if files_left.get() == 0 {
+                return Err(Error::Config(format!(
+                    "Too many input files found in '{}', check the input paths or raise --max-files.",
+                    self.name
+                )));
+            }
+            files_left.set(files_left.get() - 1);
         }
+        Ok(())
     }
 
     fn is_gcno_llvm(reader: &mut dyn Read) -> bool {
@@ -128,6 +181,24 @@ impl Archive {
         false
     }
 
+    fn is_cobertura(reader: &mut dyn Read) -> bool {
+        let mut bytes = Vec::new();
+        reader.take(1024).read_to_end(&mut bytes).is_ok()
+            && String::from_utf8_lossy(&bytes).contains("<coverage")
+    }
+
+    fn is_grcov_json(reader: &mut dyn Read) -> bool {
+        // The header is written before the files by the `json` output type.
+        let header = b"{\"format\":\"grcov\"";
+        let mut bytes = Vec::new();
+        reader.take(64).read_to_end(&mut bytes).is_ok()
+            && bytes
+                .iter()
+                .filter(|b| !b.is_ascii_whitespace())
+                .take(header.len())
+                .eq(header.iter())
+    }
+
     fn is_info(reader: &mut dyn Read) -> bool {
         let mut bytes: [u8; 3] = [0; 3];
         reader.read_exact(&mut bytes).is_ok()
@@ -142,6 +213,14 @@ impl Archive {
         &self.name
     }
 
+    /// Returns the name of one of the files of the archive, as shown to the user.
+    pub fn get_file_name(&self, name: &str) -> String {
+        match *self.item.borrow() {
+            ArchiveType::Plain(_) => name.to_string(),
+            _ => format!("{}/{}", self.name, name),
+        }
+    }
+
     pub fn explore<'a>(
         &'a mut self,
         gcno_stem_archives: &RefCell<FxHashMap<GCNOStem, &'a Archive>>,
@@ -149,9 +228,13 @@ impl Archive {
         profraws: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
         infos: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
         xmls: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
+        cobertura_xmls: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
+        gcov_jsons: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
+        grcov_jsons: &RefCell<FxHashMap<String, Vec<&'a Archive>>>,
         linked_files_maps: &RefCell<FxHashMap<String, &'a Archive>>,
         is_llvm: bool,
-    ) {
+        files_left: &Cell<usize>,
+    ) -> Result<(), Error> {
         match *self.item.borrow() {
             ArchiveType::Zip(ref zip) => {
                 let mut zip = zip.borrow_mut();
@@ -166,9 +249,13 @@ impl Archive {
                         profraws,
                         infos,
                         xmls,
+                        cobertura_xmls,
+                        gcov_jsons,
+                        grcov_jsons,
                         linked_files_maps,
                         is_llvm,
-                    );
+                        files_left,
+                    )?;
                 }
             }
             ArchiveType::Dir(ref dir) => {
@@ -187,9 +274,13 @@ impl Archive {
                             profraws,
                             infos,
                             xmls,
+                            cobertura_xmls,
+                            gcov_jsons,
+                            grcov_jsons,
                             linked_files_maps,
                             is_llvm,
-                        );
+                            files_left,
+                        )?;
                     }
                 }
             }
@@ -205,12 +296,17 @@ impl Archive {
                         profraws,
                         infos,
                         xmls,
+                        cobertura_xmls,
+                        gcov_jsons,
+                        grcov_jsons,
                         linked_files_maps,
                         is_llvm,
-                    );
+                        files_left,
+                    )?;
                 }
             }
         }
+        Ok(())
     }
 
     pub fn read(&self, name: &str) -> Option<Vec<u8>> {
@@ -449,7 +545,7 @@ fn file_content_producer(
                     .send(Some(WorkItem {
                         format: item_format,
                         item: ItemType::Content(buffer),
-                        name: archive.get_name().to_string(),
+                        name: archive.get_file_name(name),
                     }))
                     .unwrap();
             }
@@ -477,7 +573,8 @@ pub fn producer(
     sender: &JobSender,
     ignore_orphan_gcno: bool,
     is_llvm: bool,
-) -> Option<Vec<u8>> {
+    max_files: usize,
+) -> Result<Option<Vec<u8>>, Error> {
     let mut archives: Vec<Archive> = Vec::new();
     let mut plain_files: Vec<PathBuf> = Vec::new();
 
@@ -504,16 +601,21 @@ pub fn producer(
                 });
             } else if let Some(ext) = full_path.clone().extension() {
                 let ext = ext.to_str().unwrap();
-                if ext == "info" || ext == "json" || ext == "xml" || ext == "profraw" {
+                if ext == "info"
+                    || ext == "json"
+                    || ext == "xml"
+                    || ext == "profraw"
+                    || path.ends_with(".gcov.json.gz")
+                {
                     plain_files.push(full_path);
                 } else {
                     panic!(
-                        "Cannot load file '{:?}': it isn't a .info, a .json or a .xml file.",
+                        "Cannot load file '{:?}': it isn't a .info, a .json, a .gcov.json.gz or a .xml file.",
                         full_path
                     );
                 }
             } else {
-                panic!("Cannot load file '{:?}': it isn't a directory, a .info, a .json or a .xml file.", full_path);
+                panic!("Cannot load file '{:?}': it isn't a directory, a .info, a .json, a .gcov.json.gz or a .xml file.", full_path);
             }
         }
     }
@@ -532,9 +634,14 @@ pub fn producer(
     let profraws: RefCell<FxHashMap<String, Vec<&Archive>>> = RefCell::new(FxHashMap::default());
     let infos: RefCell<FxHashMap<String, Vec<&Archive>>> = RefCell::new(FxHashMap::default());
     let xmls: RefCell<FxHashMap<String, Vec<&Archive>>> = RefCell::new(FxHashMap::default());
+    let cobertura_xmls: RefCell<FxHashMap<String, Vec<&Archive>>> =
+        RefCell::new(FxHashMap::default());
+    let gcov_jsons: RefCell<FxHashMap<String, Vec<&Archive>>> = RefCell::new(FxHashMap::default());
+    let grcov_jsons: RefCell<FxHashMap<String, Vec<&Archive>>> = RefCell::new(FxHashMap::default());
     let linked_files_maps: RefCell<FxHashMap<String, &Archive>> =
         RefCell::new(FxHashMap::default());
 
+    let files_left = Cell::new(max_files);
     for archive in &mut archives {
         archive.explore(
             &gcno_stems_archives,
@@ -542,21 +649,35 @@ pub fn producer(
             &profraws,
             &infos,
             &xmls,
+            &cobertura_xmls,
+            &gcov_jsons,
+            &grcov_jsons,
             &linked_files_maps,
             is_llvm,
-        );
+            &files_left,
+        )?;
     }
 
     assert!(
         !(gcno_stems_archives.borrow().is_empty()
             && profraws.borrow().is_empty()
             && infos.borrow().is_empty()
-            && xmls.borrow().is_empty()),
+            && xmls.borrow().is_empty()
+            && cobertura_xmls.borrow().is_empty()
+            && gcov_jsons.borrow().is_empty()
+            && grcov_jsons.borrow().is_empty()),
         "No input files found"
     );
 
     file_content_producer(&infos.into_inner(), sender, ItemFormat::Info);
     file_content_producer(&xmls.into_inner(), sender, ItemFormat::JacocoXml);
+    file_content_producer(
+        &cobertura_xmls.into_inner(),
+        sender,
+        ItemFormat::CoberturaXml,
+    );
+    file_content_producer(&gcov_jsons.into_inner(), sender, ItemFormat::GcovJson);
+    file_content_producer(&grcov_jsons.into_inner(), sender, ItemFormat::GrcovJson);
     profraw_producer(tmp_dir, &profraws.into_inner(), sender);
     gcno_gcda_producer(
         tmp_dir,
@@ -566,7 +687,7 @@ pub fn producer(
         ignore_orphan_gcno,
     );
 
-    get_mapping(&linked_files_maps.into_inner())
+    Ok(get_mapping(&linked_files_maps.into_inner()))
 }
 
 #[cfg(test)]
@@ -660,7 +781,15 @@ mod tests {
 
         let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
         let tmp_path = tmp_dir.path().to_owned();
-        let mapping = producer(&tmp_path, &["test".to_string()], &sender, false, false);
+        let mapping = producer(
+            &tmp_path,
+            &["test".to_string()],
+            &sender,
+            false,
+            false,
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "Platform_1.gcno", true),
@@ -719,6 +848,7 @@ mod tests {
             (ItemFormat::Info, false, "prova_fn_with_commas.info", false),
             (ItemFormat::Info, false, "empty_line.info", false),
             (ItemFormat::Info, false, "invalid_DA_record.info", false),
+            (ItemFormat::Info, false, "mixed/lib.info", false),
             (
                 ItemFormat::Info,
                 false,
@@ -753,6 +883,7 @@ mod tests {
                 false,
             ),
             (ItemFormat::Profraw, true, "default_1.profraw", false),
+            (ItemFormat::GcovJson, false, "mixed/foo.gcov.json.gz", false),
         ];
 
         check_produced(tmp_path, &receiver, expected);
@@ -780,7 +911,9 @@ mod tests {
             &sender,
             false,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "RootAccessibleWrap_1.gcno", true),
@@ -803,7 +936,9 @@ mod tests {
             &sender,
             false,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![(ItemFormat::Gcno, true, "main_1.gcno", true)];
 
@@ -823,7 +958,9 @@ mod tests {
             &sender,
             false,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "main_1.gcno", true),
@@ -846,7 +983,9 @@ mod tests {
             &sender,
             true,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![(ItemFormat::Gcno, true, "main_1.gcno", true)];
 
@@ -869,7 +1008,9 @@ mod tests {
             &sender,
             false,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "Platform_1.gcno", true),
@@ -920,7 +1061,9 @@ mod tests {
             &sender,
             false,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "Platform_1.gcno", true),
@@ -979,7 +1122,9 @@ mod tests {
             &sender,
             false,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "Platform_1.gcno", true),
@@ -1021,7 +1166,9 @@ mod tests {
             &sender,
             false,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "Platform_1.gcno", true),
@@ -1070,7 +1217,9 @@ mod tests {
             &sender,
             false,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Profraw, true, "default_1.profraw", false),
@@ -1093,7 +1242,9 @@ mod tests {
             &sender,
             false,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Info, false, "1494603967-2977-2_0.info", true),
@@ -1129,7 +1280,9 @@ mod tests {
             &sender,
             false,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![
             (
@@ -1162,7 +1315,9 @@ mod tests {
             &sender,
             false,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![
             (
@@ -1207,7 +1362,9 @@ mod tests {
             &sender,
             false,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "Platform_1.gcno", true),
@@ -1259,7 +1416,9 @@ mod tests {
             &sender,
             false,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![(ItemFormat::Gcno, true, "main_1.gcno", false)];
 
@@ -1284,7 +1443,9 @@ mod tests {
             &sender,
             false,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![(ItemFormat::Gcno, true, "main_1.gcno", true)];
 
@@ -1306,7 +1467,9 @@ mod tests {
             &sender,
             false,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
     }
 
     // Test extracting gcno/gcda archives, where a gcno file exist with no matching gcda file.
@@ -1322,7 +1485,9 @@ mod tests {
             &sender,
             false,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "Platform_1.gcno", false),
@@ -1364,7 +1529,9 @@ mod tests {
             &sender,
             false,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "Platform_1.gcno", false),
@@ -1410,7 +1577,9 @@ mod tests {
             &sender,
             true,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "nsMaiInterfaceValue_1.gcno", true),
@@ -1444,7 +1613,9 @@ mod tests {
             &sender,
             true,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![
             (ItemFormat::Gcno, true, "nsMaiInterfaceValue_1.gcno", true),
@@ -1486,7 +1657,9 @@ mod tests {
             &sender,
             true,
             true,
-        );
+            usize::MAX,
+        )
+        .unwrap();
         let gcno_buf: Vec<u8> = vec![
             111, 110, 99, 103, 42, 50, 48, 52, 74, 200, 254, 66, 0, 0, 0, 1, 9, 0, 0, 0, 0, 0, 0,
             0, 236, 217, 93, 255, 2, 0, 0, 0, 109, 97, 105, 110, 0, 0, 0, 0, 2, 0, 0, 0, 102, 105,
@@ -1540,7 +1713,9 @@ mod tests {
             &sender,
             true,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         assert!(mapping.is_some());
         let mapping = mapping.unwrap();
@@ -1570,7 +1745,9 @@ mod tests {
             &sender,
             true,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
 
         let expected = vec![(ItemFormat::Profraw, true, "default.profraw", false)];
 
@@ -1590,7 +1767,9 @@ mod tests {
             &sender,
             true,
             false,
-        );
+            usize::MAX,
+        )
+        .unwrap();
     }
 
     #[test]
@@ -1606,7 +1785,161 @@ mod tests {
             &sender,
             true,
             false,
+            usize::MAX,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_dir_producer_mixed_formats() {
+        let (sender, receiver) = unbounded();
+
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let tmp_path = tmp_dir.path().to_owned();
+        producer(
+            &tmp_path,
+            &["test/mixed".to_string()],
+            &sender,
+            false,
+            false,
+            usize::MAX,
+        )
+        .unwrap();
+
+        let expected = vec![
+            (ItemFormat::Info, false, "lib.info", false),
+            (ItemFormat::GcovJson, false, "foo.gcov.json.gz", false),
+        ];
+
+        check_produced(tmp_path, &receiver, expected);
+    }
+
+    #[test]
+    fn test_dir_producer_max_files() {
+        let (sender, _) = unbounded();
+
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let produced = producer(
+            tmp_dir.path(),
+            &["test/mixed".to_string()],
+            &sender,
+            false,
+            false,
+            1,
         );
+
+        match produced {
+            Err(Error::Config(message)) => {
+                assert!(message.starts_with("Too many input files found in 'test/mixed'"))
+            }
+            _ => panic!("The number of input files should be limited"),
+        }
+    }
+
+    #[test]
+    fn test_dir_producer_max_files_counts_only_coverage_files() {
+        let (sender, receiver) = unbounded();
+
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let input_dir = tmp_dir.path().join("input");
+        fs::create_dir(&input_dir).unwrap();
+        fs::copy("test/mixed/lib.info", input_dir.join("lib.info")).unwrap();
+        fs::write(input_dir.join("lib.rs"), "pub fn f() {}\n").unwrap();
+        fs::write(input_dir.join("README.md"), "# Readme\n").unwrap();
+        fs::write(input_dir.join("linked-files-map.json"), "{}").unwrap();
+
+        let tmp_path = tmp_dir.path().join("tmp");
+        fs::create_dir(&tmp_path).unwrap();
+        producer(
+            &tmp_path,
+            &[input_dir.to_str().unwrap().to_string()],
+            &sender,
+            false,
+            false,
+            1,
+        )
+        .unwrap();
+
+        let expected = vec![(ItemFormat::Info, false, "lib.info", false)];
+
+        check_produced(tmp_path, &receiver, expected);
+    }
+
+    #[test]
+    fn test_dir_producer_max_files_not_exceeded() {
+        let (sender, receiver) = unbounded();
+
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let tmp_path = tmp_dir.path().to_owned();
+        producer(
+            &tmp_path,
+            &["test/mixed".to_string()],
+            &sender,
+            false,
+            false,
+            2,
+        )
+        .unwrap();
+
+        let expected = vec![
+            (ItemFormat::Info, false, "lib.info", false),
+            (ItemFormat::GcovJson, false, "foo.gcov.json.gz", false),
+        ];
+
+        check_produced(tmp_path, &receiver, expected);
+    }
+
+    #[test]
+    fn test_plain_producer_grcov_json() {
+        let (sender, receiver) = unbounded();
+
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let tmp_path = tmp_dir.path().to_owned();
+        producer(
+            &tmp_path,
+            &[
+                "test/grcov.json".to_string(),
+                "test/test_covdir.json".to_string(),
+            ],
+            &sender,
+            false,
+            false,
+            usize::MAX,
+        )
+        .unwrap();
+
+        // Other JSON files, like covdir reports, aren't inputs.
+        let expected = vec![(ItemFormat::GrcovJson, false, "grcov.json", false)];
+
+        check_produced(tmp_path, &receiver, expected);
+    }
+
+    #[test]
+    fn test_plain_producer_cobertura_xml() {
+        let (sender, receiver) = unbounded();
+
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let tmp_path = tmp_dir.path().to_owned();
+        producer(
+            &tmp_path,
+            &[
+                "test/cobertura.xml".to_string(),
+                "test/jacoco/basic-report.xml".to_string(),
+                "test/jacoco/not_jacoco_file.xml".to_string(),
+            ],
+            &sender,
+            false,
+            false,
+            usize::MAX,
+        )
+        .unwrap();
+
+        let expected = vec![
+            (ItemFormat::JacocoXml, false, "basic-report.xml", false),
+            (ItemFormat::CoberturaXml, false, "cobertura.xml", false),
+        ];
+
+        check_produced(tmp_path, &receiver, expected);
     }
 
     #[test]
@@ -1,7 +1,7 @@
 use rustc_hash::{FxHashMap, FxHashSet};
 use smallvec::SmallVec;
 use std::cmp;
-use std::collections::{btree_map, hash_map, BTreeMap};
+use std::collections::{btree_map, hash_map};
 use std::convert::From;
 use std::fmt::{Debug, Display, Formatter};
 use std::fs::File;
@@ -854,17 +854,15 @@ impl Gcno {
             fun.add_line_count();
             let res = match results.entry(&fun.file_name) {
                 hash_map::Entry::Occupied(r) => r.into_mut(),
-                hash_map::Entry::Vacant(p) => p.insert(CovResult {
-                    lines: BTreeMap::new(),
-                    branches: BTreeMap::new(),
-                    functions: FxHashMap::default(),
-                }),
+                hash_map::Entry::Vacant(p) => p.insert(CovResult::default()),
             };
             res.functions.insert(
                 fun.name.clone(),
                 Function {
                     start: fun.start_line,
                     executed: fun.executed,
+                    // Before GCC 8, the end line isn't in the notes file.
+                    end: Some(fun.end_line).filter(|&end| end >= fun.start_line),
                 },
             );
             if fun.executed {
@@ -899,21 +897,24 @@ impl Gcno {
                         continue;
                     }
 
-                    let taken: Vec<_> = block
+                    let hits: Vec<u64> = block
                         .destination
                         .iter()
                         .filter_map(|no| {
                             let edge = &fun.edges[*no];
                             if edge.is_fake() {
                                 None
+                            } else if fun.executed {
+                                Some(edge.counter)
                             } else {
-                                Some(fun.executed && edge.counter > 0)
+                                Some(0)
                             }
                         })
                         .collect();
-                    if taken.len() <= 1 {
+                    if hits.len() <= 1 {
                         continue;
                     }
+                    let taken: Vec<_> = hits.iter().map(|&hits| hits > 0).collect();
                     match res.branches.entry(line) {
                         btree_map::Entry::Occupied(c) => {
                             let v = c.into_mut();
@@ -923,6 +924,7 @@ impl Gcno {
                             p.insert(taken);
                         }
                     }
+                    res.branch_hits.entry(line).or_default().extend(hits);
                 }
             }
         }
@@ -1214,6 +1216,7 @@ mod tests {
 
     use super::*;
     use crate::defs::FunctionMap;
+    use std::collections::BTreeMap;
 
     fn from_path(gcno: &mut Gcno, typ: FileType, path: &str) {
         let path = PathBuf::from(path);
@@ -1406,6 +1409,7 @@ mod tests {
             Function {
                 start: 1,
                 executed: true,
+                end: None,
             },
         );
         let branches: BTreeMap<u32, Vec<bool>> = BTreeMap::new();
@@ -1415,6 +1419,7 @@ mod tests {
                 lines,
                 branches,
                 functions,
+                ..Default::default()
             },
         )];
 
@@ -1461,6 +1466,7 @@ mod tests {
             Function {
                 start: 1,
                 executed: true,
+                end: None,
             },
         );
         functions.insert(
@@ -1468,6 +1474,7 @@ mod tests {
             Function {
                 start: 12,
                 executed: true,
+                end: None,
             },
         );
         functions.insert(
@@ -1475,6 +1482,7 @@ mod tests {
             Function {
                 start: 20,
                 executed: false,
+                end: None,
             },
         );
         functions.insert(
@@ -1482,6 +1490,7 @@ mod tests {
             Function {
                 start: 31,
                 executed: true,
+                end: None,
             },
         );
         let mut branches: BTreeMap<u32, Vec<bool>> = BTreeMap::new();
@@ -1496,12 +1505,26 @@ mod tests {
             branches.insert(x.0, x.1.clone());
         });
 
+        // The branches with hits are the taken ones.
+        let branch_hits = result[0].1.branch_hits.clone();
+        assert_eq!(
+            branch_hits.keys().collect::<Vec<_>>(),
+            branches.keys().collect::<Vec<_>>()
+        );
+        for (line, hits) in &branch_hits {
+            let taken: Vec<bool> = hits.iter().map(|&hits| hits > 0).collect();
+            assert_eq!(&taken, &branches[line]);
+        }
+        assert_eq!(branch_hits[&21], vec![0, 0, 0, 0]);
+
         let expected = vec![(
             String::from("file_branch.c"),
             CovResult {
                 lines,
                 branches,
+                branch_hits,
                 functions,
+                ..Default::default()
             },
         )];
 
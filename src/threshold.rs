@@ -0,0 +1,503 @@
+use globset::{Glob, GlobMatcher};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::cobertura::{BranchCollapse, CoberturaConfig};
+use crate::defs::*;
+use crate::error::Error;
+use crate::output::{format_percent, Rounding};
+
+/// The line and branch counts of all the files of a report.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CoverageTotals {
+    pub lines_covered: usize,
+    pub lines_valid: usize,
+    pub branches_covered: usize,
+    pub branches_valid: usize,
+}
+
+fn percent(covered: usize, valid: usize) -> f64 {
+    if valid > 0 {
+        covered as f64 * 100.0 / valid as f64
+    } else {
+        0.0
+    }
+}
+
+impl CoverageTotals {
+    /// Adds the lines and branches of a single file as the Cobertura report counts them, for
+    /// the numbers to agree with it: the branches follow the options of the report and the
+    /// functions without lines get a line at their start when the report adds one.
+    pub fn add_like_cobertura(&mut self, result: &CovResult, config: &CoberturaConfig) {
+        // The start lines of the functions without lines, covered if one of their functions
+        // was executed.
+        let mut function_lines: BTreeMap<u32, bool> = BTreeMap::new();
+        if config.empty_function_lines || config.dead_function_lines {
+            let end = result.lines.keys().last().unwrap_or(&0) + 1;
+            let mut starts: Vec<u32> = result.functions.values().map(|f| f.start).collect();
+            starts.sort_unstable();
+            starts.dedup();
+            let func_end = |function: &Function| match function.end {
+                Some(last) => last + 1,
+                None => starts
+                    .iter()
+                    .find(|&&start| start > function.start)
+                    .cloned()
+                    .unwrap_or(end),
+            };
+            let ranges: Vec<(u32, u32)> = result
+                .functions
+                .values()
+                .map(|function| (function.start, func_end(function)))
+                .collect();
+            for function in result.functions.values() {
+                if !config.empty_function_lines
+                    && (function.executed || !config.dead_function_lines)
+                {
+                    continue;
+                }
+                let func_end = func_end(function);
+                // The lines of the nested functions, e.g. closures, aren't the function's.
+                let has_lines = func_end > function.start
+                    && result
+                        .lines
+                        .range(function.start..func_end)
+                        .any(|(&line, _)| {
+                            !ranges.iter().any(|&(start, end)| {
+                                start > function.start
+                                    && start < func_end
+                                    && line >= start
+                                    && line < end
+                            })
+                        });
+                if !has_lines {
+                    *function_lines.entry(function.start).or_default() |= function.executed;
+                }
+            }
+        }
+
+        let lines = result
+            .lines
+            .iter()
+            .map(|(&line, &hits)| (line, hits > 0))
+            .chain(function_lines);
+        for (line, covered) in lines {
+            self.lines_valid += 1;
+            if covered {
+                self.lines_covered += 1;
+            }
+            let taken = match result.branches.get(&line).filter(|taken| is_branch(taken)) {
+                Some(taken) => taken,
+                None => continue,
+            };
+            let (covered, valid) = match (config.collapse_branches, config.decision_branch_rate) {
+                (Some(BranchCollapse::Any), _) => (usize::from(taken.iter().any(|&t| t)), 1),
+                (Some(BranchCollapse::All), _) | (None, true) => {
+                    (usize::from(taken.iter().all(|&t| t)), 1)
+                }
+                (None, false) => (taken.iter().filter(|&&t| t).count(), taken.len()),
+            };
+            self.branches_covered += covered;
+            self.branches_valid += valid;
+        }
+    }
+
+    /// Adds the lines and branches of a single file.
+    pub fn add(&mut self, result: &CovResult) {
+        self.lines_covered += result.lines.values().filter(|&&hits| hits > 0).count();
+        self.lines_valid += result.lines.len();
+        for taken in result.branches.values().filter(|taken| is_branch(taken)) {
+            self.branches_covered += taken.iter().filter(|&&taken| taken).count();
+            self.branches_valid += taken.len();
+        }
+    }
+
+    pub fn line_percent(&self) -> f64 {
+        percent(self.lines_covered, self.lines_valid)
+    }
+
+    pub fn branch_percent(&self) -> f64 {
+        percent(self.branches_covered, self.branches_valid)
+    }
+
+    /// Returns a one-line summary, e.g. `Coverage: 83.2% lines (1234/1483), 71.0% branches
+    /// (71/100)`. The branches are left out when there are none.
+    pub fn summary(&self, rounding: Rounding) -> String {
+        let mut summary = format!(
+            "Coverage: {} lines ({}/{})",
+            format_percent(self.line_percent(), rounding),
+            self.lines_covered,
+            self.lines_valid
+        );
+        if self.branches_valid > 0 {
+            summary.push_str(&format!(
+                ", {} branches ({}/{})",
+                format_percent(self.branch_percent(), rounding),
+                self.branches_covered,
+                self.branches_valid
+            ));
+        }
+        summary
+    }
+
+    /// Returns a message for each coverage below its threshold, in percent. The coverages are
+    /// compared as they're shown, rounded, for a report not to fail with e.g. `80.0%` below
+    /// `80%`.
+    pub fn check(
+        &self,
+        min_lines: Option<f64>,
+        min_branches: Option<f64>,
+        rounding: Rounding,
+    ) -> Vec<String> {
+        let mut failures = Vec::new();
+        if let Some(min) = min_lines {
+            if rounding.round_percent(self.line_percent()) < min {
+                failures.push(format!(
+                    "The line coverage is {}, below {}%",
+                    format_percent(self.line_percent(), rounding),
+                    min
+                ));
+            }
+        }
+        if let Some(min) = min_branches {
+            if rounding.round_percent(self.branch_percent()) < min {
+                failures.push(format!(
+                    "The branch coverage is {}, below {}%",
+                    format_percent(self.branch_percent(), rounding),
+                    min
+                ));
+            }
+        }
+        failures
+    }
+}
+
+/// Counts the totals of the results as they go through, like the Cobertura report does. The
+/// excluded files aren't counted.
+pub fn count_totals(
+    results: CovResultIter,
+    config: CoberturaConfig,
+    totals: Rc<RefCell<CoverageTotals>>,
+) -> CovResultIter {
+    Box::new(results.inspect(move |(_, _, result)| {
+        if !result.excluded {
+            totals.borrow_mut().add_like_cobertura(result, &config);
+        }
+    }))
+}
+
+/// A minimum line coverage required from each of the files whose relative path matches a
+/// glob, e.g. `src/core/**=90`.
+#[derive(Clone, Debug)]
+pub struct FileThreshold {
+    pattern: String,
+    matcher: GlobMatcher,
+    min_lines: f64,
+}
+
+impl FileThreshold {
+    /// Parses a `GLOB=PERCENT` rule.
+    pub fn parse(rule: &str) -> Result<FileThreshold, Error> {
+        let invalid = || Error::Config(format!("Invalid file threshold '{}'", rule));
+        let separator = rule.rfind('=').ok_or_else(invalid)?;
+        let pattern = &rule[..separator];
+        let min_lines = rule[separator + 1..]
+            .parse::<f64>()
+            .map_err(|_| invalid())?;
+        let matcher = Glob::new(pattern)
+            .map_err(|e| Error::Config(e.to_string()))?
+            .compile_matcher();
+        Ok(FileThreshold {
+            pattern: pattern.to_string(),
+            matcher,
+            min_lines,
+        })
+    }
+}
+
+/// Checks the line coverage of each file against all the thresholds matching its relative
+/// path as the results go through, a message is added to the failures for each file below
+/// one of them. The excluded files and the files without lines aren't checked.
+pub fn check_file_thresholds(
+    results: CovResultIter,
+    thresholds: Vec<FileThreshold>,
+    rounding: Rounding,
+    failures: Rc<RefCell<Vec<String>>>,
+) -> CovResultIter {
+    Box::new(results.inspect(move |(_, rel_path, result)| {
+        if result.excluded || result.lines.is_empty() {
+            return;
+        }
+        let mut totals = CoverageTotals::default();
+        totals.add(result);
+        for threshold in thresholds
+            .iter()
+            .filter(|threshold| threshold.matcher.is_match(rel_path))
+        {
+            if rounding.round_percent(totals.line_percent()) < threshold.min_lines {
+                failures.borrow_mut().push(format!(
+                    "The line coverage of {} is {}, below {}% ({})",
+                    rel_path.display(),
+                    format_percent(totals.line_percent(), rounding),
+                    threshold.min_lines,
+                    threshold.pattern
+                ));
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn result(lines: &[(u32, u64)], branches: &[(u32, Vec<bool>)], excluded: bool) -> CovResult {
+        CovResult {
+            lines: lines.iter().cloned().collect(),
+            branches: branches.iter().cloned().collect(),
+            excluded,
+            ..Default::default()
+        }
+    }
+
+    fn totals(results: CovResultIter, config: &CoberturaConfig) -> CoverageTotals {
+        let totals = Rc::new(RefCell::new(CoverageTotals::default()));
+        count_totals(results, config.clone(), totals.clone()).for_each(drop);
+        let totals = *totals.borrow();
+        totals
+    }
+
+    #[test]
+    fn test_count_totals() {
+        let results = vec![
+            (
+                PathBuf::from("a.rs"),
+                PathBuf::from("a.rs"),
+                result(&[(1, 1), (2, 0), (3, 2)], &[(1, vec![true, false])], false),
+            ),
+            (
+                PathBuf::from("b.rs"),
+                PathBuf::from("b.rs"),
+                result(&[(1, 1), (2, 0)], &[(2, vec![false, false])], false),
+            ),
+            (
+                PathBuf::from("c.rs"),
+                PathBuf::from("c.rs"),
+                result(&[(1, 0)], &[], true),
+            ),
+        ];
+
+        let totals = totals(Box::new(results.into_iter()), &Default::default());
+        assert_eq!(
+            totals,
+            CoverageTotals {
+                lines_covered: 3,
+                lines_valid: 5,
+                branches_covered: 1,
+                branches_valid: 4,
+            }
+        );
+        assert!((totals.line_percent() - 60.0).abs() < f64::EPSILON);
+        assert!((totals.branch_percent() - 25.0).abs() < f64::EPSILON);
+
+        assert!(totals
+            .check(Some(60.0), None, Rounding::HalfEven)
+            .is_empty());
+        assert_eq!(
+            totals.check(Some(80.0), Some(25.0), Rounding::HalfEven),
+            vec!["The line coverage is 60.0%, below 80%"]
+        );
+        assert_eq!(
+            totals.check(None, Some(50.0), Rounding::HalfEven),
+            vec!["The branch coverage is 25.0%, below 50%"]
+        );
+
+        assert_eq!(
+            totals.summary(Rounding::HalfEven),
+            "Coverage: 60.0% lines (3/5), 25.0% branches (1/4)"
+        );
+        assert_eq!(
+            CoverageTotals {
+                lines_covered: 1,
+                lines_valid: 3,
+                ..Default::default()
+            }
+            .summary(Rounding::HalfEven),
+            "Coverage: 33.3% lines (1/3)"
+        );
+
+        // The coverages are compared as they're shown: 2/3 is 66.7%, whatever the digits
+        // after.
+        let two_thirds = CoverageTotals {
+            lines_covered: 2,
+            lines_valid: 3,
+            ..Default::default()
+        };
+        assert!(two_thirds
+            .check(Some(66.7), None, Rounding::HalfEven)
+            .is_empty());
+        assert_eq!(
+            two_thirds.check(Some(66.7), None, Rounding::Truncate),
+            vec!["The line coverage is 66.6%, below 66.7%"]
+        );
+    }
+
+    #[test]
+    fn test_count_totals_like_cobertura() {
+        let results = || -> CovResultIter {
+            let mut with_functions = result(&[(1, 1), (2, 0)], &[(2, vec![false, false])], false);
+            with_functions.functions.insert(
+                "dead".to_string(),
+                Function {
+                    start: 10,
+                    executed: false,
+                    end: None,
+                },
+            );
+            // The outer function has no lines of its own, they're all the closure's.
+            let mut with_closure = result(&[(3, 1), (4, 1)], &[(4, vec![true])], false);
+            for (name, start, end, executed) in &[
+                ("outer", 1, Some(5), true),
+                ("outer::{{closure}}", 3, Some(4), true),
+            ] {
+                with_closure.functions.insert(
+                    name.to_string(),
+                    Function {
+                        start: *start,
+                        executed: *executed,
+                        end: *end,
+                    },
+                );
+            }
+            Box::new(
+                vec![
+                    (
+                        PathBuf::from("a.rs"),
+                        PathBuf::from("a.rs"),
+                        result(
+                            &[(1, 1), (2, 0), (3, 2)],
+                            &[(1, vec![true, false]), (3, vec![true, true, false])],
+                            false,
+                        ),
+                    ),
+                    (
+                        PathBuf::from("b/b.rs"),
+                        PathBuf::from("b/b.rs"),
+                        with_closure,
+                    ),
+                    (
+                        PathBuf::from("b/c.rs"),
+                        PathBuf::from("b/c.rs"),
+                        with_functions,
+                    ),
+                ]
+                .into_iter(),
+            )
+        };
+        let root_attribute = |xml: &str, name: &str| -> usize {
+            let re =
+                regex::Regex::new(&format!("<coverage [^>]*\\b{}=\"([0-9]+)\"", name)).unwrap();
+            re.captures(xml).unwrap()[1].parse().unwrap()
+        };
+
+        for config in [
+            CoberturaConfig::default(),
+            CoberturaConfig {
+                dead_function_lines: true,
+                decision_branch_rate: true,
+                ..Default::default()
+            },
+            CoberturaConfig {
+                empty_function_lines: true,
+                collapse_branches: Some(BranchCollapse::All),
+                ..Default::default()
+            },
+            CoberturaConfig {
+                empty_function_lines: true,
+                collapse_branches: Some(BranchCollapse::Any),
+                decision_branch_rate: true,
+                ..Default::default()
+            },
+        ] {
+            let totals = totals(results(), &config);
+            let xml = String::from_utf8(crate::cobertura_to_bytes(
+                results(),
+                crate::DemangleConfig::default(),
+                &config,
+            ))
+            .unwrap();
+
+            assert_eq!(totals.lines_covered, root_attribute(&xml, "lines-covered"));
+            assert_eq!(totals.lines_valid, root_attribute(&xml, "lines-valid"));
+            assert_eq!(
+                totals.branches_covered,
+                root_attribute(&xml, "branches-covered")
+            );
+            assert_eq!(
+                totals.branches_valid,
+                root_attribute(&xml, "branches-valid")
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_file_thresholds() {
+        let results = vec![
+            (
+                PathBuf::from("/src/core/a.rs"),
+                PathBuf::from("src/core/a.rs"),
+                result(&[(1, 1), (2, 0)], &[], false),
+            ),
+            (
+                PathBuf::from("/src/core/b.rs"),
+                PathBuf::from("src/core/b.rs"),
+                result(&[(1, 1), (2, 1)], &[], false),
+            ),
+            (
+                PathBuf::from("/src/core/c.rs"),
+                PathBuf::from("src/core/c.rs"),
+                result(&[(1, 0)], &[], true),
+            ),
+            (
+                PathBuf::from("/src/main.rs"),
+                PathBuf::from("src/main.rs"),
+                result(&[(1, 1), (2, 0), (3, 0)], &[], false),
+            ),
+            (
+                PathBuf::from("/src/empty.rs"),
+                PathBuf::from("src/empty.rs"),
+                result(&[], &[], false),
+            ),
+        ];
+        let thresholds = vec![
+            FileThreshold::parse("src/core/**=90").unwrap(),
+            FileThreshold::parse("**=50").unwrap(),
+        ];
+
+        let failures = Rc::new(RefCell::new(Vec::new()));
+        let count = check_file_thresholds(
+            Box::new(results.into_iter()),
+            thresholds,
+            Rounding::HalfEven,
+            failures.clone(),
+        )
+        .count();
+        assert_eq!(count, 5);
+        assert_eq!(
+            *failures.borrow(),
+            vec![
+                "The line coverage of src/core/a.rs is 50.0%, below 90% (src/core/**)",
+                "The line coverage of src/main.rs is 33.3%, below 50% (**)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_file_threshold_parse_errors() {
+        assert!(FileThreshold::parse("src/core/**").is_err());
+        assert!(FileThreshold::parse("src/core/**=high").is_err());
+        assert!(FileThreshold::parse("src/[core=90").is_err());
+    }
+}
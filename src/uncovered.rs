@@ -0,0 +1,105 @@
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use crate::defs::*;
+use crate::output::{finish_output, get_target_output_writable, LineEnding};
+
+/// Groups sorted line numbers into ranges, e.g. `3-5, 9` for the lines 3, 4, 5 and 9.
+fn line_ranges(lines: &[u32]) -> String {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for &line in lines {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == line => *end = line,
+            _ => ranges.push((line, line)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Writes the path of each file with uncovered lines, followed by these lines, e.g.
+/// `src/main.rs: 3-5, 9`. The fully covered files are left out.
+pub fn output_uncovered(
+    results: CovResultIter,
+    output_file: Option<&str>,
+    line_ending: LineEnding,
+) -> io::Result<()> {
+    let mut files: Vec<(PathBuf, Vec<u32>)> = results
+        .filter(|(_, _, result)| !result.excluded)
+        .map(|(_, rel_path, result)| {
+            let uncovered: Vec<u32> = result
+                .lines
+                .iter()
+                .filter(|&(_, &hits)| hits == 0)
+                .map(|(&line, _)| line)
+                .collect();
+            (rel_path, uncovered)
+        })
+        .filter(|(_, uncovered)| !uncovered.is_empty())
+        .collect();
+    files.sort();
+
+    let mut writer = BufWriter::new(get_target_output_writable(output_file, line_ending)?);
+    for (path, uncovered) in files {
+        writeln!(writer, "{}: {}", path.display(), line_ranges(&uncovered))?;
+    }
+
+    finish_output(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_line_ranges() {
+        assert_eq!(line_ranges(&[]), "");
+        assert_eq!(line_ranges(&[7]), "7");
+        assert_eq!(line_ranges(&[3, 4, 5, 9, 11, 12]), "3-5, 9, 11-12");
+    }
+
+    #[test]
+    fn test_uncovered() {
+        let tmp_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = tmp_dir.path().join("uncovered.txt");
+
+        let results = vec![
+            (
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/main.rs"),
+                CovResult::with_lines(&[(1, 1), (2, 0), (3, 0), (4, 0), (6, 2), (9, 0)]),
+            ),
+            (
+                PathBuf::from("src/covered.rs"),
+                PathBuf::from("src/covered.rs"),
+                CovResult::with_lines(&[(1, 1), (2, 1)]),
+            ),
+            (
+                PathBuf::from("src/lib.rs"),
+                PathBuf::from("src/lib.rs"),
+                CovResult::with_lines(&[(10, 0)]),
+            ),
+        ];
+        output_uncovered(
+            Box::new(results.into_iter()),
+            Some(file_path.to_str().unwrap()),
+            LineEnding::Lf,
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&file_path).unwrap(),
+            "src/lib.rs: 10\nsrc/main.rs: 2-4, 9\n"
+        );
+    }
+}